@@ -0,0 +1,759 @@
+use std::{
+    borrow::Cow,
+    cmp::{
+        max,
+        min,
+        Ordering,
+    },
+};
+
+use crate::crc32c;
+
+pub const CHECKSUM_SIZE: usize = 4;
+pub const OVERFLOW_REF_SIZE: usize = 16;
+pub const LOG_REF_SIZE: usize = 16;
+// inline value lengths share their u32 header with the value flags
+pub const MAX_PAGE_SIZE: usize = (!VALUE_FLAGS) as usize;
+
+const ITEMS_COUNT_SIZE: usize = 4;
+const ITEM_OFFSET_SIZE: usize = 4;
+const ITEM_HEADER_SIZE: usize = 8;
+const RESTART_INTERVAL_SIZE: usize = 4;
+const SHARED_LEN_SIZE: usize = 4;
+const KEY_HEADER_SHIFT: u32 = 28;
+const MAX_SHARED_LEN: usize = (1 << KEY_HEADER_SHIFT) - 1;
+const MAX_VARINT_LEN: usize = 10;
+const VALUE_HEADER_SIZE: usize = 4;
+const KEY_LEN_SIZE: usize = 1;
+const BIT_WIDTH_SIZE: usize = 1;
+const PACKED_KEYS_OFFSET: usize = ITEMS_COUNT_SIZE + KEY_LEN_SIZE + BIT_WIDTH_SIZE;
+const FRONT_CODED_FLAG: u32 = 1 << 31;
+const DELTA_PACKED_FLAG: u32 = 1 << 30;
+const ENCODING_FLAGS: u32 = FRONT_CODED_FLAG | DELTA_PACKED_FLAG;
+const VALUE_OVERFLOW_FLAG: u32 = 1 << 31;
+const VALUE_LOG_FLAG: u32 = 1 << 30;
+const VALUE_FLAGS: u32 = VALUE_OVERFLOW_FLAG | VALUE_LOG_FLAG;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    PageOverflow {
+        page_size: usize,
+        required: usize,
+    },
+    PageTruncated {
+        page_size: usize,
+        required: usize,
+    },
+    ItemIndexOutOfRange {
+        item_index: usize,
+        items_count: usize,
+    },
+    ItemOffsetOutOfBounds {
+        item_index: usize,
+        offset: usize,
+    },
+    ItemDataOutOfBounds {
+        item_index: usize,
+        offset: usize,
+        len: usize,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidOverflowRef {
+        item_index: usize,
+        len: usize,
+    },
+    InvalidLogRef {
+        item_index: usize,
+        len: usize,
+    },
+    InvalidValueFlags {
+        item_index: usize,
+        flags: u32,
+    },
+    InvalidRestartInterval,
+    InvalidSharedPrefix {
+        item_index: usize,
+        shared_len: usize,
+        prefix_len: usize,
+    },
+    InvalidKeyHeader {
+        item_index: usize,
+        header_len: usize,
+        key_len: usize,
+    },
+    KeyNotPackable {
+        len: usize,
+    },
+    InvalidPackedKeyLen {
+        key_len: u8,
+    },
+    InvalidBitWidth {
+        bit_width: u8,
+    },
+    InvalidEncodingFlags {
+        flags: u32,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Plain,
+    // each key keeps only the suffix it does not share with the previous key, every
+    // `restart_interval`-th key is stored whole so a search can start from it; the varint
+    // length a bytes or string key starts with is kept aside and does not end the shared prefix
+    FrontCoded {
+        restart_interval: usize,
+    },
+    // keys are big endian unsigned integers of one width, kept as the first key of the block followed
+    // by the differences of the others from it packed to the bit width of the largest one
+    DeltaPacked,
+}
+
+impl Encoding {
+    fn header_size(&self) -> usize {
+        match self {
+            Encoding::Plain =>
+                ITEMS_COUNT_SIZE,
+            Encoding::FrontCoded { .. } =>
+                ITEMS_COUNT_SIZE + RESTART_INTERVAL_SIZE,
+            Encoding::DeltaPacked =>
+                PACKED_KEYS_OFFSET,
+        }
+    }
+
+    fn item_header_size(&self) -> usize {
+        match self {
+            Encoding::Plain =>
+                ITEM_HEADER_SIZE,
+            Encoding::FrontCoded { .. } =>
+                SHARED_LEN_SIZE + ITEM_HEADER_SIZE,
+            Encoding::DeltaPacked =>
+                VALUE_HEADER_SIZE,
+        }
+    }
+
+    pub fn page_budget(&self, page_size: usize) -> usize {
+        page_size.saturating_sub(self.header_size())
+    }
+
+    // an upper bound: neither the shared prefix nor the packed delta width is known until the neighbour keys are
+    pub fn item_footprint(&self, key_len: usize, value_len: usize) -> usize {
+        ITEM_OFFSET_SIZE + self.item_header_size() + key_len + value_len
+    }
+
+    // the key bytes an item leaves out when it follows `prev_key` in the same restart run
+    pub fn shared_len(&self, prev_key: &[u8], key: &[u8]) -> usize {
+        match self {
+            Encoding::FrontCoded { .. } =>
+                shared_len(prev_key, key),
+            _ =>
+                0,
+        }
+    }
+
+    // the difference a delta packed key adds to the one before it, keys which do not pack give the widest one
+    pub fn key_delta(&self, prev_key: &[u8], key: &[u8]) -> u128 {
+        match (self, pack_key(prev_key), pack_key(key)) {
+            (Encoding::DeltaPacked, Ok(prev_key), Ok(key)) =>
+                key.saturating_sub(prev_key),
+            (Encoding::DeltaPacked, _, _) =>
+                u128::MAX,
+            _ =>
+                0,
+        }
+    }
+}
+
+// the byte width of the keys a codec encodes as big endian unsigned integers, which are the ones to delta pack
+pub fn packed_key_len(key_codec: &str) -> Option<usize> {
+    match key_codec {
+        "u8" =>
+            Some(1),
+        "u16" =>
+            Some(2),
+        "u32" =>
+            Some(4),
+        "u64" =>
+            Some(8),
+        "u128" =>
+            Some(16),
+        _ =>
+            None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OverflowRef {
+    pub offset: u64,
+    pub value_len: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LogRef {
+    pub offset: u64,
+    pub value_len: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Value<'a> {
+    Inline(&'a [u8]),
+    Overflow(OverflowRef),
+    Log(LogRef),
+}
+
+pub fn page_budget(page_size: usize) -> usize {
+    Encoding::Plain.page_budget(page_size)
+}
+
+pub fn item_footprint(key_len: usize, value_len: usize) -> usize {
+    Encoding::Plain.item_footprint(key_len, value_len)
+}
+
+// values longer than this are moved out of the block into a chain of overflow pages
+pub fn max_inline_value_len(page_size: usize) -> usize {
+    max(page_budget(page_size) / 4, OVERFLOW_REF_SIZE)
+}
+
+pub fn stored_value_len(page_size: usize, value_len: usize) -> usize {
+    if value_len > max_inline_value_len(page_size) {
+        OVERFLOW_REF_SIZE
+    } else {
+        value_len
+    }
+}
+
+pub fn overflow_pages_count(page_size: usize, value_len: u64) -> u64 {
+    value_len.div_ceil(page_size as u64)
+}
+
+pub struct Builder {
+    page_size: usize,
+    encoding: Encoding,
+    offsets: Vec<u32>,
+    data: Vec<u8>,
+    prev_key: Vec<u8>,
+    packed_keys: Vec<u128>,
+    packed_key_len: usize,
+    max_delta: u128,
+}
+
+impl Builder {
+    pub fn new(page_size: usize) -> Builder {
+        Builder::with_encoding(page_size, Encoding::Plain)
+    }
+
+    pub fn with_encoding(page_size: usize, encoding: Encoding) -> Builder {
+        let encoding = match encoding {
+            Encoding::FrontCoded { restart_interval, } =>
+                Encoding::FrontCoded { restart_interval: max(restart_interval, 1), },
+            encoding =>
+                encoding,
+        };
+        Builder {
+            page_size,
+            encoding,
+            offsets: Vec::new(),
+            data: Vec::with_capacity(page_size),
+            prev_key: Vec::new(),
+            packed_keys: Vec::new(),
+            packed_key_len: 0,
+            max_delta: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.offsets.clear();
+        self.data.clear();
+        self.prev_key.clear();
+        self.packed_keys.clear();
+        self.packed_key_len = 0;
+        self.max_delta = 0;
+    }
+
+    pub fn items_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn push(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.push_raw(key, value, 0)
+    }
+
+    pub fn push_overflow(&mut self, key: &[u8], overflow: OverflowRef) -> Result<(), Error> {
+        self.push_raw(key, &encode_ref(overflow.offset, overflow.value_len), VALUE_OVERFLOW_FLAG)
+    }
+
+    pub fn push_log_ref(&mut self, key: &[u8], log_ref: LogRef) -> Result<(), Error> {
+        self.push_raw(key, &encode_ref(log_ref.offset, log_ref.value_len), VALUE_LOG_FLAG)
+    }
+
+    fn push_raw(&mut self, key: &[u8], value: &[u8], value_flags: u32) -> Result<(), Error> {
+        let mut max_delta = self.max_delta;
+        // a front coded key is stored as its header followed by the part of its body not shared with the previous body
+        let (shared_len, key_header, stored_key) = match self.encoding {
+            Encoding::Plain =>
+                (0, &[][..], key),
+            Encoding::FrontCoded { restart_interval, } => {
+                let shared_len = if self.offsets.len().is_multiple_of(restart_interval) { 0 } else { shared_len(&self.prev_key, key) };
+                // the header is recorded for restart keys too, the next key shares from the body after it
+                let (key_header, key_body) = key.split_at(key_header_len(key));
+                (shared_len, key_header, &key_body[shared_len ..])
+            },
+            Encoding::DeltaPacked => {
+                let packed_key = pack_key(key)?;
+                if let Some(&first_key) = self.packed_keys.first() {
+                    if key.len() != self.packed_key_len {
+                        return Err(Error::KeyNotPackable { len: key.len(), });
+                    }
+                    max_delta = max(max_delta, packed_key.wrapping_sub(first_key));
+                }
+                (0, &[][..], &[][..])
+            },
+        };
+        let stored_key_len = key_header.len() + stored_key.len();
+        let items_count = self.offsets.len() + 1;
+        let required = self.encoding.header_size()
+            + self.packed_keys_size(items_count, key.len(), max_delta)
+            + items_count * ITEM_OFFSET_SIZE
+            + self.data.len()
+            + self.encoding.item_header_size()
+            + stored_key_len
+            + value.len();
+        if required > self.page_size {
+            return Err(Error::PageOverflow { page_size: self.page_size, required, });
+        }
+        self.offsets.push(self.data.len() as u32);
+        match self.encoding {
+            Encoding::Plain =>
+                self.data.extend_from_slice(&(stored_key_len as u32).to_le_bytes()),
+            Encoding::FrontCoded { .. } => {
+                let header_word = (key_header.len() as u32) << KEY_HEADER_SHIFT | shared_len as u32;
+                self.data.extend_from_slice(&header_word.to_le_bytes());
+                self.data.extend_from_slice(&(stored_key_len as u32).to_le_bytes());
+                self.prev_key.clear();
+                self.prev_key.extend_from_slice(key);
+            },
+            Encoding::DeltaPacked => {
+                self.packed_keys.push(pack_key(key)?);
+                self.packed_key_len = key.len();
+                self.max_delta = max_delta;
+            },
+        }
+        self.data.extend_from_slice(&(value.len() as u32 | value_flags).to_le_bytes());
+        self.data.extend_from_slice(key_header);
+        self.data.extend_from_slice(stored_key);
+        self.data.extend_from_slice(value);
+        Ok(())
+    }
+
+    // the first key stored whole and the deltas of the others
+    fn packed_keys_size(&self, items_count: usize, key_len: usize, max_delta: u128) -> usize {
+        match self.encoding {
+            Encoding::DeltaPacked if items_count > 0 =>
+                key_len + packed_len(items_count - 1, bit_width(max_delta)),
+            _ =>
+                0,
+        }
+    }
+
+    pub fn write_page(&self, page: &mut Vec<u8>) {
+        page.clear();
+        let data_offset = self.encoding.header_size()
+            + self.packed_keys_size(self.offsets.len(), self.packed_key_len, self.max_delta)
+            + self.offsets.len() * ITEM_OFFSET_SIZE;
+        match self.encoding {
+            Encoding::Plain =>
+                page.extend_from_slice(&(self.offsets.len() as u32).to_le_bytes()),
+            Encoding::FrontCoded { restart_interval, } => {
+                page.extend_from_slice(&(self.offsets.len() as u32 | FRONT_CODED_FLAG).to_le_bytes());
+                page.extend_from_slice(&(restart_interval as u32).to_le_bytes());
+            },
+            Encoding::DeltaPacked => {
+                let first_key = self.packed_keys.first().copied().unwrap_or(0);
+                let bit_width = bit_width(self.max_delta);
+                page.extend_from_slice(&(self.offsets.len() as u32 | DELTA_PACKED_FLAG).to_le_bytes());
+                page.push(self.packed_key_len as u8);
+                page.push(bit_width as u8);
+                page.extend_from_slice(&first_key.to_be_bytes()[16 - self.packed_key_len ..]);
+                let deltas_offset = page.len();
+                page.resize(deltas_offset + packed_len(self.packed_keys.len().saturating_sub(1), bit_width), 0);
+                for (index, &packed_key) in self.packed_keys.iter().enumerate().skip(1) {
+                    let mut delta = packed_key.wrapping_sub(first_key);
+                    let mut bit_offset = (index - 1) * bit_width;
+                    let bit_end = bit_offset + bit_width;
+                    while bit_offset < bit_end {
+                        let bits = min(8 - bit_offset % 8, bit_end - bit_offset);
+                        page[deltas_offset + bit_offset / 8] |= (delta as u8 & low_bits_mask(bits)) << (bit_offset % 8);
+                        delta >>= bits;
+                        bit_offset += bits;
+                    }
+                }
+            },
+        }
+        for &offset in &self.offsets {
+            page.extend_from_slice(&(data_offset as u32 + offset).to_le_bytes());
+        }
+        page.extend_from_slice(&self.data);
+        page.resize(self.page_size, 0);
+    }
+}
+
+// keys encoded as bytes or strings start with their varint length, which differs between keys of different
+// lengths, so the shared prefix is taken over what follows it; any other key counts as all body
+fn key_header_len(key: &[u8]) -> usize {
+    let mut len = 0u64;
+    for (index, &byte) in key.iter().take(MAX_VARINT_LEN).enumerate() {
+        len |= u64::from(byte & 0x7f).checked_shl(7 * index as u32).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            let header_len = index + 1;
+            return if len == (key.len() - header_len) as u64 { header_len } else { 0 };
+        }
+    }
+    0
+}
+
+fn shared_len(prev_key: &[u8], key: &[u8]) -> usize {
+    let prev_body = &prev_key[key_header_len(prev_key) ..];
+    let body = &key[key_header_len(key) ..];
+    prev_body.iter().zip(body).take_while(|(a, b)| a == b).count().min(MAX_SHARED_LEN)
+}
+
+fn pack_key(key: &[u8]) -> Result<u128, Error> {
+    if !matches!(key.len(), 1 | 2 | 4 | 8 | 16) {
+        return Err(Error::KeyNotPackable { len: key.len(), });
+    }
+    Ok(key.iter().fold(0, |packed_key, &byte| packed_key << 8 | u128::from(byte)))
+}
+
+fn bit_width(max_delta: u128) -> usize {
+    (u128::BITS - max_delta.leading_zeros()) as usize
+}
+
+fn low_bits_mask(bits: usize) -> u8 {
+    (u16::MAX >> (16 - bits)) as u8
+}
+
+fn packed_len(items_count: usize, bit_width: usize) -> usize {
+    (items_count * bit_width).div_ceil(8)
+}
+
+pub fn seal_page(page: &mut Vec<u8>) {
+    let checksum = crc32c::checksum(page);
+    page.extend_from_slice(&checksum.to_le_bytes());
+}
+
+pub fn unseal_page(page: &mut Vec<u8>) -> Result<(), Error> {
+    let data_len = page.len().checked_sub(CHECKSUM_SIZE)
+        .ok_or(Error::PageTruncated { page_size: page.len(), required: CHECKSUM_SIZE, })?;
+    let expected = read_u32(page, data_len)
+        .ok_or(Error::PageTruncated { page_size: page.len(), required: CHECKSUM_SIZE, })?;
+    let actual = crc32c::checksum(&page[.. data_len]);
+    if actual != expected {
+        return Err(Error::ChecksumMismatch { expected, actual, });
+    }
+    page.truncate(data_len);
+    Ok(())
+}
+
+pub struct Block<'a> {
+    page: &'a [u8],
+    items_count: usize,
+    offsets_offset: usize,
+    keys: Keys,
+}
+
+#[derive(Clone, Copy, Default)]
+struct StoredKey {
+    header_len: usize,
+    shared_len: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Keys {
+    Plain,
+    FrontCoded {
+        restart_interval: usize,
+    },
+    DeltaPacked {
+        first_key: u128,
+        key_len: usize,
+        bit_width: usize,
+    },
+}
+
+impl<'a> Block<'a> {
+    pub fn decode(page: &'a [u8]) -> Result<Block<'a>, Error> {
+        let header = read_u32(page, 0)
+            .ok_or(Error::PageTruncated { page_size: page.len(), required: ITEMS_COUNT_SIZE, })?;
+        let items_count = (header & !ENCODING_FLAGS) as usize;
+        let (offsets_offset, keys) = match header & ENCODING_FLAGS {
+            0 =>
+                (ITEMS_COUNT_SIZE, Keys::Plain),
+            FRONT_CODED_FLAG => {
+                let offsets_offset = ITEMS_COUNT_SIZE + RESTART_INTERVAL_SIZE;
+                let restart_interval = read_u32(page, ITEMS_COUNT_SIZE)
+                    .ok_or(Error::PageTruncated { page_size: page.len(), required: offsets_offset, })?;
+                if restart_interval == 0 {
+                    return Err(Error::InvalidRestartInterval);
+                }
+                (offsets_offset, Keys::FrontCoded { restart_interval: restart_interval as usize, })
+            },
+            DELTA_PACKED_FLAG => {
+                let header = page.get(.. PACKED_KEYS_OFFSET)
+                    .ok_or(Error::PageTruncated { page_size: page.len(), required: PACKED_KEYS_OFFSET, })?;
+                let key_len = header[ITEMS_COUNT_SIZE];
+                if items_count > 0 && !matches!(key_len, 1 | 2 | 4 | 8 | 16) {
+                    return Err(Error::InvalidPackedKeyLen { key_len, });
+                }
+                let bit_width = header[ITEMS_COUNT_SIZE + KEY_LEN_SIZE];
+                if bit_width as usize > key_len as usize * 8 {
+                    return Err(Error::InvalidBitWidth { bit_width, });
+                }
+                let (key_len, bit_width) = (key_len as usize, bit_width as usize);
+                let first_key = page.get(PACKED_KEYS_OFFSET .. PACKED_KEYS_OFFSET + key_len)
+                    .ok_or(Error::PageTruncated { page_size: page.len(), required: PACKED_KEYS_OFFSET + key_len, })?;
+                let first_key = pack_key(first_key).unwrap_or(0);
+                let deltas_offset = PACKED_KEYS_OFFSET + key_len;
+                let offsets_offset = deltas_offset + packed_len(items_count.saturating_sub(1), bit_width);
+                (offsets_offset, Keys::DeltaPacked { first_key, key_len, bit_width, })
+            },
+            flags =>
+                return Err(Error::InvalidEncodingFlags { flags, }),
+        };
+        let required = offsets_offset + items_count * ITEM_OFFSET_SIZE;
+        if required > page.len() {
+            return Err(Error::PageTruncated { page_size: page.len(), required, });
+        }
+        Ok(Block { page, items_count, offsets_offset, keys, })
+    }
+
+    pub fn items_count(&self) -> usize {
+        self.items_count
+    }
+
+    pub fn item(&self, item_index: usize) -> Result<(Cow<'a, [u8]>, Value<'a>), Error> {
+        let (stored, suffix, value) = self.raw_item(item_index)?;
+        let key = match self.keys {
+            Keys::FrontCoded { restart_interval, } if stored.shared_len > 0 => {
+                let mut key = Vec::new();
+                let mut header_len = 0;
+                for index in item_index - item_index % restart_interval .. item_index {
+                    let (stored, suffix, _value) = self.raw_item(index)?;
+                    key = extend_key(&key, header_len, index, stored, suffix)?;
+                    header_len = stored.header_len;
+                }
+                Cow::Owned(extend_key(&key, header_len, item_index, stored, suffix)?)
+            },
+            Keys::DeltaPacked { first_key, key_len, bit_width, } => {
+                let delta = if item_index == 0 { 0 } else { self.packed_delta(PACKED_KEYS_OFFSET + key_len, item_index - 1, bit_width) };
+                Cow::Owned(first_key.wrapping_add(delta).to_be_bytes()[16 - key_len ..].to_vec())
+            },
+            _ =>
+                Cow::Borrowed(suffix),
+        };
+        Ok((key, value))
+    }
+
+    fn raw_item(&self, item_index: usize) -> Result<(StoredKey, &'a [u8], Value<'a>), Error> {
+        if item_index >= self.items_count {
+            return Err(Error::ItemIndexOutOfRange { item_index, items_count: self.items_count, });
+        }
+        let mut offset = read_u32(self.page, self.offsets_offset + item_index * ITEM_OFFSET_SIZE)
+            .ok_or(Error::ItemOffsetOutOfBounds { item_index, offset: self.page.len(), })? as usize;
+        let stored = if let Keys::FrontCoded { .. } = self.keys {
+            let header_word = read_u32(self.page, offset)
+                .ok_or(Error::ItemOffsetOutOfBounds { item_index, offset, })?;
+            offset += SHARED_LEN_SIZE;
+            StoredKey {
+                header_len: (header_word >> KEY_HEADER_SHIFT) as usize,
+                shared_len: header_word as usize & MAX_SHARED_LEN,
+            }
+        } else {
+            StoredKey::default()
+        };
+        let key_len = if let Keys::DeltaPacked { .. } = self.keys {
+            0
+        } else {
+            let key_len = read_u32(self.page, offset)
+                .ok_or(Error::ItemOffsetOutOfBounds { item_index, offset, })? as usize;
+            offset += ITEM_HEADER_SIZE - VALUE_HEADER_SIZE;
+            key_len
+        };
+        let value_header = read_u32(self.page, offset)
+            .ok_or(Error::ItemOffsetOutOfBounds { item_index, offset, })?;
+        let value_len = (value_header & !VALUE_FLAGS) as usize;
+        let data_offset = offset + VALUE_HEADER_SIZE;
+        let len = key_len + value_len;
+        let data = self.page.get(data_offset .. data_offset + len)
+            .ok_or(Error::ItemDataOutOfBounds { item_index, offset: data_offset, len, })?;
+        let (key, value) = data.split_at(key_len);
+        if stored.header_len > key.len() {
+            return Err(Error::InvalidKeyHeader { item_index, header_len: stored.header_len, key_len: key.len(), });
+        }
+        let value = match value_header & VALUE_FLAGS {
+            0 =>
+                Value::Inline(value),
+            VALUE_OVERFLOW_FLAG if value.len() == OVERFLOW_REF_SIZE =>
+                Value::Overflow(OverflowRef { offset: read_u64(value, 0), value_len: read_u64(value, 8), }),
+            VALUE_OVERFLOW_FLAG =>
+                return Err(Error::InvalidOverflowRef { item_index, len: value.len(), }),
+            VALUE_LOG_FLAG if value.len() == LOG_REF_SIZE =>
+                Value::Log(LogRef { offset: read_u64(value, 0), value_len: read_u64(value, 8), }),
+            VALUE_LOG_FLAG =>
+                return Err(Error::InvalidLogRef { item_index, len: value.len(), }),
+            flags =>
+                return Err(Error::InvalidValueFlags { item_index, flags, }),
+        };
+        Ok((stored, key, value))
+    }
+
+    // the packed deltas were bounds checked against the page in `decode`
+    fn packed_delta(&self, deltas_offset: usize, delta_index: usize, bit_width: usize) -> u128 {
+        let mut delta = 0;
+        let mut bit_offset = delta_index * bit_width;
+        let bit_end = bit_offset + bit_width;
+        while bit_offset < bit_end {
+            let bits = min(8 - bit_offset % 8, bit_end - bit_offset);
+            let byte = self.page[deltas_offset + bit_offset / 8] >> (bit_offset % 8) & low_bits_mask(bits);
+            delta |= u128::from(byte) << (bit_offset - delta_index * bit_width);
+            bit_offset += bits;
+        }
+        delta
+    }
+
+    pub fn key(&self, item_index: usize) -> Result<Cow<'a, [u8]>, Error> {
+        self.item(item_index).map(|(key, _value)| key)
+    }
+
+    pub fn key_cursor(&self) -> KeyCursor<'_, 'a> {
+        KeyCursor { block: self, key: Vec::new(), header_len: 0, item_index: None, }
+    }
+
+    pub fn search(&self, key: &[u8]) -> Result<Result<usize, usize>, Error> {
+        let mut keys = self.key_cursor();
+        self.search_by(|item_index| keys.key(item_index).map(|item_key| item_key.cmp(key)))
+    }
+
+    // finds the first item among equal keys
+    pub fn search_by<F, E>(&self, mut compare: F) -> Result<Result<usize, usize>, E> where F: FnMut(usize) -> Result<Ordering, E> {
+        let item_index = self.partition_point_by(|item_index| compare(item_index).map(Ordering::is_lt))?;
+        if item_index < self.items_count && compare(item_index)? == Ordering::Equal {
+            Ok(Ok(item_index))
+        } else {
+            Ok(Err(item_index))
+        }
+    }
+
+    // returns the index of the first item for which `is_before` is false, the items it holds for must come first
+    pub fn partition_point_by<F, E>(&self, mut is_before: F) -> Result<usize, E> where F: FnMut(usize) -> Result<bool, E> {
+        let restart_interval = match self.keys {
+            Keys::FrontCoded { restart_interval, } =>
+                restart_interval,
+            Keys::Plain | Keys::DeltaPacked { .. } =>
+                1,
+        };
+        // binary search over the restart points, whose keys are stored whole
+        let mut lo = 0;
+        let mut hi = self.items_count.div_ceil(restart_interval);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if is_before(mid * restart_interval)? {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return Ok(0);
+        }
+        // then a linear scan through the run following the last restart point which is still before
+        let run_end = (lo * restart_interval).min(self.items_count);
+        for item_index in (lo - 1) * restart_interval + 1 .. run_end {
+            if !is_before(item_index)? {
+                return Ok(item_index);
+            }
+        }
+        Ok(run_end)
+    }
+}
+
+// Keeps the last key it rebuilt, so a front coded key asked for right after the one before it in the same restart
+// run only costs its own suffix, which makes a scan through a run linear.
+pub struct KeyCursor<'b, 'a> {
+    block: &'b Block<'a>,
+    key: Vec<u8>,
+    header_len: usize,
+    item_index: Option<usize>,
+}
+
+impl<'b, 'a> KeyCursor<'b, 'a> {
+    pub fn key(&mut self, item_index: usize) -> Result<&[u8], Error> {
+        let Keys::FrontCoded { restart_interval, } = self.block.keys else {
+            let key = self.block.key(item_index)?;
+            self.key.clear();
+            self.key.extend_from_slice(&key);
+            return Ok(&self.key);
+        };
+        let run_start = item_index - item_index % restart_interval;
+        let from = match self.item_index {
+            Some(last_index) if run_start <= last_index && last_index <= item_index =>
+                last_index + 1,
+            _ =>
+                run_start,
+        };
+        if from == run_start {
+            self.key.clear();
+            self.header_len = 0;
+        }
+        for index in from ..= item_index {
+            // drop the item on error, the key buffer is only partly rebuilt
+            self.item_index = None;
+            let (stored, suffix, _value) = self.block.raw_item(index)?;
+            let prefix_len = self.key.len() - self.header_len;
+            if stored.shared_len > prefix_len {
+                return Err(Error::InvalidSharedPrefix { item_index: index, shared_len: stored.shared_len, prefix_len, });
+            }
+            let (key_header, suffix) = suffix.split_at(stored.header_len);
+            self.key.truncate(self.header_len + stored.shared_len);
+            self.key.splice(.. self.header_len, key_header.iter().cloned());
+            self.key.extend_from_slice(suffix);
+            self.header_len = stored.header_len;
+            self.item_index = Some(index);
+        }
+        Ok(&self.key)
+    }
+}
+
+// rebuilds a front coded key from the previous one, whose header is `prev_header_len` bytes long
+fn extend_key(prev_key: &[u8], prev_header_len: usize, item_index: usize, stored: StoredKey, suffix: &[u8]) -> Result<Vec<u8>, Error> {
+    let prev_body = &prev_key[prev_header_len ..];
+    if stored.shared_len > prev_body.len() {
+        return Err(Error::InvalidSharedPrefix { item_index, shared_len: stored.shared_len, prefix_len: prev_body.len(), });
+    }
+    let (key_header, suffix) = suffix.split_at(stored.header_len);
+    let mut key = Vec::with_capacity(key_header.len() + stored.shared_len + suffix.len());
+    key.extend_from_slice(key_header);
+    key.extend_from_slice(&prev_body[.. stored.shared_len]);
+    key.extend_from_slice(suffix);
+    Ok(key)
+}
+
+fn read_u32(page: &[u8], offset: usize) -> Option<u32> {
+    let bytes = page.get(offset .. offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn encode_ref(offset: u64, value_len: u64) -> [u8; 16] {
+    let mut value = [0; 16];
+    value[.. 8].copy_from_slice(&offset.to_le_bytes());
+    value[8 ..].copy_from_slice(&value_len.to_le_bytes());
+    value
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[offset .. offset + 8]);
+    u64::from_le_bytes(value)
+}
@@ -0,0 +1,262 @@
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    UnexpectedEof {
+        required: usize,
+        available: usize,
+    },
+    VarintOverflow,
+    LengthOverflow {
+        len: u64,
+    },
+    InvalidUtf8,
+    TrailingBytes {
+        count: usize,
+    },
+}
+
+pub trait Codec {
+    fn codec_id() -> String;
+
+    // whether the encoding is a varint length followed by that many payload bytes
+    fn length_prefixed() -> bool {
+        false
+    }
+}
+
+pub trait Encode: Codec {
+    fn encode(&self, target: &mut Vec<u8>);
+}
+
+pub trait Decode: Codec + Sized {
+    fn decode(source: &mut &[u8]) -> Result<Self, Error>;
+}
+
+pub fn encode<T>(value: &T, target: &mut Vec<u8>) where T: Encode + ?Sized {
+    target.clear();
+    value.encode(target);
+}
+
+pub fn decode<T>(mut source: &[u8]) -> Result<T, Error> where T: Decode {
+    let value = T::decode(&mut source)?;
+    if !source.is_empty() {
+        return Err(Error::TrailingBytes { count: source.len(), });
+    }
+    Ok(value)
+}
+
+fn take<'a>(source: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if source.len() < len {
+        return Err(Error::UnexpectedEof { required: len, available: source.len(), });
+    }
+    let (bytes, rest) = source.split_at(len);
+    *source = rest;
+    Ok(bytes)
+}
+
+fn encode_varint(mut value: u64, target: &mut Vec<u8>) {
+    while value >= 0x80 {
+        target.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    target.push(value as u8);
+}
+
+fn decode_varint(source: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0 .. 64).step_by(7) {
+        let byte = take(source, 1)?[0];
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::VarintOverflow)
+}
+
+pub fn decode_len(source: &mut &[u8]) -> Result<usize, Error> {
+    let len = decode_varint(source)?;
+    usize::try_from(len)
+        .map_err(|_| Error::LengthOverflow { len, })
+}
+
+pub fn len_prefix_size(len: u64) -> usize {
+    let mut size = 1;
+    let mut len = len >> 7;
+    while len > 0 {
+        size += 1;
+        len >>= 7;
+    }
+    size
+}
+
+macro_rules! impl_int_codec {
+    ($($int:ty),*) => {
+        $(
+            impl Codec for $int {
+                fn codec_id() -> String {
+                    stringify!($int).to_string()
+                }
+            }
+
+            impl Encode for $int {
+                fn encode(&self, target: &mut Vec<u8>) {
+                    target.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+
+            impl Decode for $int {
+                fn decode(source: &mut &[u8]) -> Result<Self, Error> {
+                    let mut bytes = [0; std::mem::size_of::<$int>()];
+                    let len = bytes.len();
+                    bytes.copy_from_slice(take(source, len)?);
+                    Ok(<$int>::from_be_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Codec for [u8] {
+    fn codec_id() -> String {
+        "bytes".to_string()
+    }
+
+    fn length_prefixed() -> bool {
+        true
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn codec_id() -> String {
+        <[u8]>::codec_id()
+    }
+
+    fn length_prefixed() -> bool {
+        true
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, target: &mut Vec<u8>) {
+        encode_varint(self.len() as u64, target);
+        target.extend_from_slice(self);
+    }
+}
+
+impl Encode for Vec<u8> {
+    fn encode(&self, target: &mut Vec<u8>) {
+        self[..].encode(target);
+    }
+}
+
+impl Decode for Vec<u8> {
+    fn decode(source: &mut &[u8]) -> Result<Self, Error> {
+        let len = decode_len(source)?;
+        Ok(take(source, len)?.to_vec())
+    }
+}
+
+impl Codec for str {
+    fn codec_id() -> String {
+        "string".to_string()
+    }
+
+    fn length_prefixed() -> bool {
+        true
+    }
+}
+
+impl Codec for String {
+    fn codec_id() -> String {
+        str::codec_id()
+    }
+
+    fn length_prefixed() -> bool {
+        true
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, target: &mut Vec<u8>) {
+        self.as_bytes().encode(target);
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, target: &mut Vec<u8>) {
+        self.as_str().encode(target);
+    }
+}
+
+impl Decode for String {
+    fn decode(source: &mut &[u8]) -> Result<Self, Error> {
+        String::from_utf8(Vec::decode(source)?)
+            .map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+impl<T> Codec for &T where T: Codec + ?Sized {
+    fn codec_id() -> String {
+        T::codec_id()
+    }
+
+    fn length_prefixed() -> bool {
+        T::length_prefixed()
+    }
+}
+
+impl<T> Encode for &T where T: Encode + ?Sized {
+    fn encode(&self, target: &mut Vec<u8>) {
+        (**self).encode(target);
+    }
+}
+
+impl Codec for () {
+    fn codec_id() -> String {
+        "()".to_string()
+    }
+}
+
+impl Encode for () {
+    fn encode(&self, _target: &mut Vec<u8>) { }
+}
+
+impl Decode for () {
+    fn decode(_source: &mut &[u8]) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_tuple_codec {
+    ($(($($name:ident : $index:tt),+)),*) => {
+        $(
+            impl<$($name),+> Codec for ($($name,)+) where $($name: Codec),+ {
+                fn codec_id() -> String {
+                    let ids = [$($name::codec_id()),+];
+                    format!("({})", ids.join(","))
+                }
+            }
+
+            impl<$($name),+> Encode for ($($name,)+) where $($name: Encode),+ {
+                fn encode(&self, target: &mut Vec<u8>) {
+                    $(self.$index.encode(target);)+
+                }
+            }
+
+            impl<$($name),+> Decode for ($($name,)+) where $($name: Decode),+ {
+                fn decode(source: &mut &[u8]) -> Result<Self, Error> {
+                    Ok(($($name::decode(source)?,)+))
+                }
+            }
+        )*
+    };
+}
+
+impl_tuple_codec!(
+    (A: 0),
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3)
+);
@@ -1,7 +1,16 @@
 #![forbid(unsafe_code)]
 
 pub mod sketch;
+pub mod block;
+pub mod crc32c;
+pub mod codec;
+pub mod trailer;
 pub mod writer;
+pub mod reader;
+pub mod verify;
+
+pub use verify::verify;
+pub use writer::builder::TreeBuilder;
 
 #[cfg(test)]
 mod tests;
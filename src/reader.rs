@@ -0,0 +1,519 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fs,
+    io::{
+        self,
+        Read,
+        Seek,
+    },
+    marker::PhantomData,
+    mem,
+    ops::RangeBounds,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    writer::{
+        level_files,
+        regions,
+        value_log,
+    },
+    block,
+    codec,
+    crc32c,
+    sketch,
+    trailer,
+};
+
+pub mod source;
+pub mod scan;
+pub mod range;
+pub mod value;
+
+use source::BlockSource;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug)]
+pub enum Error {
+    Header(trailer::Error),
+    Trailer(trailer::Error),
+    Sketch(sketch::Error),
+    UnexpectedLayout,
+    ManifestOpen(io::Error),
+    LevelFileOpen {
+        level_index: usize,
+        error: io::Error,
+    },
+    KeyCodecMismatch {
+        expected: String,
+        found: String,
+    },
+    ValueCodecMismatch {
+        expected: String,
+        found: String,
+    },
+    BlockIndexOutOfRange {
+        level_index: usize,
+        block_index: usize,
+    },
+    BlockSeek {
+        level_index: usize,
+        block_index: usize,
+        error: io::Error,
+    },
+    BlockRead {
+        level_index: usize,
+        block_index: usize,
+        error: io::Error,
+    },
+    BlockChecksumMismatch {
+        level_index: usize,
+        block_index: usize,
+        expected: u32,
+        actual: u32,
+    },
+    BlockDecode {
+        level_index: usize,
+        block_index: usize,
+        error: block::Error,
+    },
+    KeyDecode {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: codec::Error,
+    },
+    ValueDecode {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: codec::Error,
+    },
+    MissingChildBlock {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+    },
+    OverflowFileOpen(io::Error),
+    MissingOverflowFile {
+        offset: u64,
+    },
+    OverflowSeek {
+        offset: u64,
+        error: io::Error,
+    },
+    OverflowRead {
+        offset: u64,
+        error: io::Error,
+    },
+    OverflowChecksumMismatch {
+        offset: u64,
+        expected: u32,
+        actual: u32,
+    },
+    OverflowDecode {
+        offset: u64,
+        error: block::Error,
+    },
+    ValueLogRequired,
+    MissingValueLog {
+        offset: u64,
+    },
+    LogRefOutOfBounds {
+        offset: u64,
+        value_len: u64,
+        value_log_len: u64,
+    },
+    ValueLogSeek {
+        offset: u64,
+        error: io::Error,
+    },
+    ValueLogRead {
+        offset: u64,
+        error: io::Error,
+    },
+    ValueLogChecksumMismatch {
+        offset: u64,
+        expected: u32,
+        actual: u32,
+    },
+    StreamedValuePrefix(codec::Error),
+    StreamedValueLenMismatch {
+        value_len: u64,
+        prefix_len: u64,
+    },
+}
+
+pub struct Reader<S, K, V> {
+    source: S,
+    sketch: Arc<sketch::Tree>,
+    page_size: usize,
+    value_log_len: Option<u64>,
+    page: Vec<u8>,
+    overflow_page: Vec<u8>,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<R, K, V> Reader<source::SingleFile<R>, K, V> where R: Read + Seek, K: codec::Decode + Ord, V: codec::Decode {
+    pub fn open(source: R) -> Result<Reader<source::SingleFile<R>, K, V>, Error> {
+        let (source, trailer) = open_single_file(source)?;
+        if trailer.value_log_len.is_some() {
+            return Err(Error::ValueLogRequired);
+        }
+        Reader::with_source(source, trailer)
+    }
+
+    pub fn open_with_value_log<L>(source: R, log: L) -> Result<Reader<source::SingleFileValueLog<R, L>, K, V>, Error>
+    where L: Read + Seek,
+    {
+        let (source, trailer) = open_single_file(source)?;
+        Reader::with_source(source::ValueLog::new(source, log), trailer)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source.into_inner()
+    }
+}
+
+fn open_single_file<R>(mut source: R) -> Result<(source::SingleFile<R>, trailer::Trailer), Error> where R: Read + Seek {
+    let mut trailer = read_trailer(&mut source)?;
+    let blocks_offsets = match &mut trailer.layout {
+        // the offsets table goes to the source, the reader only needs the rest of the trailer
+        trailer::Layout::Indexed { blocks_offsets, } =>
+            source::BlockOffsets::Indexed(mem::take(blocks_offsets)),
+        trailer::Layout::Regions => {
+            let sketch = restore_sketch(&trailer)?;
+            source::BlockOffsets::Regions {
+                regions: regions::Regions::new(&sketch, trailer.page_size),
+                blocks_counts: trailer.levels.iter().map(|level| level.blocks_count as usize).collect(),
+            }
+        },
+        trailer::Layout::LevelFiles { .. } =>
+            return Err(Error::UnexpectedLayout),
+    };
+    Ok((source::SingleFile::new(source, blocks_offsets), trailer))
+}
+
+impl<K, V> Reader<source::LevelFiles, K, V> where K: codec::Decode + Ord, V: codec::Decode {
+    pub fn open_level_files<P>(dir: P) -> Result<Reader<source::LevelFiles, K, V>, Error> where P: AsRef<Path> {
+        let dir = dir.as_ref();
+        let mut manifest = fs::File::open(dir.join(level_files::MANIFEST_FILE_NAME))
+            .map_err(Error::ManifestOpen)?;
+        let trailer = read_trailer(&mut manifest)?;
+        let file_names = match &trailer.layout {
+            trailer::Layout::LevelFiles { file_names, } =>
+                file_names,
+            _ =>
+                return Err(Error::UnexpectedLayout),
+        };
+        let mut files = Vec::with_capacity(file_names.len());
+        for (level_index, file_name) in file_names.iter().enumerate() {
+            let file = fs::File::open(dir.join(file_name))
+                .map_err(|error| Error::LevelFileOpen { level_index, error, })?;
+            files.push(file);
+        }
+        let overflow = if trailer.overflow_pages_count > 0 {
+            let file = fs::File::open(dir.join(level_files::OVERFLOW_FILE_NAME))
+                .map_err(Error::OverflowFileOpen)?;
+            Some(file)
+        } else {
+            None
+        };
+        let blocks_counts = trailer.levels
+            .iter()
+            .map(|level| level.blocks_count as usize)
+            .collect();
+        Reader::with_source(source::LevelFiles::new(files, blocks_counts, overflow), trailer)
+    }
+}
+
+impl<S, K, V> Reader<S, K, V> where S: BlockSource, K: codec::Decode + Ord, V: codec::Decode {
+    pub fn with_source(source: S, trailer: trailer::Trailer) -> Result<Reader<S, K, V>, Error> {
+        if trailer.key_codec != K::codec_id() {
+            return Err(Error::KeyCodecMismatch { expected: K::codec_id(), found: trailer.key_codec, });
+        }
+        if trailer.value_codec != V::codec_id() {
+            return Err(Error::ValueCodecMismatch { expected: V::codec_id(), found: trailer.value_codec, });
+        }
+        let sketch = restore_sketch(&trailer)?;
+        Ok(Reader {
+            source,
+            sketch: Arc::new(sketch),
+            page_size: trailer.page_size,
+            value_log_len: trailer.value_log_len,
+            page: Vec::new(),
+            overflow_page: Vec::new(),
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn sketch(&self) -> &sketch::Tree {
+        &self.sketch
+    }
+
+    pub(crate) fn shared_sketch(&self) -> Arc<sketch::Tree> {
+        Arc::clone(&self.sketch)
+    }
+
+    pub fn into_source(self) -> S {
+        self.source
+    }
+
+    pub fn value_log_len(&self) -> Option<u64> {
+        self.value_log_len
+    }
+
+    pub fn scan(&mut self) -> scan::Scan<'_, S, K, V> {
+        scan::Scan::new(self)
+    }
+
+    pub fn range<B>(&mut self, range: B) -> range::Range<'_, S, K, V> where B: RangeBounds<K>, K: Clone {
+        range::Range::new(self, range.start_bound().cloned(), range.end_bound().cloned())
+    }
+
+    pub fn rev_scan(&mut self) -> scan::Scan<'_, S, K, V> {
+        scan::Scan::new_rev(self)
+    }
+
+    pub fn rev_range<B>(&mut self, range: B) -> range::Range<'_, S, K, V> where B: RangeBounds<K>, K: Clone {
+        range::Range::new_rev(self, range.start_bound().cloned(), range.end_bound().cloned())
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Result<Option<V>, Error> where K: Borrow<Q>, Q: Ord + ?Sized {
+        let mut page = mem::take(&mut self.page);
+        let result = self.lookup(key, &mut page)
+            .and_then(|found| {
+                let Some(position) = found else {
+                    return Ok(None);
+                };
+                let block = block::Block::decode(&page)
+                    .map_err(|error| Error::BlockDecode { level_index: position.level_index, block_index: position.block_index, error, })?;
+                self.decode_item(&block, position.level_index, position.block_index, position.item_index)
+                    .map(|(_key, value)| Some(value))
+            });
+        self.page = page;
+        result
+    }
+
+    // Streams the value found for `key` instead of decoding it, an overflow value is read a page at a time. The
+    // codec length prefix of byte and string values is stripped, anything else is streamed as encoded.
+    pub fn get_value_reader<Q>(&mut self, key: &Q) -> Result<Option<value::ValueReader<'_, S, K, V>>, Error> where K: Borrow<Q>, Q: Ord + ?Sized {
+        let mut page = mem::take(&mut self.page);
+        let found = self.lookup(key, &mut page)
+            .and_then(|found| {
+                let Some(position) = found else {
+                    return Ok(None);
+                };
+                let sketch::ItemPosition { level_index, block_index, item_index, } = position;
+                let block = block::Block::decode(&page)
+                    .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+                let (_key, value) = block.item(item_index)
+                    .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+                if let block::Value::Log(log_ref) = value {
+                    self.check_log_ref(log_ref)?;
+                }
+                Ok(Some(value::Source::new(value)))
+            });
+        self.page = page;
+        Ok(found?.map(|source| value::ValueReader::new(self, source, V::length_prefixed())))
+    }
+
+    fn lookup<Q>(&mut self, key: &Q, page: &mut Vec<u8>) -> Result<Option<sketch::ItemPosition>, Error> where K: Borrow<Q>, Q: Ord + ?Sized {
+        if self.sketch.levels().is_empty() {
+            return Ok(None);
+        }
+        let mut level_index = 0;
+        let mut block_index = 0;
+        loop {
+            self.read_block(level_index, block_index, page)?;
+            let block = block::Block::decode(page)
+                .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+            let found = search_block::<K, Q>(&block, key, level_index, block_index)?;
+            if self.sketch.is_separators_level(level_index) {
+                // a separator is the greatest key of its child subtree
+                let (Ok(item_index) | Err(item_index)) = found;
+                if item_index >= block.items_count() {
+                    return Ok(None);
+                }
+                block_index = self.child_block_index(level_index, block_index, item_index)
+                    .ok_or(Error::MissingChildBlock { level_index, block_index, item_index, })?;
+                level_index += 1;
+                continue;
+            }
+            match found {
+                Ok(item_index) =>
+                    return Ok(Some(sketch::ItemPosition { level_index, block_index, item_index, })),
+                Err(item_index) if item_index < block.items_count() =>
+                    match self.child_block_index(level_index, block_index, item_index) {
+                        Some(child_block_index) => {
+                            level_index += 1;
+                            block_index = child_block_index;
+                        },
+                        None =>
+                            return Ok(None),
+                    },
+                Err(..) =>
+                    return Ok(None),
+            }
+        }
+    }
+
+    pub(crate) fn child_block_index(&self, level_index: usize, block_index: usize, item_index: usize) -> Option<usize> {
+        self.sketch.child_block(sketch::ItemPosition { level_index, block_index, item_index, })
+            .map(|child| child.block_index)
+    }
+
+    pub(crate) fn read_block(&mut self, level_index: usize, block_index: usize, page: &mut Vec<u8>) -> Result<(), Error> {
+        page.resize(self.page_size + block::CHECKSUM_SIZE, 0);
+        self.source.read_page(level_index, block_index, page)?;
+        block::unseal_page(page)
+            .map_err(|error| match error {
+                block::Error::ChecksumMismatch { expected, actual, } =>
+                    Error::BlockChecksumMismatch { level_index, block_index, expected, actual, },
+                error =>
+                    Error::BlockDecode { level_index, block_index, error, },
+            })
+    }
+
+    // leaves in `page` just the part of the value stored in the overflow page `page_index`
+    pub(crate) fn read_overflow_page(&mut self, overflow: block::OverflowRef, page_index: u64, page: &mut Vec<u8>) -> Result<(), Error> {
+        let page_size = self.page_size as u64;
+        let record_size = page_size + block::CHECKSUM_SIZE as u64;
+        let offset = overflow.offset.saturating_add(page_index.saturating_mul(record_size));
+        page.resize(self.page_size + block::CHECKSUM_SIZE, 0);
+        self.source.read_overflow_page(offset, page)?;
+        block::unseal_page(page)
+            .map_err(|error| match error {
+                block::Error::ChecksumMismatch { expected, actual, } =>
+                    Error::OverflowChecksumMismatch { offset, expected, actual, },
+                error =>
+                    Error::OverflowDecode { offset, error, },
+            })?;
+        let chunk_len = overflow.value_len.saturating_sub(page_index * page_size).min(page_size);
+        page.truncate(chunk_len as usize);
+        Ok(())
+    }
+
+    pub(crate) fn read_value_log(&mut self, offset: u64, record: &mut [u8]) -> Result<(), Error> {
+        self.source.read_value_log(offset, record)
+    }
+
+    fn check_log_ref(&self, log_ref: block::LogRef) -> Result<(), Error> {
+        let value_log_len = self.value_log_len.unwrap_or(0);
+        let record_end = log_ref.offset
+            .checked_add(log_ref.value_len)
+            .and_then(|value_end| value_end.checked_add(value_log::RECORD_CHECKSUM_SIZE as u64));
+        match record_end {
+            Some(record_end) if record_end <= value_log_len =>
+                Ok(()),
+            _ =>
+                Err(Error::LogRefOutOfBounds { offset: log_ref.offset, value_len: log_ref.value_len, value_log_len, }),
+        }
+    }
+
+    fn load_log_value(&mut self, log_ref: block::LogRef, value: &mut Vec<u8>) -> Result<(), Error> {
+        self.check_log_ref(log_ref)?;
+        let value_len = log_ref.value_len as usize;
+        value.resize(value_len + value_log::RECORD_CHECKSUM_SIZE, 0);
+        self.read_value_log(log_ref.offset, value)?;
+        let expected = u32::from_le_bytes([value[value_len], value[value_len + 1], value[value_len + 2], value[value_len + 3]]);
+        value.truncate(value_len);
+        let actual = crc32c::checksum(value);
+        if actual != expected {
+            return Err(Error::ValueLogChecksumMismatch { offset: log_ref.offset, expected, actual, });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode_item(&mut self, block: &block::Block, level_index: usize, block_index: usize, item_index: usize) -> Result<(K, V), Error> {
+        let (key, value) = block.item(item_index)
+            .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+        let key = codec::decode(&key)
+            .map_err(|error| Error::KeyDecode { level_index, block_index, item_index, error, })?;
+        let value = match value {
+            block::Value::Inline(value) =>
+                codec::decode(value),
+            block::Value::Overflow(overflow) => {
+                let mut value = Vec::new();
+                let mut page = mem::take(&mut self.overflow_page);
+                let loaded = (0 .. block::overflow_pages_count(self.page_size, overflow.value_len))
+                    .try_for_each(|page_index| {
+                        self.read_overflow_page(overflow, page_index, &mut page)?;
+                        value.extend_from_slice(&page);
+                        Ok(())
+                    });
+                self.overflow_page = page;
+                loaded?;
+                codec::decode(&value)
+            },
+            block::Value::Log(log_ref) => {
+                let mut value = Vec::new();
+                self.load_log_value(log_ref, &mut value)?;
+                codec::decode(&value)
+            },
+        };
+        let value = value
+            .map_err(|error| Error::ValueDecode { level_index, block_index, item_index, error, })?;
+        Ok((key, value))
+    }
+}
+
+fn restore_sketch(trailer: &trailer::Trailer) -> Result<sketch::Tree, Error> {
+    let restored = match trailer.block_fill {
+        sketch::BlockFill::Explicit =>
+            sketch::Tree::restore_explicit(trailer.items_total, trailer.mode, trailer.levels.clone(), trailer.blocks_items_counts.clone()),
+        block_fill =>
+            sketch::Tree::restore(trailer.items_total, trailer.block_sizes.clone(), block_fill, trailer.mode, trailer.levels.clone()),
+    };
+    restored.map_err(Error::Sketch)
+}
+
+fn read_trailer<R>(source: &mut R) -> Result<trailer::Trailer, Error> where R: Read + Seek {
+    trailer::read_header(source)
+        .map_err(Error::Header)?;
+    trailer::Trailer::read_from(source)
+        .map_err(Error::Trailer)
+}
+
+fn search_block<K, Q>(block: &block::Block, key: &Q, level_index: usize, block_index: usize) -> Result<Result<usize, usize>, Error>
+where K: codec::Decode + Borrow<Q>,
+      Q: Ord + ?Sized,
+{
+    let mut keys = block.key_cursor();
+    block.search_by(|item_index| -> Result<Ordering, Error> {
+        let item_key = keys.key(item_index)
+            .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+        let item_key: K = codec::decode(item_key)
+            .map_err(|error| Error::KeyDecode { level_index, block_index, item_index, error, })?;
+        Ok(item_key.borrow().cmp(key))
+    })
+}
+
+// returns the first item with a key not less than `key`, or greater than `key` when `upper` is set
+fn search_bound<K, Q>(block: &block::Block, key: &Q, upper: bool, level_index: usize, block_index: usize) -> Result<usize, Error>
+where K: codec::Decode + Borrow<Q>,
+      Q: Ord + ?Sized,
+{
+    let mut keys = block.key_cursor();
+    block.partition_point_by(|item_index| -> Result<bool, Error> {
+        let item_key = keys.key(item_index)
+            .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+        let item_key: K = codec::decode(item_key)
+            .map_err(|error| Error::KeyDecode { level_index, block_index, item_index, error, })?;
+        let ordering = item_key.borrow().cmp(key);
+        Ok(ordering.is_lt() || (upper && ordering.is_eq()))
+    })
+}
+
+pub(crate) fn decode_key<K>(block: &block::Block, level_index: usize, block_index: usize, item_index: usize) -> Result<K, Error>
+where K: codec::Decode,
+{
+    let key = block.key(item_index)
+        .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+    codec::decode(&key)
+        .map_err(|error| Error::KeyDecode { level_index, block_index, item_index, error, })
+}
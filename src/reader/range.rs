@@ -0,0 +1,217 @@
+use std::{
+    cmp::min,
+    mem,
+    ops::Bound,
+};
+
+use crate::{
+    writer::plan,
+    block,
+    codec,
+    sketch,
+};
+
+use super::{
+    source::BlockSource,
+    scan,
+    search_bound,
+    Error,
+    Reader,
+};
+
+pub struct Range<'a, S, K, V> {
+    state: State<'a, S, K, V>,
+    direction: Direction,
+    stop: Bound<K>,
+}
+
+enum State<'a, S, K, V> {
+    Seek {
+        reader: &'a mut Reader<S, K, V>,
+        start: Bound<K>,
+    },
+    Scan(scan::Scan<'a, S, K, V>),
+    Done,
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+impl<'a, S, K, V> Range<'a, S, K, V> where K: Ord {
+    pub(super) fn new(reader: &'a mut Reader<S, K, V>, start: Bound<K>, end: Bound<K>) -> Range<'a, S, K, V> {
+        Range {
+            state: State::Seek { reader, start, },
+            direction: Direction::Forward,
+            stop: end,
+        }
+    }
+
+    pub(super) fn new_rev(reader: &'a mut Reader<S, K, V>, start: Bound<K>, end: Bound<K>) -> Range<'a, S, K, V> {
+        Range {
+            state: State::Seek { reader, start: end, },
+            direction: Direction::Backward,
+            stop: start,
+        }
+    }
+
+    fn within_stop(&self, key: &K) -> bool {
+        match (&self.stop, self.direction) {
+            (Bound::Included(stop), Direction::Forward) =>
+                key <= stop,
+            (Bound::Excluded(stop), Direction::Forward) =>
+                key < stop,
+            (Bound::Included(stop), Direction::Backward) =>
+                key >= stop,
+            (Bound::Excluded(stop), Direction::Backward) =>
+                key > stop,
+            (Bound::Unbounded, _) =>
+                true,
+        }
+    }
+}
+
+impl<'a, S, K, V> Iterator for Range<'a, S, K, V> where S: BlockSource, K: codec::Decode + Ord, V: codec::Decode {
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Seek { reader, start, } if reader.sketch.mode() == sketch::Mode::BPlus =>
+                    match seek_leaf(reader, start.as_ref(), self.direction) {
+                        Ok((block_index, item_index, levels)) => {
+                            let backward = matches!(self.direction, Direction::Backward);
+                            self.state = State::Scan(scan::Scan::with_leaf_position(reader, block_index, item_index, backward, levels));
+                        },
+                        Err(error) =>
+                            return Some(Err(error)),
+                    },
+                State::Seek { reader, start, } =>
+                    match seek(reader, start.as_ref(), self.direction) {
+                        Ok((path, levels)) =>
+                            self.state = State::Scan(match self.direction {
+                                Direction::Forward =>
+                                    scan::Scan::with_path(reader, &path, levels),
+                                Direction::Backward =>
+                                    scan::Scan::with_rev_path(reader, &path, levels),
+                            }),
+                        Err(error) =>
+                            return Some(Err(error)),
+                    },
+                State::Scan(mut scan) =>
+                    match scan.next()? {
+                        Ok((key, value)) => {
+                            if !self.within_stop(&key) {
+                                return None;
+                            }
+                            self.state = State::Scan(scan);
+                            return Some(Ok((key, value)));
+                        },
+                        Err(error) =>
+                            return Some(Err(error)),
+                    },
+                State::Done =>
+                    return None,
+            }
+        }
+    }
+}
+
+// Descends from the root to the gap where the scan should resume: `item_index` in a path position
+// points between the items `item_index - 1` and `item_index`, which is also the slot of the child block.
+fn seek<S, K, V>(
+    reader: &mut Reader<S, K, V>,
+    start: Bound<&K>,
+    direction: Direction,
+)
+    -> Result<(Vec<plan::Position>, Vec<scan::LevelPage>), Error>
+where S: BlockSource,
+      K: codec::Decode + Ord,
+      V: codec::Decode,
+{
+    let mut levels = vec![scan::LevelPage::default(); reader.sketch.levels().len()];
+    let mut path = Vec::with_capacity(levels.len());
+    if levels.is_empty() {
+        return Ok((path, levels));
+    }
+
+    let mut level_index = 0;
+    let mut block_index = 0;
+    loop {
+        let level_page = &mut levels[level_index];
+        reader.read_block(level_index, block_index, &mut level_page.page)?;
+        level_page.block_index = Some(block_index);
+        let block = block::Block::decode(&level_page.page)
+            .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+        let item_index = seek_gap(&block, start, direction, level_index, block_index)?;
+        path.push(plan::Position { block_index, item_index, });
+        // equal keys may continue into the child before the found item, so the descent goes on to the leaves
+        if item_index >= block.items_count() {
+            break;
+        }
+        match reader.child_block_index(level_index, block_index, item_index) {
+            Some(child_block_index) => {
+                level_index += 1;
+                block_index = child_block_index;
+            },
+            None =>
+                break,
+        }
+    }
+    Ok((path, levels))
+}
+
+// Descends through B+ separators to the leaf gap where the scan should resume. Keys beyond the last
+// separator of a block can only neighbour the end of its last child, the leaf scan continues from there.
+fn seek_leaf<S, K, V>(
+    reader: &mut Reader<S, K, V>,
+    start: Bound<&K>,
+    direction: Direction,
+)
+    -> Result<(usize, usize, Vec<scan::LevelPage>), Error>
+where S: BlockSource,
+      K: codec::Decode + Ord,
+      V: codec::Decode,
+{
+    let mut levels = vec![scan::LevelPage::default(); reader.sketch.levels().len()];
+    if levels.is_empty() {
+        return Ok((0, 0, levels));
+    }
+
+    let mut level_index = 0;
+    let mut block_index = 0;
+    loop {
+        let level_page = &mut levels[level_index];
+        reader.read_block(level_index, block_index, &mut level_page.page)?;
+        level_page.block_index = Some(block_index);
+        let block = block::Block::decode(&level_page.page)
+            .map_err(|error| Error::BlockDecode { level_index, block_index, error, })?;
+        let item_index = seek_gap(&block, start, direction, level_index, block_index)?;
+        if !reader.sketch.is_separators_level(level_index) {
+            return Ok((block_index, item_index, levels));
+        }
+        let item_index = min(item_index, block.items_count().saturating_sub(1));
+        block_index = reader.child_block_index(level_index, block_index, item_index)
+            .ok_or(Error::MissingChildBlock { level_index, block_index, item_index, })?;
+        level_index += 1;
+    }
+}
+
+// The gap between the items already passed and the items still to scan: a forward scan starting at
+// an included key and a backward one stopping short of an excluded key both begin at the first of equal keys.
+fn seek_gap<K>(block: &block::Block, start: Bound<&K>, direction: Direction, level_index: usize, block_index: usize) -> Result<usize, Error>
+where K: codec::Decode + Ord,
+{
+    match (start, direction) {
+        (Bound::Included(key), Direction::Forward) | (Bound::Excluded(key), Direction::Backward) =>
+            search_bound::<K, K>(block, key, false, level_index, block_index),
+        (Bound::Excluded(key), Direction::Forward) | (Bound::Included(key), Direction::Backward) =>
+            search_bound::<K, K>(block, key, true, level_index, block_index),
+        (Bound::Unbounded, Direction::Forward) =>
+            Ok(0),
+        (Bound::Unbounded, Direction::Backward) =>
+            Ok(block.items_count()),
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::{
+    writer::plan,
+    block,
+    codec,
+    sketch,
+};
+
+use super::{
+    source::BlockSource,
+    Error,
+    Reader,
+};
+
+pub struct Scan<'a, S, K, V> {
+    reader: &'a mut Reader<S, K, V>,
+    cursor: PlanCursor,
+    levels: Vec<LevelPage>,
+}
+
+#[derive(Clone, Default)]
+pub(super) struct LevelPage {
+    pub(super) block_index: Option<usize>,
+    pub(super) page: Vec<u8>,
+}
+
+enum PlanCursor {
+    Forward {
+        plan_ctx: plan::Context,
+        kont: Option<plan::Continue>,
+    },
+    Backward {
+        plan_ctx: plan::rev::Context,
+        kont: Option<plan::rev::Continue>,
+    },
+    Leaves(LeafCursor),
+}
+
+// walks the bottom level only, which holds every item of a B+ tree
+struct LeafCursor {
+    sketch: Arc<sketch::Tree>,
+    level_index: usize,
+    block_index: Option<usize>,
+    block_cursor: LeafBlockCursor,
+    backward: bool,
+}
+
+enum LeafBlockCursor {
+    Begin,
+    Write { index: usize, },
+}
+
+impl<'a, S, K, V> Scan<'a, S, K, V> {
+    pub(super) fn new(reader: &'a mut Reader<S, K, V>) -> Scan<'a, S, K, V> {
+        if reader.sketch.mode() == sketch::Mode::BPlus {
+            let leaf_cursor = LeafCursor::new(&reader.sketch, false);
+            return Scan::with_cursor(reader, PlanCursor::Leaves(leaf_cursor), None);
+        }
+        let plan_ctx = plan::Context::new(Arc::clone(&reader.sketch));
+        Scan::with_cursor(reader, PlanCursor::Forward { plan_ctx, kont: Some(plan::Script::boot()), }, None)
+    }
+
+    pub(super) fn new_rev(reader: &'a mut Reader<S, K, V>) -> Scan<'a, S, K, V> {
+        if reader.sketch.mode() == sketch::Mode::BPlus {
+            let leaf_cursor = LeafCursor::new(&reader.sketch, true);
+            return Scan::with_cursor(reader, PlanCursor::Leaves(leaf_cursor), None);
+        }
+        let plan_ctx = plan::rev::Context::new(Arc::clone(&reader.sketch));
+        Scan::with_cursor(reader, PlanCursor::Backward { plan_ctx, kont: Some(plan::rev::Script::boot()), }, None)
+    }
+
+    pub(super) fn with_path(reader: &'a mut Reader<S, K, V>, path: &[plan::Position], levels: Vec<LevelPage>) -> Scan<'a, S, K, V> {
+        let plan_ctx = plan::Context::with_path(Arc::clone(&reader.sketch), path);
+        Scan::with_cursor(reader, PlanCursor::Forward { plan_ctx, kont: Some(plan::Script::boot()), }, Some(levels))
+    }
+
+    pub(super) fn with_rev_path(reader: &'a mut Reader<S, K, V>, path: &[plan::Position], levels: Vec<LevelPage>) -> Scan<'a, S, K, V> {
+        let plan_ctx = plan::rev::Context::with_path(Arc::clone(&reader.sketch), path);
+        Scan::with_cursor(reader, PlanCursor::Backward { plan_ctx, kont: Some(plan::rev::Script::boot()), }, Some(levels))
+    }
+
+    pub(super) fn with_leaf_position(
+        reader: &'a mut Reader<S, K, V>,
+        block_index: usize,
+        item_index: usize,
+        backward: bool,
+        levels: Vec<LevelPage>,
+    )
+        -> Scan<'a, S, K, V>
+    {
+        let mut leaf_cursor = LeafCursor::new(&reader.sketch, backward);
+        leaf_cursor.block_index = leaf_cursor.block_index.map(|_| block_index);
+        leaf_cursor.block_cursor = LeafBlockCursor::Write { index: item_index, };
+        Scan::with_cursor(reader, PlanCursor::Leaves(leaf_cursor), Some(levels))
+    }
+
+    fn with_cursor(reader: &'a mut Reader<S, K, V>, cursor: PlanCursor, levels: Option<Vec<LevelPage>>) -> Scan<'a, S, K, V> {
+        let levels = levels
+            .unwrap_or_else(|| vec![LevelPage::default(); reader.sketch.levels().len()]);
+        Scan { reader, cursor, levels, }
+    }
+}
+
+impl PlanCursor {
+    fn step(&mut self) -> Option<(plan::Op, usize, usize)> {
+        match self {
+            PlanCursor::Forward { plan_ctx, kont, } =>
+                match kont.take()?.next.step(plan_ctx) {
+                    plan::Instruction::Perform(plan::Perform { op, level_index, block_index, next, }) => {
+                        *kont = Some(next);
+                        Some((op, level_index, block_index))
+                    },
+                    plan::Instruction::Done =>
+                        None,
+                },
+            PlanCursor::Backward { plan_ctx, kont, } =>
+                match kont.take()?.next.step(plan_ctx) {
+                    plan::rev::Instruction::Perform(plan::rev::Perform { op, level_index, block_index, next, }) => {
+                        *kont = Some(next);
+                        Some((op, level_index, block_index))
+                    },
+                    plan::rev::Instruction::Done =>
+                        None,
+                },
+            PlanCursor::Leaves(leaf_cursor) =>
+                leaf_cursor.step(),
+        }
+    }
+
+    fn stop(&mut self) {
+        match self {
+            PlanCursor::Forward { kont, .. } =>
+                *kont = None,
+            PlanCursor::Backward { kont, .. } =>
+                *kont = None,
+            PlanCursor::Leaves(leaf_cursor) =>
+                leaf_cursor.block_index = None,
+        }
+    }
+}
+
+impl LeafCursor {
+    fn new(sketch: &Arc<sketch::Tree>, backward: bool) -> LeafCursor {
+        let level_index = sketch.leaf_level_index();
+        let block_index = level_index
+            .and_then(|level_index| sketch.levels()[level_index].blocks_count.checked_sub(1))
+            .map(|last_block_index| if backward { last_block_index as usize } else { 0 });
+        LeafCursor {
+            sketch: Arc::clone(sketch),
+            level_index: level_index.unwrap_or(0),
+            block_index,
+            block_cursor: LeafBlockCursor::Begin,
+            backward,
+        }
+    }
+
+    fn step(&mut self) -> Option<(plan::Op, usize, usize)> {
+        let block_index = self.block_index?;
+        let items_count = self.sketch.block_items_count(sketch::BlockPosition { level_index: self.level_index, block_index, })?;
+        match self.block_cursor {
+            LeafBlockCursor::Begin => {
+                let index = if self.backward { items_count } else { 0 };
+                self.block_cursor = LeafBlockCursor::Write { index, };
+                Some((plan::Op::BlockStart { items_count, }, self.level_index, block_index))
+            },
+            LeafBlockCursor::Write { index, } if !self.backward && index < items_count => {
+                self.block_cursor = LeafBlockCursor::Write { index: index + 1, };
+                Some((plan::Op::BlockItem { index, }, self.level_index, block_index))
+            },
+            LeafBlockCursor::Write { index, } if self.backward && index > 0 => {
+                self.block_cursor = LeafBlockCursor::Write { index: index - 1, };
+                Some((plan::Op::BlockItem { index: index - 1, }, self.level_index, block_index))
+            },
+            LeafBlockCursor::Write { .. } => {
+                self.block_cursor = LeafBlockCursor::Begin;
+                self.block_index = if self.backward { block_index.checked_sub(1) } else { Some(block_index + 1) };
+                Some((plan::Op::BlockFinish, self.level_index, block_index))
+            },
+        }
+    }
+}
+
+impl<'a, S, K, V> Iterator for Scan<'a, S, K, V> where S: BlockSource, K: codec::Decode + Ord, V: codec::Decode {
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.cursor.step()? {
+                (plan::Op::BlockStart { .. }, level_index, block_index) => {
+                    let level_page = &mut self.levels[level_index];
+                    if level_page.block_index != Some(block_index) {
+                        level_page.block_index = None;
+                        if let Err(error) = self.reader.read_block(level_index, block_index, &mut level_page.page) {
+                            self.cursor.stop();
+                            return Some(Err(error));
+                        }
+                        level_page.block_index = Some(block_index);
+                    }
+                },
+                (plan::Op::BlockItem { index, }, level_index, block_index) => {
+                    let item = block::Block::decode(&self.levels[level_index].page)
+                        .map_err(|error| Error::BlockDecode { level_index, block_index, error, })
+                        .and_then(|block| self.reader.decode_item(&block, level_index, block_index, index));
+                    if item.is_err() {
+                        self.cursor.stop();
+                    }
+                    return Some(item);
+                },
+                (plan::Op::BlockFinish, ..) =>
+                    (),
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fs,
+    io::{
+        Read,
+        Seek,
+        SeekFrom,
+    },
+};
+
+use crate::writer::regions::Regions;
+
+use super::Error;
+
+pub trait BlockSource {
+    fn read_page(&mut self, level_index: usize, block_index: usize, page: &mut [u8]) -> Result<(), Error>;
+
+    fn read_overflow_page(&mut self, offset: u64, page: &mut [u8]) -> Result<(), Error>;
+
+    fn read_value_log(&mut self, offset: u64, _record: &mut [u8]) -> Result<(), Error> {
+        Err(Error::MissingValueLog { offset, })
+    }
+}
+
+pub struct SingleFile<R> {
+    source: R,
+    blocks_offsets: BlockOffsets,
+}
+
+pub enum BlockOffsets {
+    Indexed(Vec<Vec<u64>>),
+    Regions {
+        regions: Regions,
+        blocks_counts: Vec<usize>,
+    },
+}
+
+impl<R> SingleFile<R> {
+    pub fn new(source: R, blocks_offsets: BlockOffsets) -> SingleFile<R> {
+        SingleFile { source, blocks_offsets, }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+impl BlockOffsets {
+    fn block_offset(&self, level_index: usize, block_index: usize) -> Option<u64> {
+        match self {
+            BlockOffsets::Indexed(blocks_offsets) =>
+                blocks_offsets
+                    .get(level_index)
+                    .and_then(|level_offsets| level_offsets.get(block_index))
+                    .cloned(),
+            BlockOffsets::Regions { regions, blocks_counts, } =>
+                if block_index < *blocks_counts.get(level_index)? {
+                    regions.block_offset(level_index, block_index)
+                } else {
+                    None
+                },
+        }
+    }
+}
+
+impl<R> BlockSource for SingleFile<R> where R: Read + Seek {
+    fn read_page(&mut self, level_index: usize, block_index: usize, page: &mut [u8]) -> Result<(), Error> {
+        let offset = self.blocks_offsets.block_offset(level_index, block_index)
+            .ok_or(Error::BlockIndexOutOfRange { level_index, block_index, })?;
+        self.source.seek(SeekFrom::Start(offset))
+            .map_err(|error| Error::BlockSeek { level_index, block_index, error, })?;
+        self.source.read_exact(page)
+            .map_err(|error| Error::BlockRead { level_index, block_index, error, })
+    }
+
+    fn read_overflow_page(&mut self, offset: u64, page: &mut [u8]) -> Result<(), Error> {
+        read_overflow_page(&mut self.source, offset, page)
+    }
+}
+
+pub struct LevelFiles {
+    files: Vec<fs::File>,
+    blocks_counts: Vec<usize>,
+    overflow: Option<fs::File>,
+}
+
+impl LevelFiles {
+    pub fn new(files: Vec<fs::File>, blocks_counts: Vec<usize>, overflow: Option<fs::File>) -> LevelFiles {
+        LevelFiles { files, blocks_counts, overflow, }
+    }
+}
+
+impl BlockSource for LevelFiles {
+    fn read_page(&mut self, level_index: usize, block_index: usize, page: &mut [u8]) -> Result<(), Error> {
+        let file = match self.files.get_mut(level_index) {
+            Some(file) if block_index < self.blocks_counts[level_index] =>
+                file,
+            _ =>
+                return Err(Error::BlockIndexOutOfRange { level_index, block_index, }),
+        };
+        let offset = block_index as u64 * page.len() as u64;
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|error| Error::BlockSeek { level_index, block_index, error, })?;
+        file.read_exact(page)
+            .map_err(|error| Error::BlockRead { level_index, block_index, error, })
+    }
+
+    fn read_overflow_page(&mut self, offset: u64, page: &mut [u8]) -> Result<(), Error> {
+        let overflow = self.overflow.as_mut()
+            .ok_or(Error::MissingOverflowFile { offset, })?;
+        read_overflow_page(overflow, offset, page)
+    }
+}
+
+pub type SingleFileValueLog<R, L> = ValueLog<SingleFile<R>, L>;
+
+pub struct ValueLog<S, L> {
+    source: S,
+    log: L,
+}
+
+impl<S, L> ValueLog<S, L> {
+    pub fn new(source: S, log: L) -> ValueLog<S, L> {
+        ValueLog { source, log, }
+    }
+
+    pub fn into_inner(self) -> (S, L) {
+        (self.source, self.log)
+    }
+}
+
+impl<S, L> BlockSource for ValueLog<S, L> where S: BlockSource, L: Read + Seek {
+    fn read_page(&mut self, level_index: usize, block_index: usize, page: &mut [u8]) -> Result<(), Error> {
+        self.source.read_page(level_index, block_index, page)
+    }
+
+    fn read_overflow_page(&mut self, offset: u64, page: &mut [u8]) -> Result<(), Error> {
+        self.source.read_overflow_page(offset, page)
+    }
+
+    fn read_value_log(&mut self, offset: u64, record: &mut [u8]) -> Result<(), Error> {
+        self.log.seek(SeekFrom::Start(offset))
+            .map_err(|error| Error::ValueLogSeek { offset, error, })?;
+        self.log.read_exact(record)
+            .map_err(|error| Error::ValueLogRead { offset, error, })
+    }
+}
+
+fn read_overflow_page<R>(source: &mut R, offset: u64, page: &mut [u8]) -> Result<(), Error> where R: Read + Seek {
+    source.seek(SeekFrom::Start(offset))
+        .map_err(|error| Error::OverflowSeek { offset, error, })?;
+    source.read_exact(page)
+        .map_err(|error| Error::OverflowRead { offset, error, })
+}
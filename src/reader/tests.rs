@@ -0,0 +1,591 @@
+use std::{
+    io::{
+        self,
+        Cursor,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    ops::Bound,
+};
+
+use super::{
+    super::{
+        block,
+        codec,
+        sketch,
+        trailer,
+        writer::file,
+    },
+    source,
+    Error,
+    Reader,
+};
+
+#[test]
+fn get_tree17_4() {
+    check_get_all(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn get_tree17_3() {
+    check_get_all(&sketch::Tree::new(17, 3));
+}
+
+#[test]
+fn get_tree22_3() {
+    check_get_all(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn get_tree1000_5() {
+    check_get_all(&sketch::Tree::new(1000, 5));
+}
+
+#[test]
+fn get_empty() {
+    let mut reader = make_reader(0, 4);
+    assert_eq!(reader.get(&b"k0000"[..]).unwrap(), None);
+}
+
+#[test]
+fn scan_tree17_4() {
+    check_scan_all(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn scan_tree17_3() {
+    check_scan_all(&sketch::Tree::new(17, 3));
+}
+
+#[test]
+fn scan_tree22_3() {
+    check_scan_all(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn scan_tree1000_5() {
+    check_scan_all(&sketch::Tree::new(1000, 5));
+}
+
+#[test]
+fn scan_empty() {
+    let mut reader = make_reader(0, 4);
+    assert_eq!(reader.scan().count(), 0);
+}
+
+#[test]
+fn scan_reads_each_block_once_forward() {
+    let sketch = sketch::Tree::new(1000, 5);
+    let items = (0 .. 1000)
+        .map(|index| (key(index), value(index)));
+    let cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, Vec<u8>, Vec<u8>> = Reader::open(SeekLog { inner: cursor, seeks: Vec::new(), }).unwrap();
+    assert_eq!(reader.scan().count(), 1000);
+
+    let block_seeks = reader.into_inner().seeks.split_off(2);
+    let blocks_total = sketch.levels().iter().map(|level| level.blocks_count as usize).sum();
+    assert_eq!(block_seeks.len(), blocks_total);
+    let mut offsets = block_seeks.clone();
+    offsets.sort_unstable();
+    offsets.dedup();
+    assert_eq!(offsets.len(), blocks_total);
+}
+
+#[test]
+fn range_tree17_4() {
+    check_ranges(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn range_tree22_3() {
+    check_ranges(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn range_tree40_3() {
+    check_ranges(&sketch::Tree::new(40, 3));
+}
+
+#[test]
+fn bplus_tree() {
+    for fill in [sketch::FillStrategy::TopHeavy, sketch::FillStrategy::MinHeight] {
+        for (items_total, block_size) in [(0, 4), (1, 4), (4, 4), (17, 4), (22, 3), (40, 3)] {
+            let sketch = sketch::Tree::try_with_mode(items_total, block_size, fill, sketch::Mode::BPlus).unwrap();
+            check_get_all(&sketch);
+            check_scan_all(&sketch);
+            check_rev_scan_all(&sketch);
+            check_ranges(&sketch);
+        }
+        let sketch = sketch::Tree::try_with_mode(1000, 5, fill, sketch::Mode::BPlus).unwrap();
+        check_get_all(&sketch);
+        check_scan_all(&sketch);
+        check_rev_scan_all(&sketch);
+    }
+}
+
+#[test]
+fn block_sizes() {
+    let block_sizes = [
+        sketch::BlockSizes::LeafInner { leaf: 8, inner: 3, },
+        sketch::BlockSizes::PerLevel(vec![2, 5, 3]),
+    ];
+    for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+        for sizes in &block_sizes {
+            for items_total in [1, 17, 40] {
+                let sketch = sketch::Tree::try_with_sizes(items_total, sizes.clone(), sketch::FillStrategy::TopHeavy, mode).unwrap();
+                check_get_all(&sketch);
+                check_scan_all(&sketch);
+                check_rev_scan_all(&sketch);
+                check_ranges(&sketch);
+            }
+            let sketch = sketch::Tree::try_with_sizes(500, sizes.clone(), sketch::FillStrategy::MinHeight, mode).unwrap();
+            check_get_all(&sketch);
+            check_scan_all(&sketch);
+            check_rev_scan_all(&sketch);
+        }
+    }
+}
+
+#[test]
+fn byte_budget() {
+    for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+        for items_total in [0, 1, 17, 40] {
+            check_budget_sketch(items_total, mode, true);
+        }
+        check_budget_sketch(300, mode, false);
+    }
+}
+
+fn check_budget_sketch(items_total: u64, mode: sketch::Mode, with_ranges: bool) {
+    // mirrors the items written by `make_sketch_reader`
+    let mut key_buf = Vec::new();
+    let mut value_buf = Vec::new();
+    let footprints: Vec<_> = (0 .. items_total)
+        .map(|index| {
+            codec::encode(&key(index * 2), &mut key_buf);
+            codec::encode(&value(index * 2), &mut value_buf);
+            sketch::Footprint {
+                item: block::item_footprint(key_buf.len(), value_buf.len()),
+                separator: block::item_footprint(key_buf.len(), 0),
+                shared: 0,
+                delta: 0,
+            }
+        })
+        .collect();
+    let budget = sketch::Budget { block_budget: block::page_budget(256), max_footprint: 64, restart_interval: 1, packed_key_len: 0, };
+    let sketch = sketch::Tree::try_with_budget(items_total, budget, mode, footprints).unwrap();
+    check_get_all(&sketch);
+    check_scan_all(&sketch);
+    check_rev_scan_all(&sketch);
+    if with_ranges {
+        check_ranges(&sketch);
+    }
+}
+
+#[test]
+fn bplus_scan_reads_leaves_only() {
+    let sketch = sketch::Tree::try_with_mode(1000, 5, sketch::FillStrategy::TopHeavy, sketch::Mode::BPlus).unwrap();
+    let items = (0 .. 1000)
+        .map(|index| (key(index), value(index)));
+    let cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut cursor.clone()).unwrap();
+    let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+    let mut reader: Reader<_, Vec<u8>, Vec<u8>> = Reader::open(SeekLog { inner: cursor, seeks: Vec::new(), }).unwrap();
+    assert_eq!(reader.scan().count(), 1000);
+
+    let block_seeks = reader.into_inner().seeks.split_off(2);
+    assert_eq!(&block_seeks, blocks_offsets.last().unwrap());
+}
+
+#[test]
+fn range_empty() {
+    let mut reader = make_reader(0, 4);
+    assert_eq!(reader.range(..).count(), 0);
+}
+
+#[test]
+fn range_reads_each_block_once() {
+    let sketch = sketch::Tree::new(1000, 5);
+    let items = (0 .. 1000)
+        .map(|index| (key(index), value(index)));
+    let cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, Vec<u8>, Vec<u8>> = Reader::open(SeekLog { inner: cursor, seeks: Vec::new(), }).unwrap();
+    let lo = key(100);
+    let hi = key(900);
+    assert_eq!(reader.range(lo .. hi).count(), 800);
+
+    let mut block_seeks = reader.into_inner().seeks.split_off(2);
+    let seeks_count = block_seeks.len();
+    block_seeks.sort_unstable();
+    block_seeks.dedup();
+    assert_eq!(block_seeks.len(), seeks_count);
+}
+
+fn check_ranges(sketch: &sketch::Tree) {
+    let items_total = sketch.items_total();
+    let mut reader = make_sketch_reader(sketch);
+    let expected: Vec<_> = (0 .. items_total)
+        .map(|index| (key(index * 2), value(index * 2)))
+        .collect();
+    let max_key = items_total * 2 + 1;
+    for lo in 0 ..= max_key {
+        for hi in lo ..= max_key {
+            let (lo_key, hi_key) = (key(lo), key(hi));
+            let bounds = [
+                (Bound::Included(&lo_key), Bound::Excluded(&hi_key)),
+                (Bound::Included(&lo_key), Bound::Included(&hi_key)),
+                (Bound::Excluded(&lo_key), Bound::Included(&hi_key)),
+                (Bound::Excluded(&lo_key), Bound::Unbounded),
+                (Bound::Unbounded, Bound::Excluded(&hi_key)),
+            ];
+            for bounds in bounds {
+                let ranged: Vec<_> = reader.range(bounds).collect::<Result<_, _>>().unwrap();
+                let filtered: Vec<_> = expected
+                    .iter()
+                    .filter(|(key, _value)| std::ops::RangeBounds::contains(&bounds, key))
+                    .cloned()
+                    .collect();
+                assert_eq!(ranged, filtered, "range {:?}", bounds);
+                let rev_ranged: Vec<_> = reader.rev_range(bounds).collect::<Result<_, _>>().unwrap();
+                let rev_filtered: Vec<_> = filtered.into_iter().rev().collect();
+                assert_eq!(rev_ranged, rev_filtered, "rev range {:?}", bounds);
+            }
+        }
+    }
+}
+
+#[test]
+fn duplicate_keys_ranges() {
+    for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+        for (items_total, block_size) in [(17, 3), (40, 3), (60, 4)] {
+            let sketch = sketch::Tree::try_with_mode(items_total, block_size, sketch::FillStrategy::TopHeavy, mode).unwrap();
+            let items: Vec<_> = (0 .. items_total).map(|index| (index / 5, index)).collect();
+            let data = file::write(&sketch, 128, items.clone(), Cursor::new(Vec::new())).unwrap();
+            let mut reader: Reader<_, u64, u64> = Reader::open(data).unwrap();
+            let max_key = items_total / 5 + 1;
+            for lo in 0 ..= max_key {
+                for hi in lo ..= max_key {
+                    let bounds = [
+                        (Bound::Included(lo), Bound::Included(hi)),
+                        (Bound::Included(lo), Bound::Excluded(hi)),
+                        (Bound::Excluded(lo), Bound::Included(hi)),
+                        (Bound::Excluded(lo), Bound::Unbounded),
+                        (Bound::Unbounded, Bound::Excluded(hi)),
+                    ];
+                    for bounds in bounds {
+                        let filtered: Vec<_> = items
+                            .iter()
+                            .filter(|(key, _value)| std::ops::RangeBounds::contains(&bounds, key))
+                            .cloned()
+                            .collect();
+                        let ranged: Vec<_> = reader.range(bounds).collect::<Result<_, _>>().unwrap();
+                        assert_eq!(ranged, filtered, "{:?} range {:?}", mode, bounds);
+                        let rev_ranged: Vec<_> = reader.rev_range(bounds).collect::<Result<_, _>>().unwrap();
+                        let rev_filtered: Vec<_> = filtered.into_iter().rev().collect();
+                        assert_eq!(rev_ranged, rev_filtered, "{:?} rev range {:?}", mode, bounds);
+                    }
+                }
+                let found = reader.get(&lo).unwrap();
+                assert_eq!(found.map(|value| value / 5), items.iter().map(|&(key, _value)| key).find(|&key| key == lo));
+            }
+        }
+    }
+}
+
+#[test]
+fn rev_scan_tree17_4() {
+    check_rev_scan_all(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn rev_scan_tree22_3() {
+    check_rev_scan_all(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn rev_scan_tree1000_5() {
+    check_rev_scan_all(&sketch::Tree::new(1000, 5));
+}
+
+#[test]
+fn rev_scan_latest() {
+    let mut reader = make_reader(1000, 5);
+    let latest: Vec<_> = reader.rev_scan().take(3).collect::<Result<_, _>>().unwrap();
+    assert_eq!(latest, vec![(key(1998), value(1998)), (key(1996), value(1996)), (key(1994), value(1994))]);
+}
+
+#[test]
+fn rev_range_reads_each_block_once() {
+    let sketch = sketch::Tree::new(1000, 5);
+    let items = (0 .. 1000)
+        .map(|index| (key(index), value(index)));
+    let cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, Vec<u8>, Vec<u8>> = Reader::open(SeekLog { inner: cursor, seeks: Vec::new(), }).unwrap();
+    let lo = key(100);
+    let hi = key(900);
+    assert_eq!(reader.rev_range(lo .. hi).count(), 800);
+
+    let mut block_seeks = reader.into_inner().seeks.split_off(2);
+    let seeks_count = block_seeks.len();
+    block_seeks.sort_unstable();
+    block_seeks.dedup();
+    assert_eq!(block_seeks.len(), seeks_count);
+}
+
+fn check_rev_scan_all(sketch: &sketch::Tree) {
+    let items_total = sketch.items_total();
+    let mut reader = make_sketch_reader(sketch);
+    let scanned: Vec<_> = reader.rev_scan().collect::<Result<_, _>>().unwrap();
+    let expected: Vec<_> = (0 .. items_total)
+        .rev()
+        .map(|index| (key(index * 2), value(index * 2)))
+        .collect();
+    assert_eq!(scanned, expected);
+}
+
+fn check_scan_all(sketch: &sketch::Tree) {
+    let items_total = sketch.items_total();
+    let mut reader = make_sketch_reader(sketch);
+    let scanned: Vec<_> = reader.scan().collect::<Result<_, _>>().unwrap();
+    let expected: Vec<_> = (0 .. items_total)
+        .map(|index| (key(index * 2), value(index * 2)))
+        .collect();
+    assert_eq!(scanned, expected);
+}
+
+#[test]
+fn typed_items() {
+    let sketch = sketch::Tree::new(100, 4);
+    let items = (0 .. 100u64)
+        .map(|index| ((index / 10, format!("item{}", index % 10)), index * index));
+    let cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, (u64, String), u64> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.get(&(4, "item2".to_string())).unwrap(), Some(42 * 42));
+    assert_eq!(reader.get(&(4, "item".to_string())).unwrap(), None);
+    let ranged: Vec<_> = reader.range((3, String::new()) .. (3, "item3".to_string()))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        ranged,
+        vec![((3, "item0".to_string()), 900), ((3, "item1".to_string()), 961), ((3, "item2".to_string()), 1024)],
+    );
+}
+
+#[test]
+fn string_keys() {
+    let sketch = sketch::Tree::new(3, 2);
+    let items = vec![("apple", ()), ("banana", ()), ("cherry", ())];
+    let cursor = file::write(&sketch, 64, items, Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, String, ()> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.get("banana").unwrap(), Some(()));
+    assert_eq!(reader.get("blueberry").unwrap(), None);
+}
+
+#[test]
+fn open_codec_mismatch() {
+    let sketch = sketch::Tree::new(3, 2);
+    let items = vec![(1u64, "a"), (2, "b"), (3, "c")];
+    let cursor = file::write(&sketch, 64, items, Cursor::new(Vec::new())).unwrap();
+    match Reader::<_, u32, String>::open(cursor.clone()) {
+        Err(Error::KeyCodecMismatch { expected, found, }) =>
+            assert_eq!((expected.as_str(), found.as_str()), ("u32", "u64")),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+    match Reader::<_, u64, Vec<u8>>::open(cursor.clone()) {
+        Err(Error::ValueCodecMismatch { expected, found, }) =>
+            assert_eq!((expected.as_str(), found.as_str()), ("bytes", "string")),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+    let mut reader = Reader::<_, u64, String>::open(cursor).unwrap();
+    assert_eq!(reader.sketch().levels(), sketch.levels());
+    assert_eq!(reader.get(&2).unwrap(), Some("b".to_string()));
+}
+
+#[test]
+fn overflow_values() {
+    for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+        let sketch = sketch::Tree::try_with_mode(40, 3, sketch::FillStrategy::TopHeavy, mode).unwrap();
+        let items: Vec<_> = (0 .. 40u64).map(|index| (index * 2, overflow_value(index))).collect();
+        let cursor = file::write(&sketch, 256, items.clone(), Cursor::new(Vec::new())).unwrap();
+        let mut reader: Reader<_, u64, Vec<u8>> = Reader::open(cursor).unwrap();
+
+        let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, items);
+        let rev_ranged: Vec<_> = reader.rev_range(10 ..= 20).map(Result::unwrap).collect();
+        assert_eq!(rev_ranged, items[5 ..= 10].iter().rev().cloned().collect::<Vec<_>>());
+        for (key, value) in &items {
+            assert_eq!(reader.get(key).unwrap().as_ref(), Some(value));
+
+            let mut encoded = Vec::new();
+            codec::encode(value, &mut encoded);
+            let mut value_reader = reader.get_value_reader(key).unwrap().unwrap();
+            assert_eq!(value_reader.value_len(), value.len() as u64);
+            assert_eq!(value_reader.is_overflow(), encoded.len() > block::max_inline_value_len(256));
+            let mut streamed = Vec::new();
+            let mut chunk = [0; 7];
+            loop {
+                match value_reader.read(&mut chunk).unwrap() {
+                    0 =>
+                        break,
+                    len =>
+                        streamed.extend_from_slice(&chunk[.. len]),
+                }
+            }
+            assert_eq!(&streamed, value);
+        }
+        assert!(reader.get_value_reader(&1).unwrap().is_none());
+    }
+}
+
+fn overflow_value(index: u64) -> Vec<u8> {
+    let len = if index.is_multiple_of(3) { 100 + index as usize * 37 } else { index as usize };
+    (0 .. len).map(|offset| (offset as u64 * 31 + index) as u8).collect()
+}
+
+#[test]
+fn open_unsupported_version() {
+    let sketch = sketch::Tree::new(3, 2);
+    let items = vec![(1u64, ()), (2, ()), (3, ())];
+    let mut data = file::write(&sketch, 64, items, Cursor::new(Vec::new())).unwrap().into_inner();
+    let tail_version = data.len() - 16;
+    for version in 1 .. trailer::FORMAT_VERSION {
+        data[tail_version .. tail_version + 4].copy_from_slice(&version.to_le_bytes());
+        match Reader::<_, u64, ()>::open(Cursor::new(data.clone())) {
+            Err(Error::Trailer(trailer::Error::UnsupportedVersion { version: found, supported: trailer::FORMAT_VERSION, })) if found == version =>
+                (),
+            other =>
+                panic!("unexpected result: {:?}", other.err()),
+        }
+    }
+    data[8 .. 12].copy_from_slice(&7u32.to_le_bytes());
+    match Reader::<_, u64, ()>::open(Cursor::new(data)) {
+        Err(Error::Header(trailer::Error::UnsupportedVersion { version: 7, supported: trailer::FORMAT_VERSION, })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+#[test]
+fn open_invalid_magic() {
+    let mut data = vec![0; 64];
+    data[.. 8].copy_from_slice(&trailer::MAGIC);
+    data[8 .. 12].copy_from_slice(&trailer::FORMAT_VERSION.to_le_bytes());
+    match Reader::<_, u64, ()>::open(Cursor::new(data)) {
+        Err(Error::Trailer(trailer::Error::InvalidMagic { magic: [0, 0, 0, 0, 0, 0, 0, 0], })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+#[test]
+fn block_corruption() {
+    let sketch = sketch::Tree::new(17, 3);
+    let items = (0 .. 17).map(|index| (key(index), value(index)));
+    let mut cursor = file::write(&sketch, 96, items, Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut cursor).unwrap();
+    let mut data = cursor.into_inner();
+    let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+    data[blocks_offsets[1][1] as usize + 10] ^= 0x40;
+
+    let mut reader: Reader<_, Vec<u8>, Vec<u8>> = Reader::open(Cursor::new(data)).unwrap();
+    assert_eq!(reader.get(&key(0)).unwrap(), Some(value(0)));
+    match reader.get(&key(10)) {
+        Err(Error::BlockChecksumMismatch { level_index: 1, block_index: 1, .. }) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other),
+    }
+    let mut scan = reader.scan();
+    for index in 0 .. 9 {
+        assert_eq!(scan.next().unwrap().unwrap(), (key(index), value(index)));
+    }
+    match scan.next() {
+        Some(Err(Error::BlockChecksumMismatch { level_index: 1, block_index: 1, .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn trailer_corruption() {
+    let sketch = sketch::Tree::new(3, 2);
+    let items = vec![(1u64, ()), (2, ()), (3, ())];
+    let data = file::write(&sketch, 64, items, Cursor::new(Vec::new())).unwrap().into_inner();
+
+    let mut corrupted = data.clone();
+    let levels_count_offset = corrupted.len() - 24 - 8 * 6;
+    corrupted[levels_count_offset] ^= 1;
+    match Reader::<_, u64, ()>::open(Cursor::new(corrupted)) {
+        Err(Error::Trailer(trailer::Error::ChecksumMismatch { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+
+    let mut truncated = data;
+    truncated.truncate(truncated.len() - 5);
+    match Reader::<_, u64, ()>::open(Cursor::new(truncated)) {
+        Err(Error::Trailer(trailer::Error::InvalidMagic { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+fn check_get_all(sketch: &sketch::Tree) {
+    let items_total = sketch.items_total();
+    let mut reader = make_sketch_reader(sketch);
+    for index in 0 .. items_total {
+        assert_eq!(reader.get(&key(index * 2)).unwrap(), Some(value(index * 2)));
+        assert_eq!(reader.get(&key(index * 2 + 1)).unwrap(), None);
+    }
+    assert_eq!(reader.get(&b""[..]).unwrap(), None);
+    assert_eq!(reader.get(&b"k"[..]).unwrap(), None);
+    assert_eq!(reader.get(&b"z"[..]).unwrap(), None);
+}
+
+fn key(index: u64) -> Vec<u8> {
+    format!("k{:04}", index).into_bytes()
+}
+
+fn value(index: u64) -> Vec<u8> {
+    format!("v{}", index).into_bytes()
+}
+
+fn make_reader(items_total: u64, block_size: usize) -> Reader<source::SingleFile<Cursor<Vec<u8>>>, Vec<u8>, Vec<u8>> {
+    make_sketch_reader(&sketch::Tree::new(items_total, block_size))
+}
+
+fn make_sketch_reader(sketch: &sketch::Tree) -> Reader<source::SingleFile<Cursor<Vec<u8>>>, Vec<u8>, Vec<u8>> {
+    let items_total = sketch.items_total();
+    let items = (0 .. items_total)
+        .map(|index| (key(index * 2), value(index * 2)));
+    let cursor = file::write(sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    Reader::open(cursor).unwrap()
+}
+
+struct SeekLog<S> {
+    inner: S,
+    seeks: Vec<u64>,
+}
+
+impl<S> Read for SeekLog<S> where S: Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S> Seek for SeekLog<S> where S: Seek {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if let SeekFrom::Start(offset) = pos {
+            self.seeks.push(offset);
+        }
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    cmp::min,
+    io::{
+        self,
+        Read,
+    },
+};
+
+use crate::{
+    writer::value_log,
+    block,
+    codec,
+    crc32c,
+};
+
+use super::{
+    source::BlockSource,
+    Error,
+    Reader,
+};
+
+pub struct ValueReader<'a, S, K, V> {
+    reader: &'a mut Reader<S, K, V>,
+    stream: Stream,
+    value_len: u64,
+    chunk: Vec<u8>,
+    chunk_offset: usize,
+    prefix: Vec<u8>,
+    prefix_len: usize,
+}
+
+pub(super) enum Source {
+    Inline(Vec<u8>),
+    Overflow(block::OverflowRef),
+    Log(block::LogRef),
+}
+
+enum Stream {
+    Inline,
+    Overflow {
+        overflow: block::OverflowRef,
+        pages_count: u64,
+        next_page_index: u64,
+    },
+    Log {
+        log_ref: block::LogRef,
+        read: u64,
+        checksum: u32,
+    },
+}
+
+impl Source {
+    pub(super) fn new(value: block::Value) -> Source {
+        match value {
+            block::Value::Inline(value) =>
+                Source::Inline(value.to_vec()),
+            block::Value::Overflow(overflow) =>
+                Source::Overflow(overflow),
+            block::Value::Log(log_ref) =>
+                Source::Log(log_ref),
+        }
+    }
+}
+
+impl<'a, S, K, V> ValueReader<'a, S, K, V> {
+    pub(super) fn new(reader: &'a mut Reader<S, K, V>, source: Source, length_prefixed: bool) -> ValueReader<'a, S, K, V> {
+        let (stream, encoded_len, chunk) = match source {
+            Source::Inline(value) =>
+                (Stream::Inline, value.len() as u64, value),
+            Source::Overflow(overflow) => {
+                let pages_count = block::overflow_pages_count(reader.page_size, overflow.value_len);
+                (Stream::Overflow { overflow, pages_count, next_page_index: 0, }, overflow.value_len, Vec::new())
+            },
+            Source::Log(log_ref) =>
+                (Stream::Log { log_ref, read: 0, checksum: 0, }, log_ref.value_len, Vec::new()),
+        };
+        let prefix_len = if length_prefixed { prefix_len(encoded_len) } else { 0 };
+        ValueReader {
+            reader,
+            stream,
+            value_len: encoded_len.saturating_sub(prefix_len as u64),
+            chunk,
+            chunk_offset: 0,
+            prefix: Vec::with_capacity(prefix_len),
+            prefix_len,
+        }
+    }
+
+    pub fn value_len(&self) -> u64 {
+        self.value_len
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        matches!(self.stream, Stream::Overflow { .. })
+    }
+
+    pub fn is_logged(&self) -> bool {
+        matches!(self.stream, Stream::Log { .. })
+    }
+}
+
+impl<'a, S, K, V> ValueReader<'a, S, K, V> where S: BlockSource, K: codec::Decode + Ord, V: codec::Decode {
+    fn next_chunk(&mut self) -> Result<bool, Error> {
+        match &mut self.stream {
+            Stream::Overflow { overflow, pages_count, next_page_index, } if *next_page_index < *pages_count => {
+                self.reader.read_overflow_page(*overflow, *next_page_index, &mut self.chunk)?;
+                *next_page_index += 1;
+            },
+            Stream::Log { log_ref, read, checksum, } if *read < log_ref.value_len => {
+                // the record checksum is fetched along with the last chunk and checked once the whole value went by
+                let len = min(self.reader.page_size as u64, log_ref.value_len - *read) as usize;
+                let last = *read + len as u64 == log_ref.value_len;
+                let record_len = if last { len + value_log::RECORD_CHECKSUM_SIZE } else { len };
+                self.chunk.resize(record_len, 0);
+                self.reader.read_value_log(log_ref.offset + *read, &mut self.chunk)?;
+                let expected = self.chunk.split_off(len);
+                *checksum = crc32c::extend(*checksum, &self.chunk);
+                *read += len as u64;
+                if last {
+                    let expected = u32::from_le_bytes([expected[0], expected[1], expected[2], expected[3]]);
+                    if *checksum != expected {
+                        return Err(Error::ValueLogChecksumMismatch { offset: log_ref.offset, expected, actual: *checksum, });
+                    }
+                }
+            },
+            _ =>
+                return Ok(false),
+        }
+        self.chunk_offset = 0;
+        Ok(true)
+    }
+}
+
+impl<'a, S, K, V> ValueReader<'a, S, K, V> {
+    fn check_prefix(&self) -> Result<(), Error> {
+        let mut source = &self.prefix[..];
+        let prefix_len = codec::decode_len(&mut source)
+            .map_err(Error::StreamedValuePrefix)? as u64;
+        if !source.is_empty() || prefix_len != self.value_len {
+            return Err(Error::StreamedValueLenMismatch { value_len: self.value_len, prefix_len, });
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S, K, V> Read for ValueReader<'a, S, K, V> where S: BlockSource, K: codec::Decode + Ord, V: codec::Decode {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.chunk_offset == self.chunk.len() && !self.next_chunk().map_err(into_io_error)? {
+                return Ok(0);
+            }
+            if self.prefix.len() == self.prefix_len {
+                break;
+            }
+            // the length prefix may span chunks, it is collected whole and checked against the value length
+            let len = min(self.prefix_len - self.prefix.len(), self.chunk.len() - self.chunk_offset);
+            self.prefix.extend_from_slice(&self.chunk[self.chunk_offset .. self.chunk_offset + len]);
+            self.chunk_offset += len;
+            if self.prefix.len() == self.prefix_len {
+                self.check_prefix().map_err(into_io_error)?;
+            }
+        }
+        let len = min(buf.len(), self.chunk.len() - self.chunk_offset);
+        buf[.. len].copy_from_slice(&self.chunk[self.chunk_offset .. self.chunk_offset + len]);
+        self.chunk_offset += len;
+        Ok(len)
+    }
+}
+
+// the size of the varint `len` such that `len` payload bytes follow it in `encoded_len` bytes
+fn prefix_len(encoded_len: u64) -> usize {
+    (1 ..= 10)
+        .find(|&size| size as u64 <= encoded_len && codec::len_prefix_size(encoded_len - size as u64) == size)
+        .unwrap_or(1)
+}
+
+fn into_io_error(error: Error) -> io::Error {
+    match error {
+        Error::OverflowSeek { error, .. } |
+        Error::OverflowRead { error, .. } |
+        Error::ValueLogSeek { error, .. } |
+        Error::ValueLogRead { error, .. } =>
+            error,
+        error =>
+            io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)),
+    }
+}
@@ -1,51 +1,675 @@
-use std::cmp::min;
+use std::cmp::{
+    max,
+    min,
+};
+
+pub mod budget;
+
+pub use budget::{
+    Budget,
+    Footprint,
+};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Level {
     pub index: usize,
-    pub blocks_count: usize,
-    pub items_count: usize,
+    pub blocks_count: u64,
+    pub items_count: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockFill {
+    Packed,
+    Even,
+    Explicit,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BlockSizes {
+    Uniform(usize),
+    LeafInner {
+        leaf: usize,
+        inner: usize,
+    },
+    // from the root down, levels deeper than the list reuse its last size
+    PerLevel(Vec<usize>),
+}
+
+impl BlockSizes {
+    pub fn level_block_size(&self, level_index: usize, levels_count: usize) -> usize {
+        match self {
+            BlockSizes::Uniform(block_size) =>
+                *block_size,
+            BlockSizes::LeafInner { leaf, .. } if level_index + 1 == levels_count =>
+                *leaf,
+            BlockSizes::LeafInner { inner, .. } =>
+                *inner,
+            BlockSizes::PerLevel(block_sizes) =>
+                block_sizes.get(level_index)
+                    .or(block_sizes.last())
+                    .copied()
+                    .unwrap_or(0),
+        }
+    }
+
+    fn resolve(&self, levels_count: usize) -> Vec<usize> {
+        (0 .. levels_count)
+            .map(|level_index| self.level_block_size(level_index, levels_count))
+            .collect()
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let block_sizes = match self {
+            BlockSizes::Uniform(block_size) =>
+                std::slice::from_ref(block_size),
+            BlockSizes::LeafInner { leaf, inner, } =>
+                return [*leaf, *inner].iter().try_for_each(|&block_size| check_block_size(block_size)),
+            BlockSizes::PerLevel(block_sizes) if block_sizes.is_empty() =>
+                return Err(Error::NoBlockSizes),
+            BlockSizes::PerLevel(block_sizes) =>
+                block_sizes,
+        };
+        block_sizes.iter().try_for_each(|&block_size| check_block_size(block_size))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MinFill {
+    pub numerator: usize,
+    pub denominator: usize,
+}
+
+impl MinFill {
+    pub const TWO_THIRDS: MinFill = MinFill { numerator: 2, denominator: 3, };
+}
+
+// Every strategy builds a tree of the lowest height that holds all the items, they only differ in how the
+// items spread over the blocks: top-heavy packs blocks full and leaves the remainder in the last one, balanced
+// moves items down to keep every bottom block at least `min_fill` full, min-height spreads each level evenly.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum FillStrategy {
+    #[default]
+    TopHeavy,
+    Balanced {
+        min_fill: MinFill,
+    },
+    MinHeight,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Mode {
+    #[default]
+    BTree,
+    BPlus,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockPosition {
+    pub level_index: usize,
+    pub block_index: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ItemPosition {
+    pub level_index: usize,
+    pub block_index: usize,
+    pub item_index: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    InvalidBlockSize {
+        block_size: usize,
+    },
+    NoBlockSizes,
+    BlocksCountOverflow {
+        level_index: usize,
+        blocks_count: u64,
+    },
+    InvalidMinFill {
+        min_fill: MinFill,
+    },
+    UnsupportedFill {
+        fill: FillStrategy,
+        mode: Mode,
+    },
+    UnexpectedLevelIndex {
+        level_index: usize,
+        expected: usize,
+    },
+    InvalidRootBlocksCount {
+        blocks_count: u64,
+    },
+    InvalidLevelItemsCount {
+        level_index: usize,
+        blocks_count: u64,
+        items_count: u64,
+    },
+    TooManyChildBlocks {
+        level_index: usize,
+        blocks_count: u64,
+        parent_items_count: u64,
+    },
+    TooFewChildBlocks {
+        level_index: usize,
+        blocks_count: u64,
+        parent_items_count: u64,
+    },
+    ItemsTotalMismatch {
+        items_total: u64,
+        levels_items_total: u64,
+    },
+    BudgetTooSmall {
+        budget: usize,
+        max_footprint: usize,
+    },
+    FootprintExceedsMax {
+        item_index: u64,
+        footprint: usize,
+        max_footprint: usize,
+    },
+    MissingBlocksItemsCounts,
+    BlocksItemsCountsMismatch {
+        level_index: usize,
+        blocks_count: u64,
+        counts_count: usize,
+    },
+    EmptyBlock {
+        level_index: usize,
+        block_index: usize,
+    },
 }
 
+#[derive(Clone, PartialEq, Debug)]
 pub struct Tree {
     levels: Vec<Level>,
-    block_size: usize,
-    items_total: usize,
+    block_sizes: BlockSizes,
+    level_block_sizes: Vec<usize>,
+    block_fill: BlockFill,
+    mode: Mode,
+    items_total: u64,
+    // items before every block of every level, only kept for explicit block fill
+    blocks_bounds: Vec<Vec<u64>>,
 }
 
 impl Tree {
-    pub fn new(items_total: usize, block_size: usize) -> Tree {
-        let mut blocks_count = (items_total as f64 / block_size as f64).ceil() as usize;
-        let mut levels = Vec::new();
-        let mut items_remain = items_total;
-        for layer in 0 .. {
-            if items_remain == 0 {
-                break;
+    pub fn new(items_total: u64, block_size: usize) -> Tree {
+        match Tree::try_new(items_total, block_size) {
+            Ok(tree) =>
+                tree,
+            Err(error) =>
+                panic!("invalid tree sketch for {items_total} items with block size {block_size}: {error:?}"),
+        }
+    }
+
+    pub fn try_new(items_total: u64, block_size: usize) -> Result<Tree, Error> {
+        Tree::try_with_fill(items_total, block_size, FillStrategy::TopHeavy)
+    }
+
+    pub fn try_with_fill(items_total: u64, block_size: usize, fill: FillStrategy) -> Result<Tree, Error> {
+        Tree::try_with_mode(items_total, block_size, fill, Mode::BTree)
+    }
+
+    pub fn try_with_mode(items_total: u64, block_size: usize, fill: FillStrategy, mode: Mode) -> Result<Tree, Error> {
+        Tree::try_with_sizes(items_total, BlockSizes::Uniform(block_size), fill, mode)
+    }
+
+    pub fn try_with_sizes(items_total: u64, block_sizes: BlockSizes, fill: FillStrategy, mode: Mode) -> Result<Tree, Error> {
+        block_sizes.validate()?;
+        if let FillStrategy::Balanced { min_fill, } = fill {
+            if min_fill.denominator == 0 || min_fill.numerator > min_fill.denominator {
+                return Err(Error::InvalidMinFill { min_fill, });
             }
-            let layer_max_blocks = (block_size as f64).powi(layer as i32) as usize;
-            let layer_blocks = min(layer_max_blocks, blocks_count);
-            let layer_max_items = layer_blocks * block_size;
-            let layer_items = min(layer_max_items, items_remain);
+        }
+        let tree = match (mode, fill) {
+            (Mode::BTree, _) =>
+                Tree::btree(items_total, block_sizes, fill),
+            // the block counts of every separators level follow from the level below, so there is no fill to trade
+            (Mode::BPlus, FillStrategy::TopHeavy | FillStrategy::MinHeight) =>
+                Tree::bplus(items_total, block_sizes, fill),
+            (Mode::BPlus, FillStrategy::Balanced { .. }) =>
+                return Err(Error::UnsupportedFill { fill, mode, }),
+        };
+        tree.levels.iter().try_for_each(check_blocks_count)?;
+        Ok(tree)
+    }
+
+    // whatever the fill, the tree has the lowest height whose full levels hold every item
+    fn btree(items_total: u64, block_sizes: BlockSizes, fill: FillStrategy) -> Tree {
+        let mut levels_count = 0;
+        while btree_capacity(&block_sizes.resolve(levels_count)) < items_total {
+            levels_count += 1;
+        }
+        let level_block_sizes = block_sizes.resolve(levels_count);
+        let mut levels = Vec::with_capacity(levels_count);
+        let mut items_remain = items_total;
+        let mut layer_max_blocks: u64 = 1;
+        for (layer, &block_size) in level_block_sizes.iter().enumerate() {
+            // every deeper layer keeps at least one item, so the leaf level is never left empty
+            let items_reserved = (levels_count - layer - 1) as u64;
+            let layer_items = min(layer_max_blocks.saturating_mul(block_size as u64), items_remain - items_reserved);
             levels.push(Level {
                 index: layer,
-                blocks_count: layer_blocks,
+                blocks_count: layer_items.div_ceil(block_size as u64),
                 items_count: layer_items,
             });
-            blocks_count -= layer_blocks;
             items_remain -= layer_items;
+            layer_max_blocks = layer_items;
+        }
+        let block_fill = match fill {
+            FillStrategy::TopHeavy =>
+                BlockFill::Packed,
+            FillStrategy::MinHeight =>
+                BlockFill::Even,
+            FillStrategy::Balanced { min_fill, } => {
+                let min_items: Vec<_> = level_block_sizes
+                    .iter()
+                    .map(|&block_size| {
+                        let min_items = (block_size as u128 * min_fill.numerator as u128).div_ceil(min_fill.denominator as u128);
+                        max(1, min_items as u64)
+                    })
+                    .collect();
+                rebalance(&mut levels, items_total, &level_block_sizes, &min_items);
+                BlockFill::Even
+            },
+        };
+        Tree { levels, block_sizes, level_block_sizes, block_fill, mode: Mode::BTree, items_total, blocks_bounds: Vec::new(), }
+    }
+
+    // all items go to the bottom level, every upper level holds one separator per child block
+    fn bplus(items_total: u64, block_sizes: BlockSizes, fill: FillStrategy) -> Tree {
+        let mut levels_count = 0;
+        let (levels, level_block_sizes) = loop {
+            let level_block_sizes = block_sizes.resolve(levels_count);
+            if let Some(levels) = bplus_levels(items_total, &level_block_sizes) {
+                break (levels, level_block_sizes);
+            }
+            levels_count += 1;
+        };
+        let block_fill = match fill {
+            FillStrategy::MinHeight =>
+                BlockFill::Even,
+            FillStrategy::TopHeavy | FillStrategy::Balanced { .. } =>
+                BlockFill::Packed,
+        };
+        Tree { levels, block_sizes, level_block_sizes, block_fill, mode: Mode::BPlus, items_total, blocks_bounds: Vec::new(), }
+    }
+
+    pub fn try_with_budget<I>(items_total: u64, budget: Budget, mode: Mode, footprints: I) -> Result<Tree, Error> where I: IntoIterator<Item = Footprint> {
+        let blocks_items_counts = match mode {
+            Mode::BTree =>
+                budget::btree(items_total, budget, footprints)?,
+            Mode::BPlus =>
+                budget::bplus(items_total, budget, footprints)?,
+        };
+        let levels = blocks_items_counts
+            .iter()
+            .enumerate()
+            .map(|(index, counts)| Level { index, blocks_count: counts.len() as u64, items_count: counts.iter().map(|&count| count as u64).sum(), })
+            .collect();
+        Tree::restore_explicit(items_total, mode, levels, blocks_items_counts)
+    }
+
+    pub fn restore(items_total: u64, block_sizes: BlockSizes, block_fill: BlockFill, mode: Mode, levels: Vec<Level>) -> Result<Tree, Error> {
+        if block_fill == BlockFill::Explicit {
+            return Err(Error::MissingBlocksItemsCounts);
+        }
+        block_sizes.validate()?;
+        let level_block_sizes = block_sizes.resolve(levels.len());
+        check_levels(items_total, mode, &levels, |level| {
+            let block_size = level_block_sizes[level.index];
+            let max_items_count = level.blocks_count.checked_mul(block_size as u64);
+            let min_items_count = match block_fill {
+                BlockFill::Packed =>
+                    (level.blocks_count.saturating_sub(1)).saturating_mul(block_size as u64).saturating_add(1),
+                BlockFill::Even | BlockFill::Explicit =>
+                    level.blocks_count,
+            };
+            if level.items_count < min_items_count || max_items_count.is_some_and(|max| level.items_count > max) {
+                return Err(Error::InvalidLevelItemsCount {
+                    level_index: level.index,
+                    blocks_count: level.blocks_count,
+                    items_count: level.items_count,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(Tree { levels, block_sizes, level_block_sizes, block_fill, mode, items_total, blocks_bounds: Vec::new(), })
+    }
+
+    pub fn restore_explicit(items_total: u64, mode: Mode, levels: Vec<Level>, blocks_items_counts: Vec<Vec<usize>>) -> Result<Tree, Error> {
+        let mut blocks_bounds = Vec::with_capacity(levels.len());
+        check_levels(items_total, mode, &levels, |level| {
+            let counts: &[usize] = blocks_items_counts.get(level.index).map_or(&[], Vec::as_slice);
+            if counts.len() as u64 != level.blocks_count {
+                return Err(Error::BlocksItemsCountsMismatch {
+                    level_index: level.index,
+                    blocks_count: level.blocks_count,
+                    counts_count: counts.len(),
+                });
+            }
+            let mut bounds = Vec::with_capacity(counts.len() + 1);
+            let mut items_before: u64 = 0;
+            for (block_index, &items_count) in counts.iter().enumerate() {
+                if items_count == 0 {
+                    return Err(Error::EmptyBlock { level_index: level.index, block_index, });
+                }
+                bounds.push(items_before);
+                items_before = items_before.saturating_add(items_count as u64);
+            }
+            if items_before != level.items_count {
+                return Err(Error::InvalidLevelItemsCount {
+                    level_index: level.index,
+                    blocks_count: level.blocks_count,
+                    items_count: level.items_count,
+                });
+            }
+            bounds.push(items_before);
+            blocks_bounds.push(bounds);
+            Ok(())
+        })?;
+        if blocks_items_counts.len() > levels.len() {
+            return Err(Error::BlocksItemsCountsMismatch {
+                level_index: levels.len(),
+                blocks_count: 0,
+                counts_count: blocks_items_counts[levels.len()].len(),
+            });
         }
-        Tree { levels, block_size, items_total, }
+        // the largest block of every level stands for its capacity
+        let level_block_sizes: Vec<usize> = blocks_items_counts
+            .iter()
+            .map(|counts| counts.iter().copied().max().unwrap_or(0))
+            .collect();
+        Ok(Tree {
+            levels,
+            block_sizes: BlockSizes::PerLevel(level_block_sizes.clone()),
+            level_block_sizes,
+            block_fill: BlockFill::Explicit,
+            mode,
+            items_total,
+            blocks_bounds,
+        })
     }
 
     pub fn levels(&self) -> &[Level] {
         &self.levels
     }
 
-    pub fn block_size(&self) -> usize {
-        self.block_size
+    pub fn block_sizes(&self) -> &BlockSizes {
+        &self.block_sizes
+    }
+
+    pub fn level_block_size(&self, level_index: usize) -> Option<usize> {
+        self.level_block_sizes.get(level_index).copied()
+    }
+
+    pub fn block_fill(&self) -> BlockFill {
+        self.block_fill
     }
 
-    pub fn items_total(&self) -> usize {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn items_total(&self) -> u64 {
         self.items_total
     }
+
+    pub fn blocks_items_counts(&self) -> Vec<Vec<usize>> {
+        self.blocks_bounds
+            .iter()
+            .map(|bounds| bounds.windows(2).map(|pair| (pair[1] - pair[0]) as usize).collect())
+            .collect()
+    }
+
+    pub fn leaf_level_index(&self) -> Option<usize> {
+        self.levels.len().checked_sub(1)
+    }
+
+    pub fn is_separators_level(&self, level_index: usize) -> bool {
+        self.mode == Mode::BPlus && level_index + 1 < self.levels.len()
+    }
+
+    pub fn block_items_count(&self, block: BlockPosition) -> Option<usize> {
+        let level = self.levels.get(block.level_index)?;
+        if block.block_index as u64 >= level.blocks_count {
+            return None;
+        }
+        let items_before = self.block_items_before(block)?;
+        let items_after = self.block_items_before(BlockPosition { block_index: block.block_index + 1, ..block })?;
+        Some((items_after - items_before) as usize)
+    }
+
+    pub fn block_items_before(&self, block: BlockPosition) -> Option<u64> {
+        let level = self.levels.get(block.level_index)?;
+        let block_index = block.block_index as u64;
+        if block_index >= level.blocks_count {
+            return Some(level.items_count);
+        }
+        match self.block_fill {
+            BlockFill::Packed =>
+                Some(min(level.items_count, block_index * self.level_block_sizes[block.level_index] as u64)),
+            BlockFill::Even => {
+                let base = level.items_count / level.blocks_count;
+                let larger_blocks = level.items_count % level.blocks_count;
+                Some(block_index * base + min(block_index, larger_blocks))
+            },
+            BlockFill::Explicit =>
+                Some(self.blocks_bounds[block.level_index][block.block_index]),
+        }
+    }
+
+    pub fn child_block(&self, item: ItemPosition) -> Option<BlockPosition> {
+        let level_item_index = self.level_item_index(item)?;
+        let child_level = self.levels.get(item.level_index + 1)?;
+        if level_item_index < child_level.blocks_count {
+            // block counts are checked to fit in usize when the sketch is made
+            Some(BlockPosition { level_index: child_level.index, block_index: level_item_index as usize, })
+        } else {
+            None
+        }
+    }
+
+    pub fn parent_item(&self, block: BlockPosition) -> Option<ItemPosition> {
+        self.block_items_count(block)?;
+        let level_index = block.level_index.checked_sub(1)?;
+        Some(self.level_item(level_index, block.block_index as u64))
+    }
+
+    pub fn rank(&self, item: ItemPosition) -> Option<u64> {
+        let level_item_index = self.level_item_index(item)?;
+        let mut rank = level_item_index;
+        // every ancestor level contributes the items preceding the ancestor item
+        let mut ancestor = item;
+        while ancestor.level_index > 0 {
+            rank += ancestor.block_index as u64;
+            ancestor = self.level_item(ancestor.level_index - 1, ancestor.block_index as u64);
+        }
+        // every descendant level contributes the items of the subtrees up to the item
+        let mut blocks_before = level_item_index + 1;
+        for level in &self.levels[item.level_index + 1 ..] {
+            let block_index = usize::try_from(blocks_before).unwrap_or(usize::MAX);
+            blocks_before = self.block_items_before(BlockPosition { level_index: level.index, block_index, })?;
+            rank += blocks_before;
+        }
+        Some(rank)
+    }
+
+    pub fn item_at_rank(&self, rank: u64) -> Option<ItemPosition> {
+        let levels_items_total: u64 = self.levels.iter().map(|level| level.items_count).sum();
+        if rank >= levels_items_total {
+            return None;
+        }
+        let mut block = BlockPosition { level_index: 0, block_index: 0, };
+        loop {
+            let items_count = self.block_items_count(block)?;
+            let mut lo = 0;
+            let mut hi = items_count;
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                let item = ItemPosition { level_index: block.level_index, block_index: block.block_index, item_index: mid, };
+                match self.rank(item)? {
+                    item_rank if item_rank == rank =>
+                        return Some(item),
+                    item_rank if item_rank < rank =>
+                        lo = mid + 1,
+                    _ =>
+                        hi = mid,
+                }
+            }
+            block = self.child_block(ItemPosition { level_index: block.level_index, block_index: block.block_index, item_index: lo, })?;
+        }
+    }
+
+    fn level_item_index(&self, item: ItemPosition) -> Option<u64> {
+        let block = BlockPosition { level_index: item.level_index, block_index: item.block_index, };
+        if item.item_index < self.block_items_count(block)? {
+            Some(self.block_items_before(block)? + item.item_index as u64)
+        } else {
+            None
+        }
+    }
+
+    fn level_item(&self, level_index: usize, level_item_index: u64) -> ItemPosition {
+        let (block_index, item_index) = match self.block_fill {
+            BlockFill::Packed => {
+                let block_size = self.level_block_sizes[level_index] as u64;
+                (level_item_index / block_size, level_item_index % block_size)
+            },
+            BlockFill::Even => {
+                let level = &self.levels[level_index];
+                let base = level.items_count / level.blocks_count;
+                let larger_blocks = level.items_count % level.blocks_count;
+                let larger_items = larger_blocks * (base + 1);
+                if level_item_index < larger_items {
+                    (level_item_index / (base + 1), level_item_index % (base + 1))
+                } else {
+                    let rest = level_item_index - larger_items;
+                    (larger_blocks + rest / base, rest % base)
+                }
+            },
+            BlockFill::Explicit => {
+                let bounds = &self.blocks_bounds[level_index];
+                let block_index = bounds.partition_point(|&items_before| items_before <= level_item_index) - 1;
+                (block_index as u64, level_item_index - bounds[block_index])
+            },
+        };
+        ItemPosition { level_index, block_index: block_index as usize, item_index: item_index as usize, }
+    }
+}
+
+fn check_levels<F>(items_total: u64, mode: Mode, levels: &[Level], mut check_items: F) -> Result<(), Error>
+where F: FnMut(&Level) -> Result<(), Error>,
+{
+    let mut parent_items_count = 1;
+    let mut levels_items_total: u64 = 0;
+    for (expected, level) in levels.iter().enumerate() {
+        if level.index != expected {
+            return Err(Error::UnexpectedLevelIndex { level_index: level.index, expected, });
+        }
+        if level.index == 0 && level.blocks_count != 1 {
+            return Err(Error::InvalidRootBlocksCount { blocks_count: level.blocks_count, });
+        }
+        if level.blocks_count > parent_items_count {
+            return Err(Error::TooManyChildBlocks {
+                level_index: level.index,
+                blocks_count: level.blocks_count,
+                parent_items_count,
+            });
+        }
+        if mode == Mode::BPlus && level.index > 0 && level.blocks_count < parent_items_count {
+            return Err(Error::TooFewChildBlocks {
+                level_index: level.index,
+                blocks_count: level.blocks_count,
+                parent_items_count,
+            });
+        }
+        check_blocks_count(level)?;
+        check_items(level)?;
+        parent_items_count = level.items_count;
+        levels_items_total = match mode {
+            Mode::BTree =>
+                levels_items_total.saturating_add(level.items_count),
+            Mode::BPlus =>
+                level.items_count,
+        };
+    }
+    if levels_items_total != items_total {
+        return Err(Error::ItemsTotalMismatch { items_total, levels_items_total, });
+    }
+    Ok(())
+}
+
+// blocks are addressed by usize positions, item counts may go beyond it on narrow targets
+fn check_blocks_count(level: &Level) -> Result<(), Error> {
+    if usize::try_from(level.blocks_count).is_err() {
+        return Err(Error::BlocksCountOverflow { level_index: level.index, blocks_count: level.blocks_count, });
+    }
+    Ok(())
+}
+
+fn check_block_size(block_size: usize) -> Result<(), Error> {
+    if block_size < 2 {
+        Err(Error::InvalidBlockSize { block_size, })
+    } else {
+        Ok(())
+    }
+}
+
+fn btree_capacity(level_block_sizes: &[usize]) -> u64 {
+    let mut capacity: u64 = 0;
+    let mut layer_max_blocks: u64 = 1;
+    for &block_size in level_block_sizes {
+        let layer_max_items = layer_max_blocks.saturating_mul(block_size as u64);
+        capacity = capacity.saturating_add(layer_max_items);
+        layer_max_blocks = layer_max_items;
+    }
+    capacity
+}
+
+// builds the levels bottom up, succeeds only when exactly the root block is left on top
+fn bplus_levels(items_total: u64, level_block_sizes: &[usize]) -> Option<Vec<Level>> {
+    if items_total == 0 {
+        return if level_block_sizes.is_empty() { Some(Vec::new()) } else { None };
+    }
+    let mut levels = vec![Level { index: 0, blocks_count: 0, items_count: 0, }; level_block_sizes.len()];
+    let mut items_count = items_total;
+    for (index, &block_size) in level_block_sizes.iter().enumerate().rev() {
+        let blocks_count = items_count.div_ceil(block_size as u64);
+        levels[index] = Level { index, blocks_count, items_count, };
+        items_count = blocks_count;
+    }
+    match levels.first() {
+        Some(root) if root.blocks_count == 1 =>
+            Some(levels),
+        _ =>
+            None,
+    }
+}
+
+fn rebalance(levels: &mut [Level], items_total: u64, level_block_sizes: &[usize], min_items: &[u64]) {
+    // falls back to lower fills when the requested one cannot be reached, keeping the top heavy counts at worst
+    let max_min_items = min_items.iter().copied().max().unwrap_or(0);
+    for fill_limit in (2 ..= max_min_items).rev() {
+        let required: Vec<u64> = levels
+            .iter()
+            .map(|level| {
+                let child_blocks_count = levels.get(level.index + 1).map_or(0, |child| child.blocks_count);
+                let level_min_items = min(min_items[level.index], fill_limit);
+                let own_items_count = if level.index == 0 { 1 } else { level.blocks_count.saturating_mul(level_min_items) };
+                max(own_items_count, child_blocks_count)
+            })
+            .collect();
+        let required_total = required.iter().fold(0, |total: u64, &count| total.saturating_add(count));
+        if required_total > items_total {
+            continue;
+        }
+        let mut items_extra = items_total - required_total;
+        for (level, required_count) in levels.iter_mut().zip(required) {
+            let level_extra = min(items_extra, level.blocks_count.saturating_mul(level_block_sizes[level.index] as u64) - required_count);
+            level.items_count = required_count + level_extra;
+            items_extra -= level_extra;
+        }
+        return;
+    }
 }
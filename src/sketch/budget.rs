@@ -0,0 +1,274 @@
+use super::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Budget {
+    pub block_budget: usize,
+    pub max_footprint: usize,
+    // every `restart_interval`-th item of a leaf takes its whole footprint, the others save their `shared` bytes
+    pub restart_interval: usize,
+    // the key bytes of delta packed footprints, a leaf keeps only its first key whole and packs the others
+    // to the bit width of their `delta` sum; zero when keys are not delta packed
+    pub packed_key_len: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Footprint {
+    pub item: usize,
+    pub separator: usize,
+    // the bytes saved when the item follows the previous one within the same leaf
+    pub shared: usize,
+    // how much the key grows over the previous one
+    pub delta: u128,
+}
+
+#[derive(Default)]
+struct OpenBlock {
+    used: usize,
+    items_count: usize,
+    // the difference of the last key from the first one in a leaf, unknown once an item did not follow the previous one
+    span: Option<u128>,
+}
+
+#[derive(Default)]
+struct BTreeLevel {
+    open: OpenBlock,
+    closed: Vec<usize>,
+}
+
+#[derive(Default)]
+struct BPlusLevel {
+    open: OpenBlock,
+    closed: Vec<usize>,
+    last_separator: usize,
+    pending_separator: Option<usize>,
+}
+
+// Both packers count levels from the leaves up and reverse them into root first order when done.
+
+// Bulk loads a B-tree bottom up: a leaf takes items while they fit, the item which does not fit goes up
+// as the parent of the closed leaf. Upper blocks are closed once the largest item could not fit anymore,
+// so the next item always has room there. Every item is placed so that the items left are still enough
+// to give each open block below the top its parent, the tail of the stream goes to the right spine then.
+// Only a leaf item right after the previous item of the stream saves its shared or packed key bytes.
+pub(super) fn btree<I>(items_total: u64, budget: Budget, footprints: I) -> Result<Vec<Vec<usize>>, Error>
+where I: IntoIterator<Item = Footprint>,
+{
+    check_budget(budget)?;
+    let mut levels: Vec<BTreeLevel> = Vec::new();
+    let mut pending_level = 0;
+    let mut follows_leaf_item = false;
+    let mut items_count: u64 = 0;
+    for footprint in footprints {
+        check_footprint(budget, items_count, footprint)?;
+        items_count += 1;
+        let items_after = items_total.saturating_sub(items_count);
+        if levels.is_empty() {
+            levels.push(BTreeLevel::default());
+        }
+        let top = levels.len() - 1;
+        let leaf = open_after(&levels[0].open, budget, footprint, follows_leaf_item);
+        let leaf_footprint = leaf.used - levels[0].open.used;
+        let level_footprint = |level_index| if level_index == 0 { leaf_footprint } else { footprint.item };
+
+        let target_level = if pending_level > 0 {
+            pending_level
+        } else if fits(&levels[0].open, budget.block_budget, leaf_footprint) {
+            0
+        } else {
+            1
+        };
+        let target_level = if need_after(&levels, budget, target_level, level_footprint(target_level), items_after) <= items_after {
+            target_level
+        } else if let Some(orphan_level) = lowest_orphan(&levels) {
+            // the item becomes the parent of the lowest block still lacking one
+            orphan_level + 1
+        } else if items_after > 0 {
+            // too few items left to reach the leaves and climb back, the item starts a childless block instead
+            top - items_after as usize
+        } else if fits(&levels[top].open, budget.block_budget, level_footprint(top)) {
+            top
+        } else {
+            // the last item does not fit the root anymore and becomes the parent of it
+            top + 1
+        };
+
+        if target_level > 0 {
+            close_block(&mut levels[target_level - 1]);
+        }
+        if target_level == levels.len() {
+            levels.push(BTreeLevel::default());
+        }
+        let level = &mut levels[target_level];
+        level.open = if target_level == 0 { leaf } else { open_after(&level.open, budget, footprint, false) };
+        follows_leaf_item = target_level == 0;
+        pending_level = 0;
+        if is_full(&level.open, budget, target_level, items_after) {
+            close_block(level);
+            pending_level = target_level + 1;
+        }
+    }
+    if items_count != items_total {
+        return Err(Error::ItemsTotalMismatch { items_total, levels_items_total: items_count, });
+    }
+    Ok(levels
+        .into_iter()
+        .rev()
+        .map(|mut level| {
+            close_block(&mut level);
+            level.closed
+        })
+        .collect())
+}
+
+// Packs the leaves and then every separators level while blocks fit, the separator of a block moves up
+// only when the next block of its level starts, so a level left with a single block becomes the root.
+pub(super) fn bplus<I>(items_total: u64, budget: Budget, footprints: I) -> Result<Vec<Vec<usize>>, Error>
+where I: IntoIterator<Item = Footprint>,
+{
+    check_budget(budget)?;
+    let mut levels: Vec<BPlusLevel> = Vec::new();
+    let mut items_count: u64 = 0;
+    for footprint in footprints {
+        check_footprint(budget, items_count, footprint)?;
+        items_count += 1;
+        push_separator(&mut levels, 0, budget, footprint);
+    }
+    if items_count != items_total {
+        return Err(Error::ItemsTotalMismatch { items_total, levels_items_total: items_count, });
+    }
+
+    let mut level_index = 0;
+    while level_index < levels.len() {
+        let is_top = level_index + 1 == levels.len();
+        let level = &mut levels[level_index];
+        if level.open.items_count > 0 {
+            level.closed.push(level.open.items_count);
+            level.open = OpenBlock::default();
+            level.pending_separator = Some(level.last_separator);
+        }
+        if is_top && level.closed.len() == 1 {
+            break;
+        }
+        if let Some(separator) = level.pending_separator.take() {
+            push_separator(&mut levels, level_index + 1, budget, Footprint { item: separator, separator, shared: 0, delta: 0, });
+        }
+        level_index += 1;
+    }
+    Ok(levels
+        .into_iter()
+        .rev()
+        .map(|level| level.closed)
+        .collect())
+}
+
+fn check_budget(budget: Budget) -> Result<(), Error> {
+    // every upper block has to hold at least two items for the tree to narrow down
+    if budget.max_footprint.saturating_mul(2) > budget.block_budget {
+        return Err(Error::BudgetTooSmall { budget: budget.block_budget, max_footprint: budget.max_footprint, });
+    }
+    Ok(())
+}
+
+fn check_footprint(budget: Budget, item_index: u64, footprint: Footprint) -> Result<(), Error> {
+    for footprint in [footprint.item, footprint.separator] {
+        if footprint > budget.max_footprint {
+            return Err(Error::FootprintExceedsMax { item_index, footprint, max_footprint: budget.max_footprint, });
+        }
+    }
+    Ok(())
+}
+
+fn fits(open: &OpenBlock, block_budget: usize, footprint: usize) -> bool {
+    open.items_count == 0 || open.used + footprint <= block_budget
+}
+
+// the open block with one more item, which `follows` the last one of the block in key order or not;
+// only a leaf item can, the keys of upper blocks are spread over the stream
+fn open_after(open: &OpenBlock, budget: Budget, footprint: Footprint, follows: bool) -> OpenBlock {
+    let items_count = open.items_count + 1;
+    if open.items_count == 0 {
+        return OpenBlock { used: footprint.item, items_count, span: Some(0), };
+    }
+    let mut item = footprint.item;
+    if follows && !open.items_count.is_multiple_of(budget.restart_interval.max(1)) {
+        item = item.saturating_sub(footprint.shared);
+    }
+    let span = open.span
+        .filter(|_| follows)
+        .and_then(|span| span.checked_add(footprint.delta));
+    if budget.packed_key_len == 0 {
+        return OpenBlock { used: open.used + item, items_count, span, };
+    }
+    // the item key moves into the packed deltas, which all widen to the bits of the new span
+    let used = open.used - packed_deltas_len(budget, open.items_count - 1, open.span)
+        + item.saturating_sub(budget.packed_key_len)
+        + packed_deltas_len(budget, items_count - 1, span);
+    OpenBlock { used, items_count, span, }
+}
+
+fn packed_deltas_len(budget: Budget, deltas_count: usize, span: Option<u128>) -> usize {
+    match span {
+        Some(span) =>
+            (deltas_count * (u128::BITS - span.leading_zeros()) as usize).div_ceil(8),
+        None =>
+            deltas_count * budget.packed_key_len,
+    }
+}
+
+fn close_block(level: &mut BTreeLevel) {
+    if level.open.items_count > 0 {
+        level.closed.push(level.open.items_count);
+        level.open = OpenBlock::default();
+    }
+}
+
+// the lowest level below the root with a block which still needs a parent item
+fn lowest_orphan(levels: &[BTreeLevel]) -> Option<usize> {
+    let top = levels.len() - 1;
+    (0 .. top).find(|&level_index| levels[level_index].open.items_count > 0)
+}
+
+// an upper block left without room for the largest item is closed, unless no item could go there anymore
+fn is_full(open: &OpenBlock, budget: Budget, level_index: usize, items_after: u64) -> bool {
+    level_index > 0 && items_after > 0 && budget.block_budget.saturating_sub(open.used) < budget.max_footprint
+}
+
+// the items needed after one goes to `target_level`: one parent for every open block from there up to the top
+fn need_after(levels: &[BTreeLevel], budget: Budget, target_level: usize, footprint: usize, items_after: u64) -> u64 {
+    let top = levels.len() - 1;
+    let new_top = top.max(target_level);
+    let used = levels.get(target_level).map_or(0, |level| level.open.used) + footprint;
+    let open = OpenBlock { used, items_count: 1, span: None, };
+    let need = if is_full(&open, budget, target_level, items_after) {
+        new_top.max(target_level + 1) - target_level
+    } else {
+        new_top - target_level
+    };
+    need as u64
+}
+
+// leaf items come in stream order, so each one follows the previous in its leaf unless it starts the next one
+fn push_separator(levels: &mut Vec<BPlusLevel>, mut level_index: usize, budget: Budget, mut footprint: Footprint) {
+    loop {
+        if level_index == levels.len() {
+            levels.push(BPlusLevel::default());
+        }
+        let level = &mut levels[level_index];
+        let mut open = open_after(&level.open, budget, footprint, level_index == 0);
+        if !fits(&level.open, budget.block_budget, open.used - level.open.used) {
+            level.closed.push(level.open.items_count);
+            level.pending_separator = Some(level.last_separator);
+            open = open_after(&OpenBlock::default(), budget, footprint, false);
+        }
+        level.open = open;
+        level.last_separator = footprint.separator;
+        match level.pending_separator.take() {
+            None =>
+                return,
+            Some(separator) => {
+                footprint = Footprint { item: separator, separator, shared: 0, delta: 0, };
+                level_index += 1;
+            },
+        }
+    }
+}
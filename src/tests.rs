@@ -1,5 +1,28 @@
 mod sketch {
-    use crate::sketch;
+    use std::sync::Arc;
+
+    use crate::{
+        sketch,
+        writer::plan,
+    };
+
+    fn plan_items(sketch: &sketch::Tree) -> Vec<sketch::ItemPosition> {
+        let mut items = Vec::new();
+        let mut plan_ctx = plan::Context::new(Arc::new(sketch.clone()));
+        let mut kont = plan::Script::boot();
+        loop {
+            match kont.next.step(&mut plan_ctx) {
+                plan::Instruction::Perform(plan::Perform { op: plan::Op::BlockItem { index, }, level_index, block_index, next, }) => {
+                    items.push(sketch::ItemPosition { level_index, block_index, item_index: index, });
+                    kont = next;
+                },
+                plan::Instruction::Perform(plan::Perform { next, .. }) =>
+                    kont = next,
+                plan::Instruction::Done =>
+                    return items,
+            }
+        }
+    }
 
     #[test]
     fn tree17_4() {
@@ -25,4 +48,905 @@ mod sketch {
             ]
         );
     }
+
+    #[test]
+    fn restore() {
+        let sketch = sketch::Tree::new(22, 3);
+        let restored = sketch::Tree::restore(22, sketch::BlockSizes::Uniform(3), sketch::BlockFill::Packed, sketch::Mode::BTree, sketch.levels().to_vec()).unwrap();
+        assert_eq!(restored.levels(), sketch.levels());
+        assert_eq!(restored.block_sizes(), &sketch::BlockSizes::Uniform(3));
+        assert_eq!(restored.items_total(), 22);
+    }
+
+    #[test]
+    fn restore_invalid() {
+        assert_eq!(
+            sketch::Tree::restore(5, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![
+                sketch::Level { index: 0, blocks_count: 1, items_count: 4 },
+                sketch::Level { index: 2, blocks_count: 1, items_count: 1 },
+            ]).err(),
+            Some(sketch::Error::UnexpectedLevelIndex { level_index: 2, expected: 1, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore(8, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![sketch::Level { index: 0, blocks_count: 2, items_count: 8 }]).err(),
+            Some(sketch::Error::InvalidRootBlocksCount { blocks_count: 2, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore(5, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![sketch::Level { index: 0, blocks_count: 1, items_count: 5 }]).err(),
+            Some(sketch::Error::InvalidLevelItemsCount { level_index: 0, blocks_count: 1, items_count: 5, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore(14, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![
+                sketch::Level { index: 0, blocks_count: 1, items_count: 2 },
+                sketch::Level { index: 1, blocks_count: 3, items_count: 12 },
+            ]).err(),
+            Some(sketch::Error::TooManyChildBlocks { level_index: 1, blocks_count: 3, parent_items_count: 2, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore(6, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![sketch::Level { index: 0, blocks_count: 1, items_count: 4 }]).err(),
+            Some(sketch::Error::ItemsTotalMismatch { items_total: 6, levels_items_total: 4, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore(9, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![
+                sketch::Level { index: 0, blocks_count: 1, items_count: 4 },
+                sketch::Level { index: 1, blocks_count: 2, items_count: 4 },
+                sketch::Level { index: 2, blocks_count: 1, items_count: 1 },
+            ]).err(),
+            Some(sketch::Error::InvalidLevelItemsCount { level_index: 1, blocks_count: 2, items_count: 4, }),
+        );
+    }
+
+    #[test]
+    fn try_new_errors() {
+        assert_eq!(sketch::Tree::try_new(10, 0), Err(sketch::Error::InvalidBlockSize { block_size: 0, }));
+        assert_eq!(sketch::Tree::try_new(10, 1), Err(sketch::Error::InvalidBlockSize { block_size: 1, }));
+        assert_eq!(
+            sketch::Tree::restore(1, sketch::BlockSizes::Uniform(1), sketch::BlockFill::Packed, sketch::Mode::BTree, vec![sketch::Level { index: 0, blocks_count: 1, items_count: 1 }]).err(),
+            Some(sketch::Error::InvalidBlockSize { block_size: 1, }),
+        );
+        #[cfg(not(target_pointer_width = "64"))]
+        assert!(matches!(sketch::Tree::try_new(u64::MAX, 4), Err(sketch::Error::BlocksCountOverflow { .. })));
+    }
+
+    #[test]
+    fn try_new_huge() {
+        // above u32::MAX items, but few enough blocks to be addressed on 32 bit targets
+        let items_total: u64 = 5_000_000_017;
+        for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+            for block_size in [2, 3, 64, 4096] {
+                let sketch = sketch::Tree::try_with_mode(items_total, block_size, sketch::FillStrategy::TopHeavy, mode).unwrap();
+                assert_eq!(sketch.items_total(), items_total);
+                let levels_items_total = match mode {
+                    sketch::Mode::BTree =>
+                        sketch.levels().iter().map(|level| level.items_count).sum::<u64>(),
+                    sketch::Mode::BPlus =>
+                        sketch.levels().last().unwrap().items_count,
+                };
+                assert_eq!(levels_items_total, items_total);
+                let restored = sketch::Tree::restore(sketch.items_total(), sketch.block_sizes().clone(), sketch.block_fill(), sketch.mode(), sketch.levels().to_vec()).unwrap();
+                assert_eq!(restored, sketch);
+                if mode == sketch::Mode::BTree {
+                    let last_rank = items_total - 1;
+                    let last_item = sketch.item_at_rank(last_rank).unwrap();
+                    assert_eq!(sketch.rank(last_item), Some(last_rank));
+                    assert_eq!(sketch.item_at_rank(items_total), None);
+                }
+            }
+        }
+        let balanced = sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, };
+        let sketch = sketch::Tree::try_with_fill(items_total, 64, balanced).unwrap();
+        assert_eq!(sketch.levels().iter().map(|level| level.items_count).sum::<u64>(), items_total);
+        assert_eq!(sketch.rank(sketch.item_at_rank(items_total - 1).unwrap()), Some(items_total - 1));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(sketch::Tree::try_new(u64::MAX, 2).unwrap().items_total(), u64::MAX);
+    }
+
+    #[test]
+    fn fill_strategies() {
+        let balanced = sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, };
+        let sketch = sketch::Tree::try_with_fill(5, 4, balanced).unwrap();
+        assert_eq!(
+            sketch.levels(),
+            &[
+                sketch::Level { index: 0, blocks_count: 1, items_count: 2 },
+                sketch::Level { index: 1, blocks_count: 1, items_count: 3 },
+            ]
+        );
+
+        let sketch = sketch::Tree::try_with_fill(17, 4, sketch::FillStrategy::MinHeight).unwrap();
+        assert_eq!(sketch.levels(), sketch::Tree::new(17, 4).levels());
+        let items_counts: Vec<_> = (0 .. 4)
+            .map(|block_index| sketch.block_items_count(sketch::BlockPosition { level_index: 1, block_index, }).unwrap())
+            .collect();
+        assert_eq!(items_counts, [4, 3, 3, 3]);
+
+        assert_eq!(
+            sketch::Tree::try_with_mode(17, 4, balanced, sketch::Mode::BPlus),
+            Err(sketch::Error::UnsupportedFill { fill: balanced, mode: sketch::Mode::BPlus, }),
+        );
+
+        assert_eq!(
+            sketch::Tree::try_with_fill(5, 4, sketch::FillStrategy::Balanced { min_fill: sketch::MinFill { numerator: 3, denominator: 2, }, }),
+            Err(sketch::Error::InvalidMinFill { min_fill: sketch::MinFill { numerator: 3, denominator: 2, }, }),
+        );
+    }
+
+    #[test]
+    fn balanced_min_fill() {
+        let balanced = sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, };
+        for block_size in 3usize .. 12 {
+            let min_items = (2 * block_size).div_ceil(3);
+            for items_total in 1 .. 2000 {
+                let sketch = sketch::Tree::try_with_fill(items_total, block_size, balanced).unwrap();
+                assert_eq!(sketch.levels().len(), sketch::Tree::new(items_total, block_size).levels().len());
+                for level in &sketch.levels()[1 ..] {
+                    for block_index in 0 .. level.blocks_count as usize {
+                        let items_count = sketch.block_items_count(sketch::BlockPosition { level_index: level.index, block_index, }).unwrap();
+                        assert!(items_count >= min_items && items_count <= block_size, "{items_total}/{block_size} {level:?}");
+                    }
+                }
+                let restored = sketch::Tree::restore(sketch.items_total(), sketch.block_sizes().clone(), sketch.block_fill(), sketch.mode(), sketch.levels().to_vec());
+                assert_eq!(restored, Ok(sketch));
+            }
+        }
+    }
+
+    #[test]
+    fn rank_follows_plan_order() {
+        let fills = [
+            sketch::FillStrategy::TopHeavy,
+            sketch::FillStrategy::MinHeight,
+            sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, },
+        ];
+        for fill in fills {
+            for &(items_total, block_size) in &[(1, 3), (5, 4), (17, 4), (17, 3), (22, 3), (100, 4), (1000, 5), (1000, 2)] {
+                let sketch = sketch::Tree::try_with_fill(items_total as u64, block_size, fill).unwrap();
+                let items = plan_items(&sketch);
+                assert_eq!(items.len(), items_total);
+                for (rank, &item) in items.iter().enumerate() {
+                    assert_eq!(sketch.rank(item), Some(rank as u64), "{items_total}/{block_size} {fill:?} {item:?}");
+                    assert_eq!(sketch.item_at_rank(rank as u64), Some(item), "{items_total}/{block_size} {fill:?} rank {rank}");
+                }
+                assert_eq!(sketch.item_at_rank(items_total as u64), None);
+            }
+        }
+    }
+
+    #[test]
+    fn bplus_levels() {
+        let sketch = sketch::Tree::try_with_mode(17, 4, sketch::FillStrategy::TopHeavy, sketch::Mode::BPlus).unwrap();
+        assert_eq!(
+            sketch.levels(),
+            &[
+                sketch::Level { index: 0, blocks_count: 1, items_count: 2 },
+                sketch::Level { index: 1, blocks_count: 2, items_count: 5 },
+                sketch::Level { index: 2, blocks_count: 5, items_count: 17 },
+            ]
+        );
+        assert_eq!(sketch.leaf_level_index(), Some(2));
+        assert!(sketch.is_separators_level(1));
+        assert!(!sketch.is_separators_level(2));
+
+        for fill in [sketch::FillStrategy::TopHeavy, sketch::FillStrategy::MinHeight] {
+            for &(items_total, block_size) in &[(0, 3), (1, 3), (3, 3), (17, 4), (22, 3), (1000, 5), (1000, 2)] {
+                let sketch = sketch::Tree::try_with_mode(items_total as u64, block_size, fill, sketch::Mode::BPlus).unwrap();
+                let leaf_items = sketch.levels().last().map_or(0, |level| level.items_count);
+                assert_eq!(leaf_items, items_total as u64);
+                let items = plan_items(&sketch);
+                for (rank, &item) in items.iter().enumerate() {
+                    assert_eq!(sketch.rank(item), Some(rank as u64), "{items_total}/{block_size} {fill:?} {item:?}");
+                }
+                check_child_parent(&sketch);
+                let restored = sketch::Tree::restore(sketch.items_total(), sketch.block_sizes().clone(), sketch.block_fill(), sketch.mode(), sketch.levels().to_vec());
+                assert_eq!(restored, Ok(sketch));
+            }
+        }
+
+        let mut levels = sketch.levels().to_vec();
+        levels[0].items_count = 3;
+        assert_eq!(
+            sketch::Tree::restore(17, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Packed, sketch::Mode::BPlus, levels),
+            Err(sketch::Error::TooFewChildBlocks { level_index: 1, blocks_count: 2, parent_items_count: 3, }),
+        );
+    }
+
+    #[test]
+    fn block_sizes() {
+        let leaf_inner = sketch::BlockSizes::LeafInner { leaf: 8, inner: 3, };
+        let sketch = sketch::Tree::try_with_sizes(50, leaf_inner.clone(), sketch::FillStrategy::TopHeavy, sketch::Mode::BTree).unwrap();
+        assert_eq!(
+            sketch.levels(),
+            &[
+                sketch::Level { index: 0, blocks_count: 1, items_count: 3 },
+                sketch::Level { index: 1, blocks_count: 3, items_count: 9 },
+                sketch::Level { index: 2, blocks_count: 5, items_count: 38 },
+            ]
+        );
+        assert_eq!(sketch.level_block_size(1), Some(3));
+        assert_eq!(sketch.level_block_size(2), Some(8));
+        assert_eq!(sketch.level_block_size(3), None);
+        assert_eq!(sketch.block_items_count(sketch::BlockPosition { level_index: 2, block_index: 4, }), Some(6));
+
+        let sketch = sketch::Tree::try_with_sizes(50, leaf_inner, sketch::FillStrategy::TopHeavy, sketch::Mode::BPlus).unwrap();
+        assert_eq!(
+            sketch.levels(),
+            &[
+                sketch::Level { index: 0, blocks_count: 1, items_count: 3 },
+                sketch::Level { index: 1, blocks_count: 3, items_count: 7 },
+                sketch::Level { index: 2, blocks_count: 7, items_count: 50 },
+            ]
+        );
+
+        // the leaf level keeps its own size even when the inner levels could hold every item
+        let sketch = sketch::Tree::try_with_sizes(100, sketch::BlockSizes::LeafInner { leaf: 2, inner: 100, }, sketch::FillStrategy::TopHeavy, sketch::Mode::BTree).unwrap();
+        assert_eq!(
+            sketch.levels(),
+            &[
+                sketch::Level { index: 0, blocks_count: 1, items_count: 99 },
+                sketch::Level { index: 1, blocks_count: 1, items_count: 1 },
+            ]
+        );
+
+        assert_eq!(
+            sketch::Tree::try_with_sizes(10, sketch::BlockSizes::PerLevel(Vec::new()), sketch::FillStrategy::TopHeavy, sketch::Mode::BTree),
+            Err(sketch::Error::NoBlockSizes),
+        );
+        assert_eq!(
+            sketch::Tree::try_with_sizes(10, sketch::BlockSizes::LeafInner { leaf: 4, inner: 1, }, sketch::FillStrategy::TopHeavy, sketch::Mode::BTree),
+            Err(sketch::Error::InvalidBlockSize { block_size: 1, }),
+        );
+
+        let fills = [
+            sketch::FillStrategy::TopHeavy,
+            sketch::FillStrategy::MinHeight,
+            sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, },
+        ];
+        let block_sizes = [
+            sketch::BlockSizes::LeafInner { leaf: 8, inner: 3, },
+            sketch::BlockSizes::LeafInner { leaf: 2, inner: 5, },
+            sketch::BlockSizes::PerLevel(vec![2, 5, 3]),
+        ];
+        for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+            for fill in fills {
+                if mode == sketch::Mode::BPlus && matches!(fill, sketch::FillStrategy::Balanced { .. }) {
+                    continue;
+                }
+                for sizes in &block_sizes {
+                    for items_total in [0, 1, 2, 7, 40, 333, 1000] {
+                        let sketch = sketch::Tree::try_with_sizes(items_total as u64, sizes.clone(), fill, mode).unwrap();
+                        for level in sketch.levels() {
+                            let block_size = sketch.level_block_size(level.index).unwrap();
+                            assert_eq!(block_size, sizes.level_block_size(level.index, sketch.levels().len()));
+                            for block_index in 0 .. level.blocks_count as usize {
+                                let items_count = sketch.block_items_count(sketch::BlockPosition { level_index: level.index, block_index, }).unwrap();
+                                assert!(items_count >= 1 && items_count <= block_size, "{items_total} {sizes:?} {fill:?} {mode:?} {level:?}");
+                            }
+                        }
+                        let items = plan_items(&sketch);
+                        for (rank, &item) in items.iter().enumerate() {
+                            assert_eq!(sketch.rank(item), Some(rank as u64), "{items_total} {sizes:?} {fill:?} {mode:?} {item:?}");
+                            if mode == sketch::Mode::BTree {
+                                assert_eq!(sketch.item_at_rank(rank as u64), Some(item));
+                            }
+                        }
+                        check_child_parent(&sketch);
+                        let restored = sketch::Tree::restore(sketch.items_total(), sizes.clone(), sketch.block_fill(), mode, sketch.levels().to_vec());
+                        assert_eq!(restored, Ok(sketch));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn byte_budget() {
+        let budget = sketch::Budget { block_budget: 100, max_footprint: 40, restart_interval: 1, packed_key_len: 0, };
+        for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+            for items_total in [0, 1, 2, 5, 17, 100, 1000, 5000] {
+                let footprints: Vec<_> = (0 .. items_total)
+                    .map(|index| {
+                        let item = 10 + (index * 7919) % 31;
+                        sketch::Footprint { item, separator: item - index % 5, shared: 0, delta: 0, }
+                    })
+                    .collect();
+                let sketch = sketch::Tree::try_with_budget(items_total as u64, budget, mode, footprints.iter().copied()).unwrap();
+                assert_eq!(sketch.block_fill(), sketch::BlockFill::Explicit);
+                check_child_parent(&sketch);
+                let restored = sketch::Tree::restore_explicit(sketch.items_total(), mode, sketch.levels().to_vec(), sketch.blocks_items_counts());
+                assert_eq!(restored.as_ref(), Ok(&sketch));
+
+                // plan order is key order, B+ separators repeat the key of the last leaf item before them
+                let mut blocks_used = std::collections::HashMap::new();
+                let mut leaf_items = 0;
+                for (rank, item) in plan_items(&sketch).into_iter().enumerate() {
+                    assert_eq!(sketch.rank(item), Some(rank as u64));
+                    let footprint = match mode {
+                        sketch::Mode::BTree =>
+                            footprints[rank].item,
+                        sketch::Mode::BPlus if sketch.is_separators_level(item.level_index) =>
+                            footprints[leaf_items - 1].separator,
+                        sketch::Mode::BPlus => {
+                            leaf_items += 1;
+                            footprints[leaf_items - 1].item
+                        },
+                    };
+                    *blocks_used.entry((item.level_index, item.block_index)).or_insert(0) += footprint;
+                }
+                for ((level_index, block_index), used) in blocks_used {
+                    assert!(used <= budget.block_budget, "{items_total} {mode:?} block {level_index}/{block_index} uses {used}");
+                }
+            }
+        }
+
+        let leaves = sketch::Tree::try_with_budget(10, budget, sketch::Mode::BPlus, [sketch::Footprint { item: 30, separator: 20, shared: 0, delta: 0, }; 10]).unwrap();
+        assert_eq!(leaves.blocks_items_counts(), vec![vec![4], vec![3, 3, 3, 1]]);
+
+        assert_eq!(
+            sketch::Tree::try_with_budget(1, sketch::Budget { block_budget: 100, max_footprint: 51, restart_interval: 1, packed_key_len: 0, }, sketch::Mode::BTree, []),
+            Err(sketch::Error::BudgetTooSmall { budget: 100, max_footprint: 51, }),
+        );
+        assert_eq!(
+            sketch::Tree::try_with_budget(2, budget, sketch::Mode::BTree, [sketch::Footprint { item: 41, separator: 12, shared: 0, delta: 0, }]),
+            Err(sketch::Error::FootprintExceedsMax { item_index: 0, footprint: 41, max_footprint: 40, }),
+        );
+        assert_eq!(
+            sketch::Tree::try_with_budget(2, budget, sketch::Mode::BPlus, [sketch::Footprint { item: 20, separator: 12, shared: 0, delta: 0, }]),
+            Err(sketch::Error::ItemsTotalMismatch { items_total: 2, levels_items_total: 1, }),
+        );
+        let levels = vec![sketch::Level { index: 0, blocks_count: 1, items_count: 2 }];
+        assert_eq!(
+            sketch::Tree::restore(2, sketch::BlockSizes::Uniform(4), sketch::BlockFill::Explicit, sketch::Mode::BTree, levels.clone()),
+            Err(sketch::Error::MissingBlocksItemsCounts),
+        );
+        assert_eq!(
+            sketch::Tree::restore_explicit(2, sketch::Mode::BTree, levels.clone(), vec![vec![1, 1]]),
+            Err(sketch::Error::BlocksItemsCountsMismatch { level_index: 0, blocks_count: 1, counts_count: 2, }),
+        );
+        assert_eq!(
+            sketch::Tree::restore_explicit(2, sketch::Mode::BTree, levels, vec![vec![3]]),
+            Err(sketch::Error::InvalidLevelItemsCount { level_index: 0, blocks_count: 1, items_count: 2, }),
+        );
+    }
+
+    #[test]
+    fn child_parent() {
+        let balanced = sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, };
+        for sketch in [sketch::Tree::new(22, 3), sketch::Tree::try_with_fill(200, 4, balanced).unwrap()] {
+            check_child_parent(&sketch);
+        }
+
+        let sketch = sketch::Tree::new(22, 3);
+        assert_eq!(
+            sketch.child_block(sketch::ItemPosition { level_index: 1, block_index: 0, item_index: 1, }),
+            Some(sketch::BlockPosition { level_index: 2, block_index: 1, }),
+        );
+        assert_eq!(sketch.child_block(sketch::ItemPosition { level_index: 2, block_index: 0, item_index: 0, }), None);
+        assert_eq!(sketch.child_block(sketch::ItemPosition { level_index: 0, block_index: 0, item_index: 3, }), None);
+        assert_eq!(sketch.parent_item(sketch::BlockPosition { level_index: 1, block_index: 3, }), None);
+        assert_eq!(sketch.block_items_count(sketch::BlockPosition { level_index: 2, block_index: 3, }), Some(1));
+        assert_eq!(sketch.block_items_count(sketch::BlockPosition { level_index: 2, block_index: 4, }), None);
+    }
+
+    fn check_child_parent(sketch: &sketch::Tree) {
+        for level in sketch.levels() {
+            for block_index in 0 .. level.blocks_count as usize {
+                let block = sketch::BlockPosition { level_index: level.index, block_index, };
+                match sketch.parent_item(block) {
+                    None =>
+                        assert_eq!(level.index, 0),
+                    Some(parent) =>
+                        assert_eq!(sketch.child_block(parent), Some(block)),
+                }
+            }
+        }
+    }
+}
+
+mod block {
+    use std::borrow::Cow;
+
+    use crate::{
+        block,
+        codec,
+    };
+
+    #[test]
+    fn build_decode() {
+        let mut builder = block::Builder::new(64);
+        builder.push(b"a", b"first").unwrap();
+        builder.push(b"bc", b"").unwrap();
+        builder.push(b"def", b"third").unwrap();
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+        assert_eq!(page.len(), 64);
+
+        let block = block::Block::decode(&page).unwrap();
+        assert_eq!(block.items_count(), 3);
+        assert_eq!(block.item(0), Ok((b"a"[..].into(), block::Value::Inline(&b"first"[..]))));
+        assert_eq!(block.item(1), Ok((b"bc"[..].into(), block::Value::Inline(&b""[..]))));
+        assert_eq!(block.item(2), Ok((b"def"[..].into(), block::Value::Inline(&b"third"[..]))));
+        assert_eq!(block.item(3), Err(block::Error::ItemIndexOutOfRange { item_index: 3, items_count: 3, }));
+        assert_eq!(block.search(b"bc"), Ok(Ok(1)));
+        assert_eq!(block.search(b"b"), Ok(Err(1)));
+        assert_eq!(block.search(b"z"), Ok(Err(3)));
+    }
+
+    #[test]
+    fn overflow_ref() {
+        let overflow = block::OverflowRef { offset: 4096, value_len: 1 << 33, };
+        let mut builder = block::Builder::new(64);
+        builder.push(b"a", b"inline").unwrap();
+        builder.push_overflow(b"b", overflow).unwrap();
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+
+        let block = block::Block::decode(&page).unwrap();
+        assert_eq!(block.item(0), Ok((b"a"[..].into(), block::Value::Inline(&b"inline"[..]))));
+        assert_eq!(block.item(1), Ok((b"b"[..].into(), block::Value::Overflow(overflow))));
+        assert_eq!(block.search(b"b"), Ok(Ok(1)));
+
+        assert_eq!(block::max_inline_value_len(256), 63);
+        assert_eq!(block::stored_value_len(256, 63), 63);
+        assert_eq!(block::stored_value_len(256, 64), block::OVERFLOW_REF_SIZE);
+        assert_eq!(block::overflow_pages_count(256, 513), 3);
+    }
+
+    #[test]
+    fn log_ref() {
+        let log_ref = block::LogRef { offset: 77, value_len: 12, };
+        let mut builder = block::Builder::new(64);
+        builder.push_log_ref(b"k", log_ref).unwrap();
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+        assert_eq!(block::Block::decode(&page).unwrap().item(0), Ok((b"k"[..].into(), block::Value::Log(log_ref))));
+
+        // both value flags live in the top bits of the value length of the first item
+        page[15] |= 0xc0;
+        assert_eq!(
+            block::Block::decode(&page).unwrap().item(0),
+            Err(block::Error::InvalidValueFlags { item_index: 0, flags: 0xc000_0000, }),
+        );
+    }
+
+    #[test]
+    fn front_coded_key_cursor() {
+        // length prefixed keys of every size around the one byte header limit, so headers change within runs
+        let mut keys: Vec<Vec<u8>> = (0 .. 40)
+            .map(|index| {
+                let mut key = Vec::new();
+                codec::encode(&format!("{}{}", "k".repeat(100 + index * 2), index), &mut key);
+                key
+            })
+            .collect();
+        keys.sort();
+        let mut builder = block::Builder::with_encoding(8192, block::Encoding::FrontCoded { restart_interval: 6, });
+        for key in &keys {
+            builder.push(key, b"").unwrap();
+        }
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+        let block = block::Block::decode(&page).unwrap();
+
+        let mut cursor = block.key_cursor();
+        let orders = [
+            (0 .. keys.len()).collect::<Vec<_>>(),
+            (0 .. keys.len()).rev().collect(),
+            (0 .. keys.len()).map(|index| index * 17 % keys.len()).collect(),
+            vec![3, 3, 4, 2, 5, 6, 7, 12, 8, 9],
+        ];
+        for order in orders {
+            for index in order {
+                assert_eq!(cursor.key(index), Ok(&keys[index][..]), "{index}");
+            }
+        }
+        assert_eq!(cursor.key(keys.len()), Err(block::Error::ItemIndexOutOfRange { item_index: keys.len(), items_count: keys.len(), }));
+        assert_eq!(cursor.key(1), Ok(&keys[1][..]));
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(block.search(key), Ok(Ok(index)));
+        }
+    }
+
+    #[test]
+    fn front_coded() {
+        let keys: Vec<Vec<u8>> = (0 .. 11)
+            .map(|index| format!("https://example.com/items/{:04}", index * 7).into_bytes())
+            .collect();
+        let mut plain = block::Builder::new(512);
+        let mut front_coded = block::Builder::with_encoding(512, block::Encoding::FrontCoded { restart_interval: 4, });
+        for key in &keys {
+            plain.push(key, b"v").unwrap();
+            front_coded.push(key, b"v").unwrap();
+        }
+        let mut plain_page = Vec::new();
+        plain.write_page(&mut plain_page);
+        let mut page = Vec::new();
+        front_coded.write_page(&mut page);
+        let used = |page: &[u8]| page.iter().rposition(|&byte| byte != 0).unwrap();
+        assert!(used(&page) < used(&plain_page));
+
+        let plain_block = block::Block::decode(&plain_page).unwrap();
+        let block = block::Block::decode(&page).unwrap();
+        assert_eq!(block.items_count(), keys.len());
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(block.item(index), Ok((key[..].into(), block::Value::Inline(&b"v"[..]))));
+            assert_eq!(block.search(key), Ok(Ok(index)));
+        }
+        assert!(matches!(block.key(4), Ok(Cow::Borrowed(_))));
+        assert!(matches!(block.key(5), Ok(Cow::Owned(_))));
+        for probe in [&b"a"[..], b"https://example.com/items/0010", b"https://example.com/items/0056", b"z"] {
+            assert_eq!(block.search(probe), plain_block.search(probe));
+        }
+
+        // the shared length of the second item claims more than the first key holds
+        let offset = u32::from_le_bytes([page[12], page[13], page[14], page[15]]) as usize;
+        page[offset .. offset + 4].copy_from_slice(&200u32.to_le_bytes());
+        assert_eq!(
+            block::Block::decode(&page).unwrap().item(1),
+            Err(block::Error::InvalidSharedPrefix { item_index: 1, shared_len: 200, prefix_len: keys[0].len(), }),
+        );
+    }
+
+    #[test]
+    fn front_coded_length_prefixed() {
+        // the encoded length differs with every power of ten, the bytes after it are shared all the same
+        let keys: Vec<Vec<u8>> = [1, 9, 10, 11, 99, 100, 101, 999, 1000, 1001, 200 * 1000]
+            .iter()
+            .map(|index| {
+                let mut key = Vec::new();
+                codec::encode(&format!("https://example.com/items/{}", index), &mut key);
+                key
+            })
+            .collect();
+        let encoding = block::Encoding::FrontCoded { restart_interval: 4, };
+        let mut plain = block::Builder::new(1024);
+        let mut front_coded = block::Builder::with_encoding(1024, encoding);
+        for key in &keys {
+            plain.push(key, b"v").unwrap();
+            front_coded.push(key, b"v").unwrap();
+        }
+        assert_eq!(encoding.shared_len(&keys[1], &keys[2]), "https://example.com/items/".len());
+        let mut plain_page = Vec::new();
+        plain.write_page(&mut plain_page);
+        let mut page = Vec::new();
+        front_coded.write_page(&mut page);
+        let used = |page: &[u8]| page.iter().rposition(|&byte| byte != 0).unwrap();
+        assert!(used(&page) < used(&plain_page));
+
+        let block = block::Block::decode(&page).unwrap();
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(block.item(index), Ok((key[..].into(), block::Value::Inline(&b"v"[..]))));
+        }
+
+        // the key header of the second item claims more bytes than it stores
+        let offset = u32::from_le_bytes([page[12], page[13], page[14], page[15]]) as usize;
+        page[offset + 3] = 0xf0;
+        assert!(matches!(block::Block::decode(&page).unwrap().item(1), Err(block::Error::InvalidKeyHeader { item_index: 1, header_len: 15, .. })));
+    }
+
+    #[test]
+    fn delta_packed() {
+        let keys: Vec<u64> = (0 .. 40).map(|index| 1_700_000_000_000 + index * index * 1000).collect();
+        let mut plain = block::Builder::new(1024);
+        let mut delta_packed = block::Builder::with_encoding(1024, block::Encoding::DeltaPacked);
+        for key in &keys {
+            plain.push(&key.to_be_bytes(), b"").unwrap();
+            delta_packed.push(&key.to_be_bytes(), b"").unwrap();
+        }
+        let mut plain_page = Vec::new();
+        plain.write_page(&mut plain_page);
+        let mut page = Vec::new();
+        delta_packed.write_page(&mut page);
+        let used = |page: &[u8]| page.iter().rposition(|&byte| byte != 0).unwrap();
+        assert!(used(&page) * 2 < used(&plain_page));
+
+        let plain_block = block::Block::decode(&plain_page).unwrap();
+        let block = block::Block::decode(&page).unwrap();
+        assert_eq!(block.items_count(), keys.len());
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(block.item(index), Ok((key.to_be_bytes()[..].into(), block::Value::Inline(&b""[..]))));
+        }
+        for probe in [0, keys[0], keys[17], keys[17] + 1, keys[39], u64::MAX] {
+            assert_eq!(block.search(&probe.to_be_bytes()), plain_block.search(&probe.to_be_bytes()));
+        }
+
+        // equal keys pack to zero bits each
+        let mut builder = block::Builder::with_encoding(64, block::Encoding::DeltaPacked);
+        builder.push(&7u64.to_be_bytes(), b"a").unwrap();
+        builder.push(&7u64.to_be_bytes(), b"b").unwrap();
+        builder.write_page(&mut page);
+        let block = block::Block::decode(&page).unwrap();
+        assert_eq!(block.item(1), Ok((7u64.to_be_bytes()[..].into(), block::Value::Inline(&b"b"[..]))));
+
+        assert_eq!(builder.push(b"short", b""), Err(block::Error::KeyNotPackable { len: 5, }));
+        assert_eq!(builder.push(&7u32.to_be_bytes(), b""), Err(block::Error::KeyNotPackable { len: 4, }));
+        page[5] = 65;
+        assert!(matches!(block::Block::decode(&page), Err(block::Error::InvalidBitWidth { bit_width: 65, })));
+        page[4] = 3;
+        assert!(matches!(block::Block::decode(&page), Err(block::Error::InvalidPackedKeyLen { key_len: 3, })));
+    }
+
+    #[test]
+    fn delta_packed_key_widths() {
+        fn check(keys: Vec<Vec<u8>>) {
+            let mut builder = block::Builder::with_encoding(4096, block::Encoding::DeltaPacked);
+            for key in &keys {
+                builder.push(key, b"v").unwrap();
+            }
+            let mut page = Vec::new();
+            builder.write_page(&mut page);
+            let block = block::Block::decode(&page).unwrap();
+            assert_eq!(block.items_count(), keys.len());
+            for (index, key) in keys.iter().enumerate() {
+                assert_eq!(block.item(index), Ok((key[..].into(), block::Value::Inline(&b"v"[..]))));
+            }
+        }
+        check((0 .. 200u16).map(|index| (index * 300).to_be_bytes().to_vec()).collect());
+        check((0 .. 200u32).map(|index| (4_000_000_000 + index * 7).to_be_bytes().to_vec()).collect());
+        check((0 .. 200u128).map(|index| (u128::MAX / 3 + index * index * u128::from(u64::MAX)).to_be_bytes().to_vec()).collect());
+        // deltas as wide as the keys
+        check(vec![0u128.to_be_bytes().to_vec(), 1u128.to_be_bytes().to_vec(), u128::MAX.to_be_bytes().to_vec()]);
+        check(vec![vec![0], vec![255]]);
+
+        // two byte keys take two bytes for the first key and pack the rest below a byte each
+        let mut builder = block::Builder::with_encoding(4096, block::Encoding::DeltaPacked);
+        for key in 1000 .. 1100u16 {
+            builder.push(&key.to_be_bytes(), b"").unwrap();
+        }
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+        let mut plain = block::Builder::new(4096);
+        for key in 1000 .. 1100u16 {
+            plain.push(&key.to_be_bytes(), b"").unwrap();
+        }
+        let mut plain_page = Vec::new();
+        plain.write_page(&mut plain_page);
+        let used = |page: &[u8]| page.iter().rposition(|&byte| byte != 0).unwrap();
+        assert!(used(&page) < used(&plain_page));
+    }
+
+    #[test]
+    fn page_overflow() {
+        let mut builder = block::Builder::new(24);
+        builder.push(b"a", b"b").unwrap();
+        assert_eq!(builder.push(b"c", b"d"), Err(block::Error::PageOverflow { page_size: 24, required: 32, }));
+        assert_eq!(builder.items_count(), 1);
+    }
+
+    #[test]
+    fn seal_unseal() {
+        let mut builder = block::Builder::new(32);
+        builder.push(b"a", b"b").unwrap();
+        let mut page = Vec::new();
+        builder.write_page(&mut page);
+        let original = page.clone();
+        block::seal_page(&mut page);
+        assert_eq!(page.len(), 32 + block::CHECKSUM_SIZE);
+        let mut sealed = page.clone();
+        assert_eq!(block::unseal_page(&mut page), Ok(()));
+        assert_eq!(page, original);
+
+        sealed[20] ^= 1;
+        assert!(matches!(block::unseal_page(&mut sealed), Err(block::Error::ChecksumMismatch { .. })));
+        assert_eq!(
+            block::unseal_page(&mut vec![0, 0]),
+            Err(block::Error::PageTruncated { page_size: 2, required: block::CHECKSUM_SIZE, }),
+        );
+    }
+
+    #[test]
+    fn truncated_page() {
+        assert_eq!(
+            block::Block::decode(&[2, 0, 0, 0, 0]).err(),
+            Some(block::Error::PageTruncated { page_size: 5, required: 12, }),
+        );
+    }
+}
+
+mod crc32c {
+    use crate::crc32c;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(crc32c::checksum(b""), 0);
+        assert_eq!(crc32c::checksum(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c::checksum(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c::checksum(&[0xff; 32]), 0x62a8_ab43);
+    }
+
+    #[test]
+    fn extend() {
+        let data = b"The quick brown fox jumps over the lazy dog";
+        let (head, tail) = data.split_at(17);
+        assert_eq!(crc32c::extend(crc32c::checksum(head), tail), crc32c::checksum(data));
+    }
+}
+
+mod codec {
+    use crate::codec;
+
+    fn roundtrip<T>(value: T) where T: codec::Encode + codec::Decode + PartialEq + std::fmt::Debug {
+        let mut encoded = Vec::new();
+        codec::encode(&value, &mut encoded);
+        assert_eq!(codec::decode::<T>(&encoded), Ok(value));
+    }
+
+    #[test]
+    fn integers() {
+        roundtrip(0u8);
+        roundtrip(u16::MAX);
+        roundtrip(0xdead_beef_u32);
+        roundtrip(u64::MAX - 1);
+        roundtrip(u128::MAX);
+        roundtrip(-1i8);
+        roundtrip(i16::MIN);
+        roundtrip(-42i32);
+        roundtrip(i64::MAX);
+        roundtrip(i128::MIN);
+    }
+
+    #[test]
+    fn bytes_and_strings() {
+        roundtrip(Vec::<u8>::new());
+        roundtrip(vec![0u8; 300]);
+        roundtrip(String::from("hello"));
+        roundtrip(String::new());
+    }
+
+    #[test]
+    fn tuples() {
+        roundtrip(());
+        roundtrip((7u64,));
+        roundtrip((7u64, String::from("seven")));
+        roundtrip((b"k".to_vec(), 1u32, String::from("v")));
+        roundtrip((1u8, 2u16, 3u32, (4u64, String::from("nested"))));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(codec::decode::<u32>(&[1, 2]), Err(codec::Error::UnexpectedEof { required: 4, available: 2, }));
+        assert_eq!(codec::decode::<u8>(&[1, 2]), Err(codec::Error::TrailingBytes { count: 1, }));
+        assert_eq!(codec::decode::<String>(&[2, 0xff, 0xfe]), Err(codec::Error::InvalidUtf8));
+        assert_eq!(codec::decode::<Vec<u8>>(&[5, 1]), Err(codec::Error::UnexpectedEof { required: 5, available: 1, }));
+    }
+}
+
+mod verify {
+    use std::{
+        fs,
+        io::Cursor,
+    };
+
+    use crate::{
+        writer::{
+            file,
+            value_log,
+        },
+        block,
+        reader,
+        sketch,
+        trailer,
+        verify,
+    };
+
+    fn write_tree(items: Vec<(u64, u64)>, block_size: usize) -> Vec<u8> {
+        let sketch = sketch::Tree::new(items.len() as u64, block_size);
+        file::write_ordered(&sketch, 256, file::Order::Unchecked, items, Cursor::new(Vec::new())).unwrap().into_inner()
+    }
+
+    #[test]
+    fn clean_tree() {
+        let data = write_tree((0 .. 100).map(|index| (index, index * 10)).collect(), 4);
+        let report = verify::verify_source::<u64, u64, _>(Cursor::new(data)).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.items_checked, 100);
+        assert_eq!(report.blocks_checked, sketch::Tree::new(100, 4).levels().iter().map(|level| level.blocks_count).sum::<u64>());
+    }
+
+    #[test]
+    fn clean_bplus_tree() {
+        let sketch = sketch::Tree::try_with_mode(100, 4, sketch::FillStrategy::TopHeavy, sketch::Mode::BPlus).unwrap();
+        let items = (0 .. 100u64).map(|index| (index, index * 10));
+        let data = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap().into_inner();
+        let report = verify::verify_source::<u64, u64, _>(Cursor::new(data)).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.blocks_checked, sketch.levels().iter().map(|level| level.blocks_count).sum::<u64>());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+            let sketch = sketch::Tree::try_with_mode(17, 3, sketch::FillStrategy::TopHeavy, mode).unwrap();
+            let items = (0 .. 17u64).map(|index| (index / 5, index));
+            let data = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap().into_inner();
+            let report = verify::verify_source::<u64, u64, _>(Cursor::new(data)).unwrap();
+            assert!(report.is_ok(), "{:?}", report.problems);
+            assert_eq!(report.items_checked, sketch.levels().iter().map(|level| level.items_count).sum::<u64>());
+        }
+    }
+
+    #[test]
+    fn unsorted_keys() {
+        let mut items: Vec<_> = (0 .. 17).map(|index| (index, index)).collect();
+        items.swap(2, 3);
+        let report = verify::verify_source::<u64, u64, _>(Cursor::new(write_tree(items, 3))).unwrap();
+        assert_eq!(report.items_checked, 17);
+        assert!(matches!(
+            &report.problems[..],
+            [
+                verify::Problem::KeyOrder { level_index: 1, block_index: 0, item_index: 0, },
+                verify::Problem::SeparatorMismatch { level_index: 1, block_index: 0, item_index: 0, child_block_index: 0, },
+            ],
+        ), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn corrupted_blocks() {
+        let mut data = write_tree((0 .. 17).map(|index| (index, index)).collect(), 3);
+        let trailer = trailer::Trailer::read_from(&mut Cursor::new(&data)).unwrap();
+        let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+        data[blocks_offsets[2][0] as usize + 9] ^= 1;
+        data[blocks_offsets[1][2] as usize + 9] ^= 1;
+        let report = verify::verify_source::<u64, u64, _>(Cursor::new(data)).unwrap();
+        assert_eq!(report.blocks_checked, 4);
+        assert!(matches!(
+            &report.problems[..],
+            [
+                verify::Problem::Read(reader::Error::BlockChecksumMismatch { level_index: 2, block_index: 0, .. }),
+                verify::Problem::Read(reader::Error::BlockChecksumMismatch { level_index: 1, block_index: 2, .. }),
+                verify::Problem::LevelBlocksCountMismatch { level_index: 1, expected: 3, found: 2, },
+                verify::Problem::LevelItemsCountMismatch { level_index: 1, .. },
+                verify::Problem::LevelBlocksCountMismatch { level_index: 2, expected: 2, found: 1, },
+                verify::Problem::LevelItemsCountMismatch { level_index: 2, .. },
+            ],
+        ), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn value_log_tree() {
+        let sketch = sketch::Tree::new(40, 4);
+        let items: Vec<_> = (0 .. 40u64).map(|index| (index, vec![index as u8; 100])).collect();
+        let (tree, log) = value_log::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items, Cursor::new(Vec::new()), Vec::new())
+            .unwrap();
+        let tree = tree.into_inner();
+        assert!(matches!(
+            verify::verify_source::<u64, Vec<u8>, _>(Cursor::new(tree.clone())),
+            Err(verify::Error::Reader(reader::Error::ValueLogRequired)),
+        ));
+
+        let report = verify::verify_source_with_value_log::<u64, Vec<u8>, _, _>(Cursor::new(tree.clone()), Cursor::new(log.clone())).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.items_checked, 40);
+
+        // the first record is one byte of length, the value and its checksum
+        let mut damaged = log.clone();
+        damaged[50] ^= 1;
+        let report = verify::verify_source_with_value_log::<u64, Vec<u8>, _, _>(Cursor::new(tree.clone()), Cursor::new(damaged)).unwrap();
+        assert_eq!(report.items_checked, 39);
+        assert!(matches!(
+            &report.problems[..],
+            [verify::Problem::Read(reader::Error::ValueLogChecksumMismatch { offset: 0, .. })],
+        ), "{:?}", report.problems);
+
+        let truncated = log[.. log.len() - 50].to_vec();
+        let report = verify::verify_source_with_value_log::<u64, Vec<u8>, _, _>(Cursor::new(tree), Cursor::new(truncated)).unwrap();
+        assert_eq!(report.items_checked, 39);
+        assert!(matches!(
+            &report.problems[..],
+            [
+                verify::Problem::Read(reader::Error::ValueLogRead { .. }),
+                verify::Problem::ValueLogLenMismatch { expected, found, },
+            ] if *expected == log.len() as u64 && *found == log.len() as u64 - 50,
+        ), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn verify_path() {
+        let path = std::env::temp_dir().join(format!("bntree-verify-{}.tree", std::process::id()));
+        fs::write(&path, write_tree((0 .. 30).map(|index| (index, index)).collect(), 5)).unwrap();
+        let report = crate::verify::<u64, u64, _>(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(report.unwrap().is_ok());
+
+        assert!(matches!(crate::verify::<u64, u64, _>(&path), Err(verify::Error::Open(..))));
+    }
 }
@@ -0,0 +1,384 @@
+use std::io::{
+    self,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+
+use crate::{
+    crc32c,
+    sketch,
+};
+
+pub const MAGIC: [u8; 8] = *b"BNTREE\r\n";
+// versions 1 to 10 were used by earlier layouts, never reuse them
+pub const FORMAT_VERSION: u32 = 11;
+pub const HEADER_SIZE: u64 = 16;
+
+const TAIL_SIZE: u64 = 24;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Trailer {
+    pub items_total: u64,
+    pub block_sizes: sketch::BlockSizes,
+    pub block_fill: sketch::BlockFill,
+    pub mode: sketch::Mode,
+    pub page_size: usize,
+    pub overflow_pages_count: u64,
+    pub value_log_len: Option<u64>,
+    pub key_codec: String,
+    pub value_codec: String,
+    pub levels: Vec<sketch::Level>,
+    pub blocks_items_counts: Vec<Vec<usize>>,
+    pub layout: Layout,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Layout {
+    Indexed {
+        blocks_offsets: Vec<Vec<u64>>,
+    },
+    LevelFiles {
+        file_names: Vec<String>,
+    },
+    Regions,
+}
+
+const BLOCK_SIZES_UNIFORM: u64 = 0;
+const BLOCK_SIZES_LEAF_INNER: u64 = 1;
+const BLOCK_SIZES_PER_LEVEL: u64 = 2;
+
+const BLOCK_FILL_PACKED: u64 = 0;
+const BLOCK_FILL_EVEN: u64 = 1;
+const BLOCK_FILL_EXPLICIT: u64 = 2;
+
+const MODE_BTREE: u64 = 0;
+const MODE_BPLUS: u64 = 1;
+
+const VALUE_LOG_NONE: u64 = 0;
+const VALUE_LOG_SEPARATE: u64 = 1;
+
+const LAYOUT_INDEXED: u64 = 0;
+const LAYOUT_LEVEL_FILES: u64 = 1;
+const LAYOUT_REGIONS: u64 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    SeekHeader(io::Error),
+    ReadHeader(io::Error),
+    SeekTail(io::Error),
+    ReadTail(io::Error),
+    SeekLevels(io::Error),
+    ReadLevels(io::Error),
+    InvalidMagic {
+        magic: [u8; 8],
+    },
+    UnsupportedVersion {
+        version: u32,
+        supported: u32,
+    },
+    TailOffsetOutOfBounds {
+        levels_offset: u64,
+        tail_offset: u64,
+    },
+    ValueOverflow {
+        value: u64,
+    },
+    InvalidCodecId(std::string::FromUtf8Error),
+    InvalidFileName(std::string::FromUtf8Error),
+    UnknownBlockSizes {
+        tag: u64,
+    },
+    UnknownBlockFill {
+        tag: u64,
+    },
+    UnknownMode {
+        tag: u64,
+    },
+    UnknownLayout {
+        tag: u64,
+    },
+    UnknownValueLog {
+        tag: u64,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+pub fn write_header<W>(sink: &mut W) -> io::Result<()> where W: Write {
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    sink.write_all(&header)
+}
+
+pub fn read_header<R>(source: &mut R) -> Result<(), Error> where R: Read + Seek {
+    source.seek(SeekFrom::Start(0))
+        .map_err(Error::SeekHeader)?;
+    let mut header = [0; HEADER_SIZE as usize];
+    source.read_exact(&mut header)
+        .map_err(Error::ReadHeader)?;
+    check_magic_version(&header[0 .. 8], &header[8 .. 12])
+}
+
+impl Trailer {
+    pub fn write_to<W>(&self, sink: &mut W, levels_offset: u64) -> io::Result<()> where W: Write {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&self.items_total.to_le_bytes());
+        match &self.block_sizes {
+            sketch::BlockSizes::Uniform(block_size) => {
+                buffer.extend_from_slice(&BLOCK_SIZES_UNIFORM.to_le_bytes());
+                buffer.extend_from_slice(&(*block_size as u64).to_le_bytes());
+            },
+            sketch::BlockSizes::LeafInner { leaf, inner, } => {
+                buffer.extend_from_slice(&BLOCK_SIZES_LEAF_INNER.to_le_bytes());
+                buffer.extend_from_slice(&(*leaf as u64).to_le_bytes());
+                buffer.extend_from_slice(&(*inner as u64).to_le_bytes());
+            },
+            sketch::BlockSizes::PerLevel(block_sizes) => {
+                buffer.extend_from_slice(&BLOCK_SIZES_PER_LEVEL.to_le_bytes());
+                buffer.extend_from_slice(&(block_sizes.len() as u64).to_le_bytes());
+                for &block_size in block_sizes {
+                    buffer.extend_from_slice(&(block_size as u64).to_le_bytes());
+                }
+            },
+        }
+        let block_fill_tag = match self.block_fill {
+            sketch::BlockFill::Packed =>
+                BLOCK_FILL_PACKED,
+            sketch::BlockFill::Even =>
+                BLOCK_FILL_EVEN,
+            sketch::BlockFill::Explicit =>
+                BLOCK_FILL_EXPLICIT,
+        };
+        buffer.extend_from_slice(&block_fill_tag.to_le_bytes());
+        let mode_tag = match self.mode {
+            sketch::Mode::BTree =>
+                MODE_BTREE,
+            sketch::Mode::BPlus =>
+                MODE_BPLUS,
+        };
+        buffer.extend_from_slice(&mode_tag.to_le_bytes());
+        buffer.extend_from_slice(&(self.page_size as u64).to_le_bytes());
+        buffer.extend_from_slice(&self.overflow_pages_count.to_le_bytes());
+        match self.value_log_len {
+            None =>
+                buffer.extend_from_slice(&VALUE_LOG_NONE.to_le_bytes()),
+            Some(value_log_len) => {
+                buffer.extend_from_slice(&VALUE_LOG_SEPARATE.to_le_bytes());
+                buffer.extend_from_slice(&value_log_len.to_le_bytes());
+            },
+        }
+        put_string(&mut buffer, &self.key_codec);
+        put_string(&mut buffer, &self.value_codec);
+        buffer.extend_from_slice(&(self.levels.len() as u64).to_le_bytes());
+        for level in &self.levels {
+            buffer.extend_from_slice(&level.blocks_count.to_le_bytes());
+            buffer.extend_from_slice(&level.items_count.to_le_bytes());
+        }
+        for &items_count in self.blocks_items_counts.iter().flatten() {
+            buffer.extend_from_slice(&(items_count as u64).to_le_bytes());
+        }
+        match &self.layout {
+            Layout::Indexed { blocks_offsets, } => {
+                buffer.extend_from_slice(&LAYOUT_INDEXED.to_le_bytes());
+                for &offset in blocks_offsets.iter().flatten() {
+                    buffer.extend_from_slice(&offset.to_le_bytes());
+                }
+            },
+            Layout::LevelFiles { file_names, } => {
+                buffer.extend_from_slice(&LAYOUT_LEVEL_FILES.to_le_bytes());
+                for file_name in file_names {
+                    put_string(&mut buffer, file_name);
+                }
+            },
+            Layout::Regions =>
+                buffer.extend_from_slice(&LAYOUT_REGIONS.to_le_bytes()),
+        }
+        buffer.extend_from_slice(&levels_offset.to_le_bytes());
+        buffer.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        let checksum = crc32c::checksum(&buffer);
+        buffer.extend_from_slice(&checksum.to_le_bytes());
+        buffer.extend_from_slice(&MAGIC);
+        sink.write_all(&buffer)
+    }
+
+    pub fn read_from<R>(source: &mut R) -> Result<Trailer, Error> where R: Read + Seek {
+        let tail_offset = source.seek(SeekFrom::End(-(TAIL_SIZE as i64)))
+            .map_err(Error::SeekTail)?;
+        let mut tail = [0; TAIL_SIZE as usize];
+        source.read_exact(&mut tail)
+            .map_err(Error::ReadTail)?;
+        check_magic_version(&tail[16 .. 24], &tail[8 .. 12])?;
+        let levels_offset = read_u64(&tail[0 .. 8]);
+        if levels_offset > tail_offset {
+            return Err(Error::TailOffsetOutOfBounds { levels_offset, tail_offset, });
+        }
+
+        source.seek(SeekFrom::Start(levels_offset))
+            .map_err(Error::SeekLevels)?;
+        let mut levels_buf = vec![0; (tail_offset - levels_offset) as usize];
+        source.read_exact(&mut levels_buf)
+            .map_err(Error::ReadLevels)?;
+        let expected = read_u32(&tail[12 .. 16]);
+        let actual = crc32c::extend(crc32c::checksum(&levels_buf), &tail[0 .. 12]);
+        if actual != expected {
+            return Err(Error::ChecksumMismatch { expected, actual, });
+        }
+        let mut cursor = &levels_buf[..];
+        let items_total = take_u64(&mut cursor)?;
+        let block_sizes = match take_u64(&mut cursor)? {
+            BLOCK_SIZES_UNIFORM =>
+                sketch::BlockSizes::Uniform(take_usize(&mut cursor)?),
+            BLOCK_SIZES_LEAF_INNER => {
+                let leaf = take_usize(&mut cursor)?;
+                let inner = take_usize(&mut cursor)?;
+                sketch::BlockSizes::LeafInner { leaf, inner, }
+            },
+            BLOCK_SIZES_PER_LEVEL => {
+                let sizes_count = take_usize(&mut cursor)?;
+                let mut block_sizes = Vec::with_capacity(sizes_count.min(cursor.len() / 8));
+                for _ in 0 .. sizes_count {
+                    block_sizes.push(take_usize(&mut cursor)?);
+                }
+                sketch::BlockSizes::PerLevel(block_sizes)
+            },
+            tag =>
+                return Err(Error::UnknownBlockSizes { tag, }),
+        };
+        let block_fill = match take_u64(&mut cursor)? {
+            BLOCK_FILL_PACKED =>
+                sketch::BlockFill::Packed,
+            BLOCK_FILL_EVEN =>
+                sketch::BlockFill::Even,
+            BLOCK_FILL_EXPLICIT =>
+                sketch::BlockFill::Explicit,
+            tag =>
+                return Err(Error::UnknownBlockFill { tag, }),
+        };
+        let mode = match take_u64(&mut cursor)? {
+            MODE_BTREE =>
+                sketch::Mode::BTree,
+            MODE_BPLUS =>
+                sketch::Mode::BPlus,
+            tag =>
+                return Err(Error::UnknownMode { tag, }),
+        };
+        let page_size = take_usize(&mut cursor)?;
+        let overflow_pages_count = take_u64(&mut cursor)?;
+        let value_log_len = match take_u64(&mut cursor)? {
+            VALUE_LOG_NONE =>
+                None,
+            VALUE_LOG_SEPARATE =>
+                Some(take_u64(&mut cursor)?),
+            tag =>
+                return Err(Error::UnknownValueLog { tag, }),
+        };
+        let key_codec = take_string(&mut cursor)
+            .and_then(|bytes| String::from_utf8(bytes).map_err(Error::InvalidCodecId))?;
+        let value_codec = take_string(&mut cursor)
+            .and_then(|bytes| String::from_utf8(bytes).map_err(Error::InvalidCodecId))?;
+        let levels_count = take_usize(&mut cursor)?;
+        let mut levels = Vec::with_capacity(levels_count.min(cursor.len() / 16));
+        for index in 0 .. levels_count {
+            let blocks_count = take_usize(&mut cursor)? as u64;
+            let items_count = take_u64(&mut cursor)?;
+            levels.push(sketch::Level { index, blocks_count, items_count, });
+        }
+        let mut blocks_items_counts = Vec::new();
+        if block_fill == sketch::BlockFill::Explicit {
+            for level in &levels {
+                let mut items_counts = Vec::with_capacity((level.blocks_count as usize).min(cursor.len() / 8));
+                for _ in 0 .. level.blocks_count {
+                    items_counts.push(take_usize(&mut cursor)?);
+                }
+                blocks_items_counts.push(items_counts);
+            }
+        }
+        let layout = match take_u64(&mut cursor)? {
+            LAYOUT_INDEXED => {
+                let mut blocks_offsets = Vec::with_capacity(levels.len());
+                for level in &levels {
+                    let mut level_offsets = Vec::with_capacity((level.blocks_count as usize).min(cursor.len() / 8));
+                    for _ in 0 .. level.blocks_count {
+                        level_offsets.push(take_u64(&mut cursor)?);
+                    }
+                    blocks_offsets.push(level_offsets);
+                }
+                Layout::Indexed { blocks_offsets, }
+            },
+            LAYOUT_LEVEL_FILES => {
+                let mut file_names = Vec::with_capacity(levels.len());
+                for _ in &levels {
+                    let file_name = take_string(&mut cursor)
+                        .and_then(|bytes| String::from_utf8(bytes).map_err(Error::InvalidFileName))?;
+                    file_names.push(file_name);
+                }
+                Layout::LevelFiles { file_names, }
+            },
+            LAYOUT_REGIONS =>
+                Layout::Regions,
+            tag =>
+                return Err(Error::UnknownLayout { tag, }),
+        };
+
+        Ok(Trailer { items_total, block_sizes, block_fill, mode, page_size, overflow_pages_count, value_log_len, key_codec, value_codec, levels, blocks_items_counts, layout, })
+    }
+}
+
+fn check_magic_version(magic_bytes: &[u8], version_bytes: &[u8]) -> Result<(), Error> {
+    let mut magic = [0; 8];
+    magic.copy_from_slice(magic_bytes);
+    if magic != MAGIC {
+        return Err(Error::InvalidMagic { magic, });
+    }
+    let version = read_u32(version_bytes);
+    if version != FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion { version, supported: FORMAT_VERSION, });
+    }
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(bytes);
+    u32::from_le_bytes(value)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(bytes);
+    u64::from_le_bytes(value)
+}
+
+fn take_u64(cursor: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = [0; 8];
+    cursor.read_exact(&mut value)
+        .map_err(Error::ReadLevels)?;
+    Ok(u64::from_le_bytes(value))
+}
+
+fn take_usize(cursor: &mut &[u8]) -> Result<usize, Error> {
+    let value = take_u64(cursor)?;
+    usize::try_from(value)
+        .map_err(|_| Error::ValueOverflow { value, })
+}
+
+fn take_string(cursor: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let len = take_usize(cursor)?;
+    if len > cursor.len() {
+        return Err(Error::ReadLevels(io::ErrorKind::UnexpectedEof.into()));
+    }
+    let (bytes, rest) = cursor.split_at(len);
+    *cursor = rest;
+    Ok(bytes.to_vec())
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
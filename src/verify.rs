@@ -0,0 +1,300 @@
+use std::{
+    fs,
+    io::{
+        self,
+        BufReader,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    writer::plan,
+    block,
+    codec,
+    reader,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Open(io::Error),
+    ValueLogOpen(io::Error),
+    ValueLogSeek(io::Error),
+    Reader(reader::Error),
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub blocks_checked: u64,
+    pub items_checked: u64,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    Read(reader::Error),
+    BlockItemsCountMismatch {
+        level_index: usize,
+        block_index: usize,
+        expected: usize,
+        found: usize,
+    },
+    LevelBlocksCountMismatch {
+        level_index: usize,
+        expected: u64,
+        found: u64,
+    },
+    LevelItemsCountMismatch {
+        level_index: usize,
+        expected: u64,
+        found: u64,
+    },
+    KeyOrder {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+    },
+    SeparatorMismatch {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        child_block_index: usize,
+    },
+    ValueLogLenMismatch {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+struct LevelState<K> {
+    page: Vec<u8>,
+    loaded: bool,
+    blocks_found: u64,
+    items_found: u64,
+    prev_key: Option<K>,
+    first_key: Option<K>,
+    finished: Option<FinishedBlock<K>>,
+}
+
+struct FinishedBlock<K> {
+    block_index: usize,
+    first_key: K,
+    last_key: K,
+}
+
+pub fn verify<K, V, P>(path: P) -> Result<Report, Error>
+where K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+      P: AsRef<Path>,
+{
+    let file = fs::File::open(path)
+        .map_err(Error::Open)?;
+    verify_source::<K, V, _>(BufReader::new(file))
+}
+
+pub fn verify_source<K, V, R>(source: R) -> Result<Report, Error>
+where K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+      R: Read + Seek,
+{
+    let reader: reader::Reader<_, K, V> = reader::Reader::open(source)
+        .map_err(Error::Reader)?;
+    Ok(verify_reader(reader))
+}
+
+pub fn verify_with_value_log<K, V, P, Q>(path: P, value_log_path: Q) -> Result<Report, Error>
+where K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+      P: AsRef<Path>,
+      Q: AsRef<Path>,
+{
+    let file = fs::File::open(path)
+        .map_err(Error::Open)?;
+    let value_log = fs::File::open(value_log_path)
+        .map_err(Error::ValueLogOpen)?;
+    verify_source_with_value_log::<K, V, _, _>(BufReader::new(file), BufReader::new(value_log))
+}
+
+// Leaf items are decoded through the log, so every log ref is checked against the log length from the trailer
+// along with its record checksum, and a log of some other length is reported too.
+pub fn verify_source_with_value_log<K, V, R, L>(source: R, mut value_log: L) -> Result<Report, Error>
+where K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+      R: Read + Seek,
+      L: Read + Seek,
+{
+    let found = value_log.seek(SeekFrom::End(0))
+        .map_err(Error::ValueLogSeek)?;
+    let reader: reader::Reader<_, K, V> = reader::Reader::open_with_value_log(source, value_log)
+        .map_err(Error::Reader)?;
+    let expected = reader.value_log_len().unwrap_or(0);
+    let mut report = verify_reader(reader);
+    if found != expected {
+        report.problems.push(Problem::ValueLogLenMismatch { expected, found, });
+    }
+    Ok(report)
+}
+
+pub fn verify_level_files<K, V, P>(dir: P) -> Result<Report, Error>
+where K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+      P: AsRef<Path>,
+{
+    let reader: reader::Reader<_, K, V> = reader::Reader::open_level_files(dir)
+        .map_err(Error::Reader)?;
+    Ok(verify_reader(reader))
+}
+
+pub fn verify_reader<S, K, V>(mut reader: reader::Reader<S, K, V>) -> Report
+where S: reader::source::BlockSource,
+      K: codec::Decode + Ord + Clone,
+      V: codec::Decode,
+{
+    let sketch = reader.shared_sketch();
+    let mut report = Report::default();
+    let mut levels: Vec<LevelState<K>> = sketch.levels()
+        .iter()
+        .map(|_| LevelState {
+            page: Vec::new(),
+            loaded: false,
+            blocks_found: 0,
+            items_found: 0,
+            prev_key: None,
+            first_key: None,
+            finished: None,
+        })
+        .collect();
+    let mut last_key: Option<K> = None;
+
+    let mut plan_ctx = plan::Context::new(Arc::clone(&sketch));
+    let mut kont = plan::Script::boot();
+    loop {
+        match kont.next.step(&mut plan_ctx) {
+            plan::Instruction::Perform(plan::Perform { op: plan::Op::BlockStart { items_count, }, level_index, block_index, next, }) => {
+                let level = &mut levels[level_index];
+                level.loaded = false;
+                match reader.read_block(level_index, block_index, &mut level.page) {
+                    Ok(()) =>
+                        match block::Block::decode(&level.page) {
+                            Ok(block) => {
+                                if block.items_count() != items_count {
+                                    report.problems.push(Problem::BlockItemsCountMismatch {
+                                        level_index,
+                                        block_index,
+                                        expected: items_count,
+                                        found: block.items_count(),
+                                    });
+                                }
+                                level.loaded = true;
+                                level.blocks_found += 1;
+                                level.items_found += block.items_count() as u64;
+                                report.blocks_checked += 1;
+                            },
+                            Err(error) =>
+                                report.problems.push(Problem::Read(reader::Error::BlockDecode { level_index, block_index, error, })),
+                        },
+                    Err(error) =>
+                        report.problems.push(Problem::Read(error)),
+                }
+                kont = next;
+            },
+            plan::Instruction::Perform(plan::Perform { op: plan::Op::BlockItem { index: item_index, }, level_index, block_index, next, }) => {
+                kont = next;
+                if !levels[level_index].loaded {
+                    continue;
+                }
+                let is_separator = sketch.is_separators_level(level_index);
+                let item = block::Block::decode(&levels[level_index].page)
+                    .map_err(|error| reader::Error::BlockDecode { level_index, block_index, error, })
+                    .and_then(|block| if is_separator {
+                        reader::decode_key::<K>(&block, level_index, block_index, item_index)
+                    } else {
+                        reader.decode_item(&block, level_index, block_index, item_index)
+                            .map(|(key, _value)| key)
+                    });
+                let key = match item {
+                    Ok(key) =>
+                        key,
+                    Err(error) => {
+                        report.problems.push(Problem::Read(error));
+                        continue;
+                    },
+                };
+                report.items_checked += 1;
+
+                // equal adjacent keys are fine, a tree may hold duplicates and a B+ separator repeats the last key of its child subtree
+                let out_of_order = last_key.as_ref()
+                    .is_some_and(|last_key| last_key > &key);
+                if out_of_order {
+                    report.problems.push(Problem::KeyOrder { level_index, block_index, item_index, });
+                }
+
+                let child = reader.child_block_index(level_index, block_index, item_index)
+                    .and_then(|child_block_index| {
+                        levels[level_index + 1].finished.as_ref()
+                            .filter(|finished| finished.block_index == child_block_index)
+                    });
+                if let Some(child) = child {
+                    let prev_key_ok = levels[level_index].prev_key.as_ref()
+                        .is_none_or(|prev_key| prev_key <= &child.first_key);
+                    let child_key_ok = if is_separator { child.last_key == key } else { child.last_key <= key };
+                    if !prev_key_ok || !child_key_ok {
+                        report.problems.push(Problem::SeparatorMismatch {
+                            level_index,
+                            block_index,
+                            item_index,
+                            child_block_index: child.block_index,
+                        });
+                    }
+                }
+
+                let level = &mut levels[level_index];
+                if level.first_key.is_none() {
+                    level.first_key = Some(key.clone());
+                }
+                level.prev_key = Some(key.clone());
+                last_key = Some(key);
+            },
+            plan::Instruction::Perform(plan::Perform { op: plan::Op::BlockFinish, level_index, block_index, next, }) => {
+                let level = &mut levels[level_index];
+                level.finished = match (level.first_key.take(), level.prev_key.take()) {
+                    (Some(first_key), Some(last_key)) =>
+                        Some(FinishedBlock { block_index, first_key, last_key, }),
+                    _ =>
+                        None,
+                };
+                kont = next;
+            },
+            plan::Instruction::Done =>
+                break,
+        }
+    }
+
+    for (level, state) in sketch.levels().iter().zip(levels.iter()) {
+        if state.blocks_found != level.blocks_count {
+            report.problems.push(Problem::LevelBlocksCountMismatch {
+                level_index: level.index,
+                expected: level.blocks_count,
+                found: state.blocks_found,
+            });
+        }
+        if state.items_found != level.items_count {
+            report.problems.push(Problem::LevelItemsCountMismatch {
+                level_index: level.index,
+                expected: level.items_count,
+                found: state.items_found,
+            });
+        }
+    }
+
+    report
+}
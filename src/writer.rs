@@ -1,5 +1,12 @@
 pub mod plan;
 pub mod fold;
+pub mod file;
+pub mod level_files;
+pub mod regions;
+pub mod value_log;
+pub mod spool;
+pub mod sort;
+pub mod builder;
 
 #[cfg(test)]
 mod tests;
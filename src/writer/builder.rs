@@ -0,0 +1,368 @@
+use std::{
+    cmp::max,
+    env,
+    io::{
+        Seek,
+        Write,
+    },
+    path::PathBuf,
+};
+
+use crate::{
+    writer::{
+        file,
+        sort,
+        spool,
+        value_log,
+    },
+    block,
+    codec,
+    sketch,
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+pub const DEFAULT_SPOOL_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+pub const DEFAULT_SORT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    Write(file::Error),
+    Spool(spool::Error),
+    Sort(sort::Error),
+    Sketch(sketch::Error),
+    ByteBudgetNeedsItems,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeBuilder {
+    block_sizes: sketch::BlockSizes,
+    fill: sketch::FillStrategy,
+    mode: sketch::Mode,
+    byte_budget: bool,
+    page_size: usize,
+    spool_memory_limit: usize,
+    spool_dir: PathBuf,
+    sort_memory_limit: usize,
+    sort_fan_in: usize,
+    order: file::Order,
+    encoding: block::Encoding,
+}
+
+impl TreeBuilder {
+    pub fn new(block_size: usize) -> TreeBuilder {
+        TreeBuilder {
+            block_sizes: sketch::BlockSizes::Uniform(block_size),
+            fill: sketch::FillStrategy::TopHeavy,
+            mode: sketch::Mode::BTree,
+            byte_budget: false,
+            page_size: DEFAULT_PAGE_SIZE,
+            spool_memory_limit: DEFAULT_SPOOL_MEMORY_LIMIT,
+            spool_dir: env::temp_dir(),
+            sort_memory_limit: DEFAULT_SORT_MEMORY_LIMIT,
+            sort_fan_in: sort::DEFAULT_FAN_IN,
+            order: file::Order::NonDecreasing,
+            encoding: block::Encoding::Plain,
+        }
+    }
+
+    pub fn block_sizes(mut self, block_sizes: sketch::BlockSizes) -> TreeBuilder {
+        self.block_sizes = block_sizes;
+        self
+    }
+
+    pub fn fill(mut self, fill: sketch::FillStrategy) -> TreeBuilder {
+        self.fill = fill;
+        self
+    }
+
+    pub fn mode(mut self, mode: sketch::Mode) -> TreeBuilder {
+        self.mode = mode;
+        self
+    }
+
+    pub fn bplus(self) -> TreeBuilder {
+        self.mode(sketch::Mode::BPlus)
+    }
+
+    pub fn byte_budget(mut self) -> TreeBuilder {
+        self.byte_budget = true;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> TreeBuilder {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn spool_memory_limit(mut self, spool_memory_limit: usize) -> TreeBuilder {
+        self.spool_memory_limit = spool_memory_limit;
+        self
+    }
+
+    pub fn spool_dir<P>(mut self, spool_dir: P) -> TreeBuilder where P: Into<PathBuf> {
+        self.spool_dir = spool_dir.into();
+        self
+    }
+
+    pub fn sort_memory_limit(mut self, sort_memory_limit: usize) -> TreeBuilder {
+        self.sort_memory_limit = sort_memory_limit;
+        self
+    }
+
+    pub fn sort_fan_in(mut self, sort_fan_in: usize) -> TreeBuilder {
+        self.sort_fan_in = sort_fan_in;
+        self
+    }
+
+    pub fn order(mut self, order: file::Order) -> TreeBuilder {
+        self.order = order;
+        self
+    }
+
+    pub fn strictly_increasing(self) -> TreeBuilder {
+        self.order(file::Order::StrictlyIncreasing)
+    }
+
+    pub fn encoding(mut self, encoding: block::Encoding) -> TreeBuilder {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn front_coding(self, restart_interval: usize) -> TreeBuilder {
+        self.encoding(block::Encoding::FrontCoded { restart_interval, })
+    }
+
+    pub fn delta_packed(self) -> TreeBuilder {
+        self.encoding(block::Encoding::DeltaPacked)
+    }
+
+    pub fn sketch(&self, items_total: u64) -> Result<sketch::Tree, Error> {
+        if self.byte_budget {
+            return Err(Error::ByteBudgetNeedsItems);
+        }
+        sketch::Tree::try_with_sizes(items_total, self.block_sizes.clone(), self.fill, self.mode)
+            .map_err(Error::Sketch)
+    }
+
+    pub fn build<W, I, K, V>(&self, items: I, sink: W) -> Result<W, Error>
+    where W: Write + Seek,
+          I: IntoIterator<Item = (K, V)>,
+          I::IntoIter: ExactSizeIterator,
+          K: codec::Encode + codec::Decode + Ord,
+          V: codec::Encode,
+    {
+        if self.byte_budget {
+            return self.build_stream(items, sink);
+        }
+        let items = items.into_iter();
+        let sketch = self.sketch(items.len() as u64)?;
+        file::write_encoded(&sketch, self.page_size, self.order, self.encoding, items, sink)
+            .map_err(Error::Write)
+    }
+
+    pub fn build_with_value_log<W, L, I, K, V>(&self, items: I, sink: W, log: L) -> Result<(W, L), Error>
+    where W: Write + Seek,
+          L: Write,
+          I: IntoIterator<Item = (K, V)>,
+          I::IntoIter: ExactSizeIterator,
+          K: codec::Encode + codec::Decode + Ord,
+          V: codec::Encode,
+    {
+        if !self.byte_budget {
+            let items = items.into_iter();
+            let sketch = self.sketch(items.len() as u64)?;
+            return value_log::write(&sketch, self.page_size, self.order, self.encoding, items, sink, log)
+                .map_err(Error::Write);
+        }
+        let (mut replay, max_footprint) = self.spool(items, true)?;
+        let sketch = self.replay_sketch::<K>(&mut replay, max_footprint, true)?;
+        self.write_replay::<K, V, _, _>(&mut replay, |items| {
+            value_log::write(&sketch, self.page_size, self.order, self.encoding, items, sink, log)
+        })
+    }
+
+    pub fn build_stream<W, I, K, V>(&self, items: I, sink: W) -> Result<W, Error>
+    where W: Write + Seek,
+          I: IntoIterator<Item = (K, V)>,
+          K: codec::Encode + codec::Decode + Ord,
+          V: codec::Encode,
+    {
+        let (mut replay, max_footprint) = self.spool(items, false)?;
+        let sketch = self.replay_sketch::<K>(&mut replay, max_footprint, false)?;
+        self.write_replay::<K, V, _, _>(&mut replay, |items| {
+            file::write_encoded(&sketch, self.page_size, self.order, self.encoding, items, sink)
+        })
+    }
+
+    pub fn build_unsorted<W, I, K, V>(&self, items: I, duplicates: sort::Duplicates<K, V>, sink: W) -> Result<W, Error>
+    where W: Write + Seek,
+          I: IntoIterator<Item = (K, V)>,
+          K: codec::Encode + codec::Decode + Ord,
+          V: codec::Encode + codec::Decode,
+    {
+        file::check_encoding::<K>(self.encoding, self.page_size)
+            .map_err(Error::Write)?;
+        let mut sorter = sort::Sorter::with_fan_in(self.sort_memory_limit, self.sort_fan_in, self.spool_dir.clone());
+        for (key, value) in items {
+            sorter.push(key, value)
+                .map_err(Error::Sort)?;
+        }
+        let mut merge = sorter.finish(duplicates)
+            .map_err(Error::Sort)?;
+
+        // the runs are merged once to size the tree and once more to feed the fold, the merged stream is never stored
+        let mut key_buf = Vec::new();
+        let mut value_buf = Vec::new();
+        let mut items_total = 0;
+        let mut max_footprint = 0;
+        while let Some((key, value)) = merge.next_item().map_err(Error::Sort)? {
+            items_total += 1;
+            if self.byte_budget {
+                codec::encode(&key, &mut key_buf);
+                codec::encode(&value, &mut value_buf);
+                max_footprint = max(max_footprint, footprint(self.page_size, self.encoding, false, &key_buf, &value_buf).item);
+            }
+        }
+        merge.rewind()
+            .map_err(Error::Sort)?;
+        let sketch = if self.byte_budget {
+            let sketch = self.footprints_sketch::<K, _>(items_total, max_footprint, false, |key_buf, value_buf| {
+                let Some((key, value)) = merge.next_item().map_err(Error::Sort)? else {
+                    return Ok(false);
+                };
+                codec::encode(&key, key_buf);
+                codec::encode(&value, value_buf);
+                Ok(true)
+            })?;
+            merge.rewind()
+                .map_err(Error::Sort)?;
+            sketch
+        } else {
+            self.sketch(items_total)?
+        };
+
+        let mut items = merge.items();
+        let result = file::write_encoded(&sketch, self.page_size, self.order, self.encoding, &mut items, sink);
+        if let Some(error) = items.take_error() {
+            return Err(Error::Sort(error));
+        }
+        result.map_err(Error::Write)
+    }
+
+    // item sizes are only known after encoding, so the items are spooled for the second pass
+    fn spool<I, K, V>(&self, items: I, value_log: bool) -> Result<(spool::Replay, usize), Error>
+    where I: IntoIterator<Item = (K, V)>,
+          K: codec::Encode,
+          V: codec::Encode,
+    {
+        file::check_encoding::<K>(self.encoding, self.page_size)
+            .map_err(Error::Write)?;
+        let mut spool = spool::Spool::new(self.spool_memory_limit, self.spool_dir.clone());
+        let mut key_buf = Vec::new();
+        let mut value_buf = Vec::new();
+        let mut max_footprint = 0;
+        for (key, value) in items {
+            codec::encode(&key, &mut key_buf);
+            codec::encode(&value, &mut value_buf);
+            spool.push(&key_buf, &value_buf)
+                .map_err(Error::Spool)?;
+            max_footprint = max(max_footprint, footprint(self.page_size, self.encoding, value_log, &key_buf, &value_buf).item);
+        }
+        let replay = spool.into_replay()
+            .map_err(Error::Spool)?;
+        Ok((replay, max_footprint))
+    }
+
+    fn replay_sketch<K>(&self, replay: &mut spool::Replay, max_footprint: usize, value_log: bool) -> Result<sketch::Tree, Error> where K: codec::Codec {
+        let items_total = replay.items_remain();
+        if !self.byte_budget {
+            return self.sketch(items_total);
+        }
+        let sketch = self.footprints_sketch::<K, _>(items_total, max_footprint, value_log, |key_buf, value_buf| {
+            replay.next_item(key_buf, value_buf)
+                .map_err(Error::Spool)
+        })?;
+        replay.rewind()
+            .map_err(Error::Spool)?;
+        Ok(sketch)
+    }
+
+    // sizes a byte-budget tree from the encoded items `next_item` yields in order
+    fn footprints_sketch<K, F>(&self, items_total: u64, max_footprint: usize, value_log: bool, mut next_item: F) -> Result<sketch::Tree, Error>
+    where K: codec::Codec,
+          F: FnMut(&mut Vec<u8>, &mut Vec<u8>) -> Result<bool, Error>,
+    {
+        let mut key_buf = Vec::new();
+        let mut value_buf = Vec::new();
+        let mut prev_key = Vec::new();
+        let mut replay_error = None;
+        let footprints = std::iter::from_fn(|| match next_item(&mut key_buf, &mut value_buf) {
+            Ok(true) => {
+                let footprint = footprint(self.page_size, self.encoding, value_log, &key_buf, &value_buf);
+                let shared = self.encoding.shared_len(&prev_key, &key_buf);
+                let delta = self.encoding.key_delta(&prev_key, &key_buf);
+                prev_key.clone_from(&key_buf);
+                Some(sketch::Footprint { shared, delta, ..footprint })
+            },
+            Ok(false) =>
+                None,
+            Err(error) => {
+                replay_error = Some(error);
+                None
+            },
+        });
+        let sketch = self.budget_sketch::<K, _>(items_total, max_footprint, footprints);
+        if let Some(error) = replay_error {
+            return Err(error);
+        }
+        sketch
+    }
+
+    fn budget_sketch<K, I>(&self, items_total: u64, max_footprint: usize, footprints: I) -> Result<sketch::Tree, Error>
+    where K: codec::Codec,
+          I: IntoIterator<Item = sketch::Footprint>,
+    {
+        let restart_interval = match self.encoding {
+            block::Encoding::FrontCoded { restart_interval, } =>
+                restart_interval,
+            _ =>
+                1,
+        };
+        let packed_key_len = match self.encoding {
+            block::Encoding::DeltaPacked =>
+                block::packed_key_len(&K::codec_id()).unwrap_or(0),
+            _ =>
+                0,
+        };
+        let budget = sketch::Budget {
+            block_budget: self.encoding.page_budget(self.page_size),
+            max_footprint,
+            restart_interval,
+            packed_key_len,
+        };
+        sketch::Tree::try_with_budget(items_total, budget, self.mode, footprints)
+            .map_err(Error::Sketch)
+    }
+
+    fn write_replay<K, V, F, R>(&self, replay: &mut spool::Replay, write: F) -> Result<R, Error>
+    where K: codec::Decode,
+          F: FnOnce(&mut spool::ReplayItems<'_, K, V>) -> Result<R, file::Error>,
+    {
+        let mut items = replay.items::<K, V>();
+        let result = write(&mut items);
+        if let Some(error) = items.take_error() {
+            return Err(Error::Spool(error));
+        }
+        result.map_err(Error::Write)
+    }
+}
+
+fn footprint(page_size: usize, encoding: block::Encoding, value_log: bool, key: &[u8], value: &[u8]) -> sketch::Footprint {
+    let stored_value_len = if value_log { block::LOG_REF_SIZE } else { block::stored_value_len(page_size, value.len()) };
+    sketch::Footprint {
+        item: encoding.item_footprint(key.len(), stored_value_len),
+        separator: encoding.item_footprint(key.len(), 0),
+        shared: 0,
+        delta: 0,
+    }
+}
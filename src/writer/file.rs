@@ -0,0 +1,407 @@
+use std::{
+    cmp::Ordering,
+    io::{
+        self,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    sync::Arc,
+};
+
+use crate::{
+    writer::{
+        plan,
+        fold,
+    },
+    block,
+    codec,
+    sketch,
+    trailer,
+};
+
+pub use crate::sketch::ItemPosition;
+
+#[derive(Debug)]
+pub enum Error {
+    Fold(fold::Error),
+    ItemsExhausted {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+    },
+    ItemsLeftover,
+    KeyOrder {
+        previous: ItemPosition,
+        current: ItemPosition,
+    },
+    DuplicateKey {
+        previous: ItemPosition,
+        current: ItemPosition,
+    },
+    BlockAppend {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: block::Error,
+    },
+    BlockPosition {
+        level_index: usize,
+        block_index: usize,
+        error: io::Error,
+    },
+    BlockWrite {
+        level_index: usize,
+        block_index: usize,
+        error: io::Error,
+    },
+    BlockOutsideRegions {
+        level_index: usize,
+        block_index: usize,
+    },
+    OverflowPosition {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: io::Error,
+    },
+    OverflowWrite {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: io::Error,
+    },
+    ValueLogWrite {
+        level_index: usize,
+        block_index: usize,
+        item_index: usize,
+        error: io::Error,
+    },
+    KeyNotPackable {
+        key_codec: String,
+    },
+    PageSizeTooLarge {
+        page_size: usize,
+        max_page_size: usize,
+    },
+    HeaderWrite(io::Error),
+    TrailerPosition(io::Error),
+    TrailerWrite(io::Error),
+    Flush(io::Error),
+    ValueLogFlush(io::Error),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Order {
+    Unchecked,
+    NonDecreasing,
+    StrictlyIncreasing,
+}
+
+pub trait BlockSink {
+    fn write_block(&mut self, level_index: usize, block_index: usize, page: &[u8]) -> Result<(), Error>;
+
+    // pages of one value are written back to back and must end up contiguous, returns the page offset
+    fn write_overflow_page(&mut self, position: ItemPosition, page: &[u8]) -> Result<u64, Error>;
+
+    // a sink which keeps values apart from the blocks takes the value here and returns where it went
+    fn append_value(&mut self, _position: ItemPosition, _value: &[u8]) -> Result<Option<block::LogRef>, Error> {
+        Ok(None)
+    }
+}
+
+struct LevelSeed {
+    block: block::Builder,
+}
+
+// Blocks go one after another from the current position in plan order, overflow pages into their own region
+// right after the last block, whose place is known from the sketch.
+pub(crate) struct IndexedSink<'a, W> {
+    sink: &'a mut W,
+    blocks_offsets: Vec<Vec<u64>>,
+    block_offset: u64,
+    overflow_offset: u64,
+    position: u64,
+}
+
+impl<'a, W> IndexedSink<'a, W> where W: Seek {
+    pub(crate) fn new(sink: &'a mut W, sketch: &sketch::Tree, page_size: usize) -> io::Result<IndexedSink<'a, W>> {
+        let block_offset = sink.stream_position()?;
+        let record_size = (page_size + block::CHECKSUM_SIZE) as u64;
+        let blocks_size: u64 = sketch.levels()
+            .iter()
+            .map(|level| level.blocks_count * record_size)
+            .sum();
+        Ok(IndexedSink {
+            sink,
+            blocks_offsets: sketch.levels()
+                .iter()
+                .map(|level| Vec::with_capacity(level.blocks_count as usize))
+                .collect(),
+            block_offset,
+            overflow_offset: block_offset + blocks_size,
+            position: block_offset,
+        })
+    }
+
+    // where the overflow region ends, which is where the trailer goes
+    pub(crate) fn end_offset(&self) -> u64 {
+        self.overflow_offset
+    }
+
+    pub(crate) fn into_layout(self) -> trailer::Layout {
+        trailer::Layout::Indexed { blocks_offsets: self.blocks_offsets, }
+    }
+
+    // the sink is only moved when the writes switch between the blocks and the overflow region
+    fn seek(&mut self, offset: u64) -> io::Result<()> {
+        if self.position != offset {
+            self.sink.seek(SeekFrom::Start(offset))?;
+            self.position = offset;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W> BlockSink for IndexedSink<'a, W> where W: Write + Seek {
+    fn write_block(&mut self, level_index: usize, block_index: usize, page: &[u8]) -> Result<(), Error> {
+        let offset = self.block_offset;
+        self.seek(offset)
+            .map_err(|error| Error::BlockPosition { level_index, block_index, error, })?;
+        self.sink.write_all(page)
+            .map_err(|error| Error::BlockWrite { level_index, block_index, error, })?;
+        self.position += page.len() as u64;
+        self.block_offset += page.len() as u64;
+        self.blocks_offsets[level_index].push(offset);
+        Ok(())
+    }
+
+    fn write_overflow_page(&mut self, position: ItemPosition, page: &[u8]) -> Result<u64, Error> {
+        let ItemPosition { level_index, block_index, item_index, } = position;
+        let offset = self.overflow_offset;
+        self.seek(offset)
+            .map_err(|error| Error::OverflowPosition { level_index, block_index, item_index, error, })?;
+        self.sink.write_all(page)
+            .map_err(|error| Error::OverflowWrite { level_index, block_index, item_index, error, })?;
+        self.position += page.len() as u64;
+        self.overflow_offset += page.len() as u64;
+        Ok(offset)
+    }
+}
+
+pub fn write<W, I, K, V>(sketch: &sketch::Tree, page_size: usize, items: I, sink: W) -> Result<W, Error>
+where W: Write + Seek,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    write_ordered(sketch, page_size, Order::NonDecreasing, items, sink)
+}
+
+pub fn write_ordered<W, I, K, V>(sketch: &sketch::Tree, page_size: usize, order: Order, items: I, sink: W) -> Result<W, Error>
+where W: Write + Seek,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    write_encoded(sketch, page_size, order, block::Encoding::Plain, items, sink)
+}
+
+pub fn write_encoded<W, I, K, V>(
+    sketch: &sketch::Tree,
+    page_size: usize,
+    order: Order,
+    encoding: block::Encoding,
+    items: I,
+    mut sink: W,
+)
+    -> Result<W, Error>
+where W: Write + Seek,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    check_encoding::<K>(encoding, page_size)?;
+    trailer::write_header(&mut sink)
+        .map_err(Error::HeaderWrite)?;
+
+    let mut indexed_sink = IndexedSink::new(&mut sink, sketch, page_size)
+        .map_err(Error::HeaderWrite)?;
+    let overflow_pages_count = write_blocks(sketch, page_size, order, encoding, items, &mut indexed_sink)?;
+
+    let levels_offset = indexed_sink.end_offset();
+    let trailer = make_trailer::<K, V>(sketch, page_size, overflow_pages_count, indexed_sink.into_layout());
+    sink.seek(SeekFrom::Start(levels_offset))
+        .map_err(Error::TrailerPosition)?;
+    trailer.write_to(&mut sink, levels_offset)
+        .map_err(Error::TrailerWrite)?;
+    sink.flush()
+        .map_err(Error::Flush)?;
+
+    Ok(sink)
+}
+
+pub fn make_trailer<K, V>(sketch: &sketch::Tree, page_size: usize, overflow_pages_count: u64, layout: trailer::Layout) -> trailer::Trailer
+where K: codec::Codec,
+      V: codec::Codec,
+{
+    trailer::Trailer {
+        items_total: sketch.items_total(),
+        block_sizes: sketch.block_sizes().clone(),
+        block_fill: sketch.block_fill(),
+        mode: sketch.mode(),
+        page_size,
+        overflow_pages_count,
+        value_log_len: None,
+        key_codec: K::codec_id(),
+        value_codec: V::codec_id(),
+        levels: sketch.levels().to_vec(),
+        blocks_items_counts: sketch.blocks_items_counts(),
+        layout,
+    }
+}
+
+// Delta packing takes keys of a fixed integer width, the key codec has to encode those before anything is written.
+pub fn check_encoding<K>(encoding: block::Encoding, page_size: usize) -> Result<(), Error> where K: codec::Codec {
+    if page_size > block::MAX_PAGE_SIZE {
+        return Err(Error::PageSizeTooLarge { page_size, max_page_size: block::MAX_PAGE_SIZE, });
+    }
+    let key_codec = K::codec_id();
+    if encoding == block::Encoding::DeltaPacked && block::packed_key_len(&key_codec).is_none() {
+        return Err(Error::KeyNotPackable { key_codec, });
+    }
+    Ok(())
+}
+
+// Returns the count of overflow pages written for the values too long to stay inline.
+pub fn write_blocks<S, I, K, V>(
+    sketch: &sketch::Tree,
+    page_size: usize,
+    order: Order,
+    encoding: block::Encoding,
+    items: I,
+    sink: &mut S,
+)
+    -> Result<u64, Error>
+where S: BlockSink,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    check_encoding::<K>(encoding, page_size)?;
+    let mut items = items.into_iter();
+    let mut prev_item: Option<(K, ItemPosition)> = None;
+    let mut page = Vec::with_capacity(page_size + block::CHECKSUM_SIZE);
+    let mut key_buf = Vec::new();
+    let mut value_buf = Vec::new();
+    let mut overflow_pages_count = 0;
+    let mut fold_ctx = fold::Context::new(
+        plan::Context::new(Arc::new(sketch.clone())),
+        sketch,
+    );
+
+    let mut kont = fold::Script::boot();
+    loop {
+        kont = match kont.step_rec(&mut fold_ctx).map_err(Error::Fold)? {
+            fold::Instruction::Op(fold::Op::VisitLevel(fold::VisitLevel { next, .. })) => {
+                let level_seed = LevelSeed {
+                    block: block::Builder::with_encoding(page_size, encoding),
+                };
+                next.level_ready(level_seed, &mut fold_ctx).map_err(Error::Fold)?
+            },
+            fold::Instruction::Op(fold::Op::VisitBlockStart(fold::VisitBlockStart { mut level_seed, next, .. })) => {
+                level_seed.block.reset();
+                next.block_ready(level_seed, &mut fold_ctx).map_err(Error::Fold)?
+            },
+            fold::Instruction::Op(fold::Op::VisitItem(fold::VisitItem {
+                level_index,
+                mut level_seed,
+                block_index,
+                block_item_index: item_index,
+                next,
+            })) if sketch.is_separators_level(level_index) => {
+                // a separator copies the last leaf key, which is the greatest key of the child subtree just finished
+                value_buf.clear();
+                level_seed.block.push(&key_buf, &value_buf)
+                    .map_err(|error| Error::BlockAppend { level_index, block_index, item_index, error, })?;
+                next.item_ready(level_seed, &mut fold_ctx).map_err(Error::Fold)?
+            },
+            fold::Instruction::Op(fold::Op::VisitItem(fold::VisitItem {
+                level_index,
+                mut level_seed,
+                block_index,
+                block_item_index: item_index,
+                next,
+            })) => {
+                let (key, value) = items.next()
+                    .ok_or(Error::ItemsExhausted { level_index, block_index, item_index, })?;
+                let current = ItemPosition { level_index, block_index, item_index, };
+                if let Some((prev_key, previous)) = &prev_item {
+                    match (order, prev_key.cmp(&key)) {
+                        (Order::Unchecked, _) | (_, Ordering::Less) =>
+                            (),
+                        (Order::NonDecreasing, Ordering::Equal) =>
+                            (),
+                        (Order::StrictlyIncreasing, Ordering::Equal) =>
+                            return Err(Error::DuplicateKey { previous: *previous, current, }),
+                        (_, Ordering::Greater) =>
+                            return Err(Error::KeyOrder { previous: *previous, current, }),
+                    }
+                }
+                codec::encode(&key, &mut key_buf);
+                codec::encode(&value, &mut value_buf);
+                let appended = if let Some(log_ref) = sink.append_value(current, &value_buf)? {
+                    level_seed.block.push_log_ref(&key_buf, log_ref)
+                } else if value_buf.len() > block::max_inline_value_len(page_size) {
+                    let overflow = write_overflow(sink, page_size, current, &value_buf, &mut page)?;
+                    overflow_pages_count += block::overflow_pages_count(page_size, overflow.value_len);
+                    level_seed.block.push_overflow(&key_buf, overflow)
+                } else {
+                    level_seed.block.push(&key_buf, &value_buf)
+                };
+                appended
+                    .map_err(|error| Error::BlockAppend { level_index, block_index, item_index, error, })?;
+                if order != Order::Unchecked {
+                    prev_item = Some((key, current));
+                }
+                next.item_ready(level_seed, &mut fold_ctx).map_err(Error::Fold)?
+            },
+            fold::Instruction::Op(fold::Op::VisitBlockFinish(fold::VisitBlockFinish {
+                level_index,
+                level_seed,
+                block_index,
+                next,
+            })) => {
+                level_seed.block.write_page(&mut page);
+                block::seal_page(&mut page);
+                sink.write_block(level_index, block_index, &page)?;
+                next.block_flushed(level_seed, &mut fold_ctx).map_err(Error::Fold)?
+            },
+            fold::Instruction::Done =>
+                break,
+        };
+    }
+
+    if items.next().is_some() {
+        return Err(Error::ItemsLeftover);
+    }
+
+    Ok(overflow_pages_count)
+}
+
+fn write_overflow<S>(sink: &mut S, page_size: usize, position: ItemPosition, value: &[u8], page: &mut Vec<u8>) -> Result<block::OverflowRef, Error>
+where S: BlockSink,
+{
+    let mut offset = 0;
+    for (page_index, chunk) in value.chunks(page_size).enumerate() {
+        page.clear();
+        page.extend_from_slice(chunk);
+        page.resize(page_size, 0);
+        block::seal_page(page);
+        let page_offset = sink.write_overflow_page(position, page)?;
+        if page_index == 0 {
+            offset = page_offset;
+        }
+    }
+    Ok(block::OverflowRef { offset, value_len: value.len() as u64, })
+}
@@ -144,8 +144,8 @@ impl Script {
                                 script: self,
                                 plan_next: plan::Instruction::Perform(plan::Perform {
                                     op: plan::Op::BlockStart { items_count, },
-                                    level_index: level_index,
-                                    block_index: block_index,
+                                    level_index,
+                                    block_index,
                                     next,
                                 }),
                             },
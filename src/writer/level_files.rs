@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    io::{
+        self,
+        BufWriter,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::{
+    writer::file::{
+        self,
+        BlockSink,
+    },
+    block,
+    codec,
+    sketch,
+    trailer,
+};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest";
+pub const OVERFLOW_FILE_NAME: &str = "overflow.pages";
+
+#[derive(Debug)]
+pub enum Error {
+    CreateDir(io::Error),
+    StaleFileRemove {
+        path: PathBuf,
+        error: io::Error,
+    },
+    LevelFileCreate {
+        level_index: usize,
+        path: PathBuf,
+        error: io::Error,
+    },
+    Write(file::Error),
+    LevelFileFlush {
+        level_index: usize,
+        error: io::Error,
+    },
+    OverflowFileFlush(io::Error),
+    ManifestCreate(io::Error),
+    ManifestWrite(io::Error),
+    ManifestFlush(io::Error),
+}
+
+pub fn level_file_name(level_index: usize) -> String {
+    format!("level-{}.blocks", level_index)
+}
+
+fn remove_stale(path: PathBuf) -> Result<bool, Error> {
+    match fs::remove_file(&path) {
+        Ok(()) =>
+            Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound =>
+            Ok(false),
+        Err(error) =>
+            Err(Error::StaleFileRemove { path, error, }),
+    }
+}
+
+struct LevelFilesSink<'a> {
+    dir: &'a Path,
+    files: Vec<BufWriter<fs::File>>,
+    overflow: Option<BufWriter<fs::File>>,
+    overflow_offset: u64,
+}
+
+impl<'a> BlockSink for LevelFilesSink<'a> {
+    fn write_block(&mut self, level_index: usize, block_index: usize, page: &[u8]) -> Result<(), file::Error> {
+        self.files[level_index].write_all(page)
+            .map_err(|error| file::Error::BlockWrite { level_index, block_index, error, })
+    }
+
+    fn write_overflow_page(&mut self, position: file::ItemPosition, page: &[u8]) -> Result<u64, file::Error> {
+        let file::ItemPosition { level_index, block_index, item_index, } = position;
+        // the overflow file only shows up for trees which actually have long values
+        let overflow = match &mut self.overflow {
+            Some(overflow) =>
+                overflow,
+            None => {
+                let file = fs::File::create(self.dir.join(OVERFLOW_FILE_NAME))
+                    .map_err(|error| file::Error::OverflowWrite { level_index, block_index, item_index, error, })?;
+                self.overflow.insert(BufWriter::new(file))
+            },
+        };
+        overflow.write_all(page)
+            .map_err(|error| file::Error::OverflowWrite { level_index, block_index, item_index, error, })?;
+        let offset = self.overflow_offset;
+        self.overflow_offset += page.len() as u64;
+        Ok(offset)
+    }
+}
+
+pub fn write<P, I, K, V>(sketch: &sketch::Tree, page_size: usize, order: file::Order, encoding: block::Encoding, items: I, dir: P) -> Result<(), Error>
+where P: AsRef<Path>,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    file::check_encoding::<K>(encoding, page_size)
+        .map_err(Error::Write)?;
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)
+        .map_err(Error::CreateDir)?;
+    // the directory may hold an older tree, whose overflow file and deeper level files would outlive it
+    remove_stale(dir.join(OVERFLOW_FILE_NAME))?;
+    for level_index in sketch.levels().len() .. {
+        if !remove_stale(dir.join(level_file_name(level_index)))? {
+            break;
+        }
+    }
+
+    let file_names: Vec<_> = sketch.levels()
+        .iter()
+        .map(|level| level_file_name(level.index))
+        .collect();
+    let mut files = Vec::with_capacity(file_names.len());
+    for (level_index, file_name) in file_names.iter().enumerate() {
+        let path = dir.join(file_name);
+        let file = fs::File::create(&path)
+            .map_err(|error| Error::LevelFileCreate { level_index, path, error, })?;
+        files.push(BufWriter::new(file));
+    }
+
+    let mut sink = LevelFilesSink { dir, files, overflow: None, overflow_offset: 0, };
+    let overflow_pages_count = file::write_blocks(sketch, page_size, order, encoding, items, &mut sink)
+        .map_err(Error::Write)?;
+    for (level_index, file) in sink.files.into_iter().enumerate() {
+        file.into_inner()
+            .map_err(|error| Error::LevelFileFlush { level_index, error: error.into_error(), })?
+            .sync_all()
+            .map_err(|error| Error::LevelFileFlush { level_index, error, })?;
+    }
+    if let Some(overflow) = sink.overflow {
+        overflow.into_inner()
+            .map_err(|error| Error::OverflowFileFlush(error.into_error()))?
+            .sync_all()
+            .map_err(Error::OverflowFileFlush)?;
+    }
+
+    let trailer = file::make_trailer::<K, V>(sketch, page_size, overflow_pages_count, trailer::Layout::LevelFiles { file_names, });
+    let mut manifest = BufWriter::new(
+        fs::File::create(dir.join(MANIFEST_FILE_NAME))
+            .map_err(Error::ManifestCreate)?,
+    );
+    trailer::write_header(&mut manifest)
+        .map_err(Error::ManifestWrite)?;
+    trailer.write_to(&mut manifest, trailer::HEADER_SIZE)
+        .map_err(Error::ManifestWrite)?;
+    manifest.into_inner()
+        .map_err(|error| Error::ManifestFlush(error.into_error()))?
+        .sync_all()
+        .map_err(Error::ManifestFlush)?;
+
+    Ok(())
+}
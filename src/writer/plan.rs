@@ -1,5 +1,9 @@
+use std::sync::Arc;
+
 use crate::sketch;
 
+pub mod rev;
+
 pub enum Instruction {
     Perform(Perform),
     Done,
@@ -24,16 +28,22 @@ pub struct Continue {
 
 pub struct Script(());
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Position {
+    pub block_index: usize,
+    pub item_index: usize,
+}
+
 pub struct Context {
     cursors: Vec<LevelCursor>,
-    block_size: usize,
+    sketch: Arc<sketch::Tree>,
     level_curr: usize,
 }
 
 struct LevelCursor {
     level_index: usize,
     block_index: usize,
-    items_remain: usize,
+    items_remain: u64,
     block_cursor: BlockCursor,
 }
 
@@ -44,7 +54,7 @@ enum BlockCursor {
 }
 
 impl Context {
-    pub fn new(sketch: &sketch::Tree) -> Context {
+    pub fn new(sketch: Arc<sketch::Tree>) -> Context {
         Context {
             cursors: sketch
                 .levels()
@@ -57,10 +67,82 @@ impl Context {
                     block_cursor: BlockCursor::Begin,
                 })
                 .collect(),
-            block_size: sketch.block_size(),
+            sketch,
             level_curr: 0,
         }
     }
+
+    pub fn with_path(sketch: Arc<sketch::Tree>, path: &[Position]) -> Context {
+        let depth = match path.len().checked_sub(1) {
+            None =>
+                return Context::new(sketch),
+            Some(depth) =>
+                depth,
+        };
+        let items_before = |level: &sketch::Level, block_index: usize| {
+            sketch.block_items_before(sketch::BlockPosition { level_index: level.index, block_index, })
+                .unwrap_or(level.items_count)
+        };
+
+        let levels = sketch.levels();
+        let mut level_curr = levels.len();
+        let mut next_block_index = 0;
+        let mut cursors = Vec::with_capacity(levels.len());
+        for level in levels {
+            let cursor = match path.get(level.index) {
+                Some(&Position { block_index, item_index, }) if level.index < depth =>
+                    LevelCursor {
+                        level_index: level.index,
+                        block_index,
+                        items_remain: level.items_count - items_before(level, block_index) - item_index as u64,
+                        // a block is started right before its first item, which follows the first child
+                        block_cursor: if item_index == 0 {
+                            BlockCursor::Begin
+                        } else {
+                            BlockCursor::Write { index: item_index, }
+                        },
+                    },
+                Some(&Position { block_index, item_index, }) => {
+                    let block_items_before = items_before(level, block_index);
+                    let block_items_count = items_before(level, block_index + 1) - block_items_before;
+                    if (item_index as u64) < block_items_count {
+                        level_curr = levels.len() - 1 - level.index;
+                        next_block_index = block_items_before + item_index as u64 + 1;
+                        LevelCursor {
+                            level_index: level.index,
+                            block_index,
+                            items_remain: level.items_count - block_items_before - item_index as u64,
+                            block_cursor: BlockCursor::Write { index: item_index, },
+                        }
+                    } else {
+                        level_curr = levels.len() - level.index;
+                        next_block_index = items_before(level, block_index + 1);
+                        LevelCursor {
+                            level_index: level.index,
+                            block_index: block_index + 1,
+                            items_remain: level.items_count - next_block_index,
+                            block_cursor: BlockCursor::Begin,
+                        }
+                    }
+                },
+                None => {
+                    // the parent items lead to the child blocks, so the index is within the blocks count
+                    let block_index = next_block_index as usize;
+                    next_block_index = items_before(level, block_index);
+                    LevelCursor {
+                        level_index: level.index,
+                        block_index,
+                        items_remain: level.items_count - next_block_index,
+                        block_cursor: BlockCursor::Begin,
+                    }
+                },
+            };
+            cursors.push(cursor);
+        }
+        cursors.reverse();
+
+        Context { cursors, sketch, level_curr, }
+    }
 }
 
 impl Script {
@@ -73,11 +155,9 @@ impl Script {
             None =>
                 Instruction::Done,
             Some(LevelCursor { block_cursor, items_remain, level_index, block_index, }) => {
-                let items_count = if *items_remain < context.block_size {
-                    *items_remain
-                } else {
-                    context.block_size
-                };
+                let items_count = context.sketch
+                    .block_items_count(sketch::BlockPosition { level_index: *level_index, block_index: *block_index, })
+                    .unwrap_or(0);
                 match *block_cursor {
                     BlockCursor::Begin => {
                         *block_cursor = BlockCursor::Write { index: 0, };
@@ -93,7 +173,7 @@ impl Script {
                         let current_block_index = *block_index;
                         let next_index = index + 1;
                         *items_remain -= 1;
-                        if *items_remain == 0 || next_index == context.block_size {
+                        if *items_remain == 0 || next_index == items_count {
                             *block_cursor = BlockCursor::Commit;
                         } else {
                             *block_cursor = BlockCursor::Write {
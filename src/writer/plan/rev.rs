@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use crate::{
+    writer::plan::{
+        Op,
+        Position,
+    },
+    sketch,
+};
+
+pub enum Instruction {
+    Perform(Perform),
+    Done,
+}
+
+pub struct Perform {
+    pub op: Op,
+    pub level_index: usize,
+    pub block_index: usize,
+    pub next: Continue,
+}
+
+pub struct Continue {
+    pub next: Script,
+}
+
+pub struct Script(());
+
+pub struct Context {
+    cursors: Vec<LevelCursor>,
+    sketch: Arc<sketch::Tree>,
+    level_curr: Option<usize>,
+}
+
+struct LevelCursor {
+    block_index: usize,
+    block_cursor: BlockCursor,
+}
+
+enum BlockCursor {
+    Begin,
+    Write { index: usize, },
+    Commit,
+    Return,
+}
+
+impl Context {
+    pub fn new(sketch: Arc<sketch::Tree>) -> Context {
+        Context {
+            cursors: sketch
+                .levels()
+                .iter()
+                .map(|_| LevelCursor {
+                    block_index: 0,
+                    block_cursor: BlockCursor::Begin,
+                })
+                .collect(),
+            level_curr: if sketch.levels().is_empty() { None } else { Some(0) },
+            sketch,
+        }
+    }
+
+    pub fn with_path(sketch: Arc<sketch::Tree>, path: &[Position]) -> Context {
+        let mut context = Context::new(sketch);
+        for (level_index, &Position { block_index, item_index, }) in path.iter().enumerate() {
+            let cursor = &mut context.cursors[level_index];
+            cursor.block_index = block_index;
+            cursor.block_cursor = match item_index.checked_sub(1) {
+                None =>
+                    BlockCursor::Return,
+                Some(index) =>
+                    BlockCursor::Write { index, },
+            };
+            context.level_curr = Some(level_index);
+        }
+        context
+    }
+
+    fn descend(&mut self, level_index: usize, block_index: usize, item_index: usize) {
+        if let Some(child) = self.sketch.child_block(sketch::ItemPosition { level_index, block_index, item_index, }) {
+            let child_cursor = &mut self.cursors[child.level_index];
+            child_cursor.block_index = child.block_index;
+            child_cursor.block_cursor = BlockCursor::Begin;
+            self.level_curr = Some(child.level_index);
+        }
+    }
+
+    fn block_items_count(&self, level_index: usize, block_index: usize) -> usize {
+        self.sketch.block_items_count(sketch::BlockPosition { level_index, block_index, })
+            .unwrap_or(0)
+    }
+}
+
+impl Script {
+    pub fn boot() -> Continue {
+        Continue { next: Script(()), }
+    }
+
+    pub fn step(self, context: &mut Context) -> Instruction {
+        loop {
+            let level_index = match context.level_curr {
+                None =>
+                    return Instruction::Done,
+                Some(level_index) =>
+                    level_index,
+            };
+            let block_index = context.cursors[level_index].block_index;
+            match context.cursors[level_index].block_cursor {
+                BlockCursor::Begin => {
+                    let items_count = context.block_items_count(level_index, block_index);
+                    context.cursors[level_index].block_cursor = BlockCursor::Write { index: items_count - 1, };
+                    return Instruction::Perform(Perform {
+                        op: Op::BlockStart { items_count, },
+                        level_index,
+                        block_index,
+                        next: Continue { next: self, },
+                    });
+                },
+                BlockCursor::Write { index, } => {
+                    if index == 0 {
+                        // mirrors the ascending plan: a block is finished before its first child is visited
+                        context.cursors[level_index].block_cursor = BlockCursor::Commit;
+                    } else {
+                        context.cursors[level_index].block_cursor = BlockCursor::Write { index: index - 1, };
+                        context.descend(level_index, block_index, index);
+                    }
+                    return Instruction::Perform(Perform {
+                        op: Op::BlockItem { index, },
+                        level_index,
+                        block_index,
+                        next: Continue { next: self, },
+                    });
+                },
+                BlockCursor::Commit => {
+                    context.cursors[level_index].block_cursor = BlockCursor::Return;
+                    context.descend(level_index, block_index, 0);
+                    return Instruction::Perform(Perform {
+                        op: Op::BlockFinish,
+                        level_index,
+                        block_index,
+                        next: Continue { next: self, },
+                    });
+                },
+                BlockCursor::Return => {
+                    context.cursors[level_index].block_cursor = BlockCursor::Begin;
+                    context.level_curr = level_index.checked_sub(1);
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    io::{
+        self,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    sync::{
+        Mutex,
+        PoisonError,
+    },
+};
+
+use crate::{
+    writer::file::{
+        self,
+        BlockSink,
+    },
+    block,
+    codec,
+    sketch,
+    trailer,
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Regions {
+    levels_offsets: Vec<u64>,
+    blocks_counts: Vec<u64>,
+    record_size: u64,
+    end_offset: u64,
+}
+
+impl Regions {
+    pub fn new(sketch: &sketch::Tree, page_size: usize) -> Regions {
+        let record_size = (page_size + block::CHECKSUM_SIZE) as u64;
+        let mut levels_offsets = Vec::with_capacity(sketch.levels().len());
+        let mut blocks_counts = Vec::with_capacity(sketch.levels().len());
+        let mut offset = trailer::HEADER_SIZE;
+        for level in sketch.levels() {
+            levels_offsets.push(offset);
+            blocks_counts.push(level.blocks_count);
+            offset += level.blocks_count * record_size;
+        }
+        Regions { levels_offsets, blocks_counts, record_size, end_offset: offset, }
+    }
+
+    pub fn level_offset(&self, level_index: usize) -> Option<u64> {
+        self.levels_offsets.get(level_index).cloned()
+    }
+
+    pub fn block_offset(&self, level_index: usize, block_index: usize) -> Option<u64> {
+        let level_offset = self.level_offset(level_index)?;
+        if block_index as u64 >= self.blocks_counts[level_index] {
+            return None;
+        }
+        Some(level_offset + block_index as u64 * self.record_size)
+    }
+
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+}
+
+// Every block has its place known up front, so a sink which writes at an offset through a shared
+// reference lets each level region be filled on its own with `write_level_at`, from as many threads as
+// there are levels.
+pub trait PositionedWrite {
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+
+    fn flush_writes(&self) -> io::Result<()>;
+}
+
+#[cfg(unix)]
+impl PositionedWrite for std::fs::File {
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, buf, offset)
+    }
+
+    fn flush_writes(&self) -> io::Result<()> {
+        // a file has no buffer of its own
+        Ok(())
+    }
+}
+
+// any seekable sink, taking one write at a time
+impl<W> PositionedWrite for Mutex<W> where W: Write + Seek {
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut sink = self.lock().unwrap_or_else(PoisonError::into_inner);
+        sink.seek(SeekFrom::Start(offset))?;
+        sink.write_all(buf)
+    }
+
+    fn flush_writes(&self) -> io::Result<()> {
+        self.lock().unwrap_or_else(PoisonError::into_inner).flush()
+    }
+}
+
+// With `only_level` set the sink drops whatever belongs to other levels, but still counts their overflow pages,
+// so the offsets come out the same as in a write of every level.
+struct RegionsSink<'a, P> {
+    sink: &'a P,
+    regions: &'a Regions,
+    only_level: Option<usize>,
+    overflow_offset: u64,
+}
+
+impl<'a, P> RegionsSink<'a, P> {
+    fn is_written(&self, level_index: usize) -> bool {
+        self.only_level.is_none_or(|only_level| only_level == level_index)
+    }
+}
+
+impl<'a, P> BlockSink for RegionsSink<'a, P> where P: PositionedWrite {
+    fn write_block(&mut self, level_index: usize, block_index: usize, page: &[u8]) -> Result<(), file::Error> {
+        let offset = self.regions.block_offset(level_index, block_index)
+            .ok_or(file::Error::BlockOutsideRegions { level_index, block_index, })?;
+        if !self.is_written(level_index) {
+            return Ok(());
+        }
+        self.sink.write_all_at(page, offset)
+            .map_err(|error| file::Error::BlockWrite { level_index, block_index, error, })
+    }
+
+    fn write_overflow_page(&mut self, position: file::ItemPosition, page: &[u8]) -> Result<u64, file::Error> {
+        let file::ItemPosition { level_index, block_index, item_index, } = position;
+        let offset = self.overflow_offset;
+        if self.is_written(level_index) {
+            self.sink.write_all_at(page, offset)
+                .map_err(|error| file::Error::OverflowWrite { level_index, block_index, item_index, error, })?;
+        }
+        self.overflow_offset += page.len() as u64;
+        Ok(offset)
+    }
+}
+
+pub fn write<W, I, K, V>(sketch: &sketch::Tree, page_size: usize, order: file::Order, encoding: block::Encoding, items: I, sink: W) -> Result<W, file::Error>
+where W: Write + Seek,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    let sink = Mutex::new(sink);
+    write_at(sketch, page_size, order, encoding, items, &sink)?;
+    Ok(sink.into_inner().unwrap_or_else(PoisonError::into_inner))
+}
+
+pub fn write_at<P, I, K, V>(sketch: &sketch::Tree, page_size: usize, order: file::Order, encoding: block::Encoding, items: I, sink: &P) -> Result<(), file::Error>
+where P: PositionedWrite,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    let overflow_pages_count = write_levels_at(sketch, page_size, order, encoding, None, items, sink)?;
+    write_frame_at::<P, K, V>(sketch, page_size, overflow_pages_count, sink)
+}
+
+// Writes the blocks of one level along with the overflow pages of its items, and returns the overflow pages count
+// of the whole tree for `write_frame_at`. Each level walks every item to find its own, so the levels of a tree
+// may be written by as many threads, then the frame once they are all done.
+pub fn write_level_at<P, I, K, V>(
+    sketch: &sketch::Tree,
+    page_size: usize,
+    order: file::Order,
+    encoding: block::Encoding,
+    level_index: usize,
+    items: I,
+    sink: &P,
+)
+    -> Result<u64, file::Error>
+where P: PositionedWrite,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    if level_index >= sketch.levels().len() {
+        return Err(file::Error::BlockOutsideRegions { level_index, block_index: 0, });
+    }
+    write_levels_at(sketch, page_size, order, encoding, Some(level_index), items, sink)
+}
+
+// Writes the header and the trailer around the level regions and the overflow region.
+pub fn write_frame_at<P, K, V>(sketch: &sketch::Tree, page_size: usize, overflow_pages_count: u64, sink: &P) -> Result<(), file::Error>
+where P: PositionedWrite,
+      K: codec::Codec,
+      V: codec::Codec,
+{
+    let mut header = Vec::with_capacity(trailer::HEADER_SIZE as usize);
+    trailer::write_header(&mut header)
+        .map_err(file::Error::HeaderWrite)?;
+    sink.write_all_at(&header, 0)
+        .map_err(file::Error::HeaderWrite)?;
+
+    let regions = Regions::new(sketch, page_size);
+    let trailer_offset = regions.end_offset() + overflow_pages_count * (page_size + block::CHECKSUM_SIZE) as u64;
+    let trailer = file::make_trailer::<K, V>(sketch, page_size, overflow_pages_count, trailer::Layout::Regions);
+    let mut trailer_buf = Vec::new();
+    trailer.write_to(&mut trailer_buf, trailer_offset)
+        .map_err(file::Error::TrailerWrite)?;
+    sink.write_all_at(&trailer_buf, trailer_offset)
+        .map_err(file::Error::TrailerWrite)?;
+    sink.flush_writes()
+        .map_err(file::Error::Flush)?;
+
+    Ok(())
+}
+
+fn write_levels_at<P, I, K, V>(
+    sketch: &sketch::Tree,
+    page_size: usize,
+    order: file::Order,
+    encoding: block::Encoding,
+    only_level: Option<usize>,
+    items: I,
+    sink: &P,
+)
+    -> Result<u64, file::Error>
+where P: PositionedWrite,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    file::check_encoding::<K>(encoding, page_size)?;
+    let regions = Regions::new(sketch, page_size);
+    // overflow pages go into their own region right after the levels
+    let mut regions_sink = RegionsSink { sink, regions: &regions, only_level, overflow_offset: regions.end_offset(), };
+    file::write_blocks(sketch, page_size, order, encoding, items, &mut regions_sink)
+}
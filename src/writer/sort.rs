@@ -0,0 +1,301 @@
+use std::{
+    cmp::{
+        max,
+        Ordering,
+    },
+    collections::BinaryHeap,
+    mem,
+    path::PathBuf,
+};
+
+use crate::{
+    writer::spool,
+    codec,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Spool(spool::Error),
+    KeyDecode(codec::Error),
+    ValueDecode(codec::Error),
+    DuplicateKey {
+        key: Vec<u8>,
+    },
+}
+
+pub const DEFAULT_FAN_IN: usize = 64;
+
+pub type MergeFn<K, V> = Box<dyn FnMut(&K, V, V) -> V>;
+
+pub enum Duplicates<K, V> {
+    KeepFirst,
+    KeepLast,
+    Error,
+    Merge(MergeFn<K, V>),
+}
+
+pub struct Sorter<K, V> {
+    memory_limit: usize,
+    fan_in: usize,
+    dir: PathBuf,
+    run: Vec<(K, V)>,
+    run_bytes: usize,
+    runs: Vec<Run>,
+    key_buf: Vec<u8>,
+    value_buf: Vec<u8>,
+}
+
+impl<K, V> Sorter<K, V> where K: codec::Encode + codec::Decode + Ord, V: codec::Encode + codec::Decode {
+    pub fn new(memory_limit: usize, dir: PathBuf) -> Sorter<K, V> {
+        Sorter::with_fan_in(memory_limit, DEFAULT_FAN_IN, dir)
+    }
+
+    // at most `fan_in` runs are merged at once, the runs beyond that are merged in intermediate passes
+    pub fn with_fan_in(memory_limit: usize, fan_in: usize, dir: PathBuf) -> Sorter<K, V> {
+        Sorter {
+            memory_limit,
+            fan_in: max(fan_in, 2),
+            dir,
+            run: Vec::new(),
+            run_bytes: 0,
+            runs: Vec::new(),
+            key_buf: Vec::new(),
+            value_buf: Vec::new(),
+        }
+    }
+
+    pub fn runs_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn push(&mut self, key: K, value: V) -> Result<(), Error> {
+        codec::encode(&key, &mut self.key_buf);
+        codec::encode(&value, &mut self.value_buf);
+        self.run_bytes += self.key_buf.len() + self.value_buf.len() + mem::size_of::<(K, V)>();
+        self.run.push((key, value));
+        if self.run_bytes > self.memory_limit {
+            self.flush_run()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self, duplicates: Duplicates<K, V>) -> Result<Merge<K, V>, Error> {
+        // the run still in memory takes one of the merge slots
+        while self.runs.len() >= self.fan_in {
+            self.merge_tail(self.fan_in)?;
+        }
+        // it stays in memory, encoded, so that the merge can be replayed like the spilled runs
+        self.run.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut spool = spool::Spool::new(usize::MAX, self.dir.clone());
+        for (key, value) in mem::take(&mut self.run) {
+            codec::encode(&key, &mut self.key_buf);
+            codec::encode(&value, &mut self.value_buf);
+            spool.push(&self.key_buf, &self.value_buf)
+                .map_err(Error::Spool)?;
+        }
+        let sources = self.runs
+            .into_iter()
+            .map(|run| run.replay)
+            .chain(Some(spool.into_replay().map_err(Error::Spool)?))
+            .collect();
+        Merge::new(sources, duplicates)
+    }
+
+    fn flush_run(&mut self) -> Result<(), Error> {
+        self.run.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut spool = spool::Spool::new(0, self.dir.clone());
+        for (key, value) in self.run.drain(..) {
+            codec::encode(&key, &mut self.key_buf);
+            codec::encode(&value, &mut self.value_buf);
+            spool.push(&self.key_buf, &self.value_buf)
+                .map_err(Error::Spool)?;
+        }
+        self.runs.push(Run { replay: spool.into_replay().map_err(Error::Spool)?, pass: 0, });
+        self.run_bytes = 0;
+        // runs produced by the same pass are merged as soon as there are enough of them, which keeps
+        // the count of open runs logarithmic in the input size
+        while self.runs.len() >= self.fan_in {
+            let last_pass = self.runs[self.runs.len() - 1].pass;
+            if self.runs[self.runs.len() - self.fan_in ..].iter().any(|run| run.pass != last_pass) {
+                break;
+            }
+            self.merge_tail(self.fan_in)?;
+        }
+        Ok(())
+    }
+
+    // merges the latest `count` runs into one, keeping every duplicate in the order of the runs
+    fn merge_tail(&mut self, count: usize) -> Result<(), Error> {
+        let tail = self.runs.split_off(self.runs.len() - count);
+        let pass = tail.iter().map(|run| run.pass).max().unwrap_or(0) + 1;
+        let sources = tail
+            .into_iter()
+            .map(|run| run.replay)
+            .collect();
+        let mut merge: Merge<K, V> = Merge::new(sources, Duplicates::KeepFirst)?;
+        let mut spool = spool::Spool::new(0, self.dir.clone());
+        while let Some((key, value)) = merge.pop()? {
+            codec::encode(&key, &mut self.key_buf);
+            codec::encode(&value, &mut self.value_buf);
+            spool.push(&self.key_buf, &self.value_buf)
+                .map_err(Error::Spool)?;
+        }
+        self.runs.push(Run { replay: spool.into_replay().map_err(Error::Spool)?, pass, });
+        Ok(())
+    }
+}
+
+struct Run {
+    replay: spool::Replay,
+    pass: usize,
+}
+
+pub struct Merge<K, V> {
+    sources: Vec<spool::Replay>,
+    heap: BinaryHeap<HeapEntry<K, V>>,
+    duplicates: Duplicates<K, V>,
+}
+
+impl<K, V> Merge<K, V> where K: codec::Encode + codec::Decode + Ord, V: codec::Decode {
+    fn new(sources: Vec<spool::Replay>, duplicates: Duplicates<K, V>) -> Result<Merge<K, V>, Error> {
+        let heap = BinaryHeap::with_capacity(sources.len());
+        let mut merge = Merge { sources, heap, duplicates, };
+        merge.fill_heap()?;
+        Ok(merge)
+    }
+
+    pub fn runs_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    // starts the merge over from the beginning of every run, the runs are kept until the merge is dropped
+    pub fn rewind(&mut self) -> Result<(), Error> {
+        self.heap.clear();
+        for source in &mut self.sources {
+            source.rewind()
+                .map_err(Error::Spool)?;
+        }
+        self.fill_heap()
+    }
+
+    pub fn items(&mut self) -> MergeItems<'_, K, V> {
+        MergeItems { merge: self, error: None, }
+    }
+
+    fn fill_heap(&mut self) -> Result<(), Error> {
+        for (run_index, source) in self.sources.iter_mut().enumerate() {
+            if let Some((key, value)) = next_run_item(source)? {
+                self.heap.push(HeapEntry { key, run_index, value, });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn next_item(&mut self) -> Result<Option<(K, V)>, Error> {
+        let (key, mut value) = match self.pop()? {
+            None =>
+                return Ok(None),
+            Some(item) =>
+                item,
+        };
+        while self.heap.peek().is_some_and(|entry| entry.key == key) {
+            let (_key, next_value) = self.pop()?.unwrap();
+            value = match &mut self.duplicates {
+                Duplicates::KeepFirst =>
+                    value,
+                Duplicates::KeepLast =>
+                    next_value,
+                Duplicates::Error => {
+                    let mut key_buf = Vec::new();
+                    codec::encode(&key, &mut key_buf);
+                    return Err(Error::DuplicateKey { key: key_buf, });
+                },
+                Duplicates::Merge(merge) =>
+                    merge(&key, value, next_value),
+            };
+        }
+        Ok(Some((key, value)))
+    }
+
+    fn pop(&mut self) -> Result<Option<(K, V)>, Error> {
+        let HeapEntry { key, run_index, value, } = match self.heap.pop() {
+            None =>
+                return Ok(None),
+            Some(entry) =>
+                entry,
+        };
+        if let Some((next_key, next_value)) = next_run_item(&mut self.sources[run_index])? {
+            self.heap.push(HeapEntry { key: next_key, run_index, value: next_value, });
+        }
+        Ok(Some((key, value)))
+    }
+}
+
+pub struct MergeItems<'a, K, V> {
+    merge: &'a mut Merge<K, V>,
+    error: Option<Error>,
+}
+
+impl<'a, K, V> MergeItems<'a, K, V> {
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+impl<'a, K, V> Iterator for MergeItems<'a, K, V> where K: codec::Encode + codec::Decode + Ord, V: codec::Decode {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.merge.next_item() {
+            Ok(item) =>
+                item,
+            Err(error) => {
+                self.error = Some(error);
+                None
+            },
+        }
+    }
+}
+
+fn next_run_item<K, V>(replay: &mut spool::Replay) -> Result<Option<(K, V)>, Error> where K: codec::Decode, V: codec::Decode {
+    let mut key_buf = Vec::new();
+    let mut value_buf = Vec::new();
+    if !replay.next_item(&mut key_buf, &mut value_buf).map_err(Error::Spool)? {
+        return Ok(None);
+    }
+    let key = codec::decode(&key_buf)
+        .map_err(Error::KeyDecode)?;
+    let value = codec::decode(&value_buf)
+        .map_err(Error::ValueDecode)?;
+    Ok(Some((key, value)))
+}
+
+struct HeapEntry<K, V> {
+    key: K,
+    run_index: usize,
+    value: V,
+}
+
+impl<K, V> PartialEq for HeapEntry<K, V> where K: Ord {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K, V> Eq for HeapEntry<K, V> where K: Ord { }
+
+impl<K, V> PartialOrd for HeapEntry<K, V> where K: Ord {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K, V> Ord for HeapEntry<K, V> where K: Ord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key)
+            .then_with(|| other.run_index.cmp(&self.run_index))
+    }
+}
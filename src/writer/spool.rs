@@ -0,0 +1,308 @@
+use std::{
+    fs,
+    io::{
+        self,
+        BufReader,
+        BufWriter,
+        Cursor,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    marker::PhantomData,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+use crate::codec;
+
+#[derive(Debug)]
+pub enum Error {
+    TempCreate {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Write(io::Error),
+    Flush(io::Error),
+    Rewind(io::Error),
+    Read(io::Error),
+    LengthOverflow {
+        len: u64,
+    },
+    KeyDecode(codec::Error),
+}
+
+pub struct TempFile {
+    path: PathBuf,
+    file: fs::File,
+}
+
+impl TempFile {
+    pub fn create(dir: &Path) -> Result<TempFile, Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = dir.join(format!(
+            "bntree-spool-{}-{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|error| Error::TempCreate { path: path.clone(), error, })?;
+        Ok(TempFile { path, file, })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for TempFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct Spool {
+    memory_limit: usize,
+    dir: PathBuf,
+    state: SpoolState,
+    items_count: u64,
+}
+
+enum SpoolState {
+    Memory(Vec<u8>),
+    File(BufWriter<TempFile>),
+}
+
+impl Spool {
+    pub fn new(memory_limit: usize, dir: PathBuf) -> Spool {
+        Spool {
+            memory_limit,
+            dir,
+            state: SpoolState::Memory(Vec::new()),
+            items_count: 0,
+        }
+    }
+
+    pub fn items_count(&self) -> u64 {
+        self.items_count
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.state, SpoolState::File(..))
+    }
+
+    pub fn push(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if let SpoolState::Memory(buffer) = &mut self.state {
+            if buffer.len() + 16 + key.len() + value.len() > self.memory_limit {
+                let mut temp_file = BufWriter::new(TempFile::create(&self.dir)?);
+                temp_file.write_all(buffer)
+                    .map_err(Error::Write)?;
+                self.state = SpoolState::File(temp_file);
+            }
+        }
+        let result = match &mut self.state {
+            SpoolState::Memory(buffer) =>
+                write_item(buffer, key, value),
+            SpoolState::File(temp_file) =>
+                write_item(temp_file, key, value),
+        };
+        result.map_err(Error::Write)?;
+        self.items_count += 1;
+        Ok(())
+    }
+
+    pub fn into_replay(self) -> Result<Replay, Error> {
+        let source = match self.state {
+            SpoolState::Memory(buffer) =>
+                ReplaySource::Memory(Cursor::new(buffer)),
+            SpoolState::File(temp_file) => {
+                let mut temp_file = temp_file.into_inner()
+                    .map_err(|error| Error::Flush(error.into_error()))?;
+                temp_file.seek(SeekFrom::Start(0))
+                    .map_err(Error::Rewind)?;
+                ReplaySource::File(BufReader::new(temp_file))
+            },
+        };
+        Ok(Replay { source, items_total: self.items_count, items_remain: self.items_count, })
+    }
+}
+
+pub struct Replay {
+    source: ReplaySource,
+    items_total: u64,
+    items_remain: u64,
+}
+
+enum ReplaySource {
+    Memory(Cursor<Vec<u8>>),
+    File(BufReader<TempFile>),
+}
+
+impl Replay {
+    pub fn items_remain(&self) -> u64 {
+        self.items_remain
+    }
+
+    pub fn rewind(&mut self) -> Result<(), Error> {
+        match &mut self.source {
+            ReplaySource::Memory(cursor) =>
+                cursor.set_position(0),
+            ReplaySource::File(temp_file) => {
+                temp_file.seek(SeekFrom::Start(0))
+                    .map_err(Error::Rewind)?;
+            },
+        }
+        self.items_remain = self.items_total;
+        Ok(())
+    }
+
+    pub fn next_item(&mut self, key: &mut Vec<u8>, value: &mut Vec<u8>) -> Result<bool, Error> {
+        if self.items_remain == 0 {
+            return Ok(false);
+        }
+        match &mut self.source {
+            ReplaySource::Memory(cursor) =>
+                read_item(cursor, key, value),
+            ReplaySource::File(temp_file) =>
+                read_item(temp_file, key, value),
+        }?;
+        self.items_remain -= 1;
+        Ok(true)
+    }
+
+    pub fn items<K, V>(&mut self) -> ReplayItems<'_, K, V> {
+        ReplayItems { replay: self, error: None, _marker: PhantomData, }
+    }
+}
+
+pub struct ReplayItems<'a, K, V> {
+    replay: &'a mut Replay,
+    error: Option<Error>,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K, V> ReplayItems<'a, K, V> {
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+impl<'a, K, V> Iterator for ReplayItems<'a, K, V> where K: codec::Decode {
+    type Item = (K, Encoded<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+        let mut key_buf = Vec::new();
+        let mut value = Encoded::new();
+        let result = self.replay.next_item(&mut key_buf, &mut value.bytes)
+            .and_then(|has_item| {
+                if !has_item {
+                    return Ok(None);
+                }
+                let key = codec::decode(&key_buf)
+                    .map_err(Error::KeyDecode)?;
+                Ok(Some((key, value)))
+            });
+        match result {
+            Ok(item) =>
+                item,
+            Err(error) => {
+                self.error = Some(error);
+                None
+            },
+        }
+    }
+}
+
+pub struct Encoded<T: ?Sized> {
+    pub bytes: Vec<u8>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: ?Sized> Encoded<T> {
+    pub fn new() -> Encoded<T> {
+        Encoded { bytes: Vec::new(), _marker: PhantomData, }
+    }
+}
+
+impl<T: ?Sized> Default for Encoded<T> {
+    fn default() -> Encoded<T> {
+        Encoded::new()
+    }
+}
+
+impl<T> codec::Codec for Encoded<T> where T: codec::Codec + ?Sized {
+    fn codec_id() -> String {
+        T::codec_id()
+    }
+}
+
+impl<T> codec::Encode for Encoded<T> where T: codec::Codec + ?Sized {
+    fn encode(&self, target: &mut Vec<u8>) {
+        target.extend_from_slice(&self.bytes);
+    }
+}
+
+fn write_item<W>(sink: &mut W, key: &[u8], value: &[u8]) -> io::Result<()> where W: Write {
+    sink.write_all(&(key.len() as u64).to_le_bytes())?;
+    sink.write_all(&(value.len() as u64).to_le_bytes())?;
+    sink.write_all(key)?;
+    sink.write_all(value)
+}
+
+fn read_item<R>(source: &mut R, key: &mut Vec<u8>, value: &mut Vec<u8>) -> Result<(), Error> where R: Read {
+    let key_len = read_len(source)?;
+    let value_len = read_len(source)?;
+    for (buffer, len) in [(key, key_len), (value, value_len)] {
+        buffer.clear();
+        buffer.resize(len, 0);
+        source.read_exact(buffer)
+            .map_err(Error::Read)?;
+    }
+    Ok(())
+}
+
+fn read_len<R>(source: &mut R) -> Result<usize, Error> where R: Read {
+    let mut bytes = [0; 8];
+    source.read_exact(&mut bytes)
+        .map_err(Error::Read)?;
+    let len = u64::from_le_bytes(bytes);
+    usize::try_from(len)
+        .map_err(|_| Error::LengthOverflow { len, })
+}
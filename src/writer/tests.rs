@@ -1,2 +1,7 @@
 pub mod plan;
 pub mod fold;
+pub mod file;
+pub mod level_files;
+pub mod regions;
+pub mod value_log;
+pub mod builder;
@@ -0,0 +1,672 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::PathBuf,
+};
+
+use super::super::{
+    builder::{
+        self,
+        TreeBuilder,
+    },
+    file,
+    sort,
+    spool,
+    super::{
+        block,
+        codec,
+        reader::{
+            source,
+            Reader,
+        },
+        sketch,
+        verify,
+    },
+};
+
+#[test]
+fn build_same_as_write() {
+    let items: Vec<_> = (0 .. 100u64).map(|index| (index, format!("v{}", index))).collect();
+    let built = TreeBuilder::new(5)
+        .page_size(256)
+        .build(items.iter().cloned(), Cursor::new(Vec::new()))
+        .unwrap();
+    let written = file::write(&sketch::Tree::new(100, 5), 256, items.iter().cloned(), Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(built.into_inner(), written.into_inner());
+}
+
+#[test]
+fn build_read_back() {
+    let items: Vec<_> = (0 .. 57u64).map(|index| (index * 3, index)).collect();
+    let cursor = TreeBuilder::new(4)
+        .build(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.sketch(), &TreeBuilder::new(4).sketch(57).unwrap());
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items);
+}
+
+#[test]
+fn build_bplus() {
+    let items: Vec<_> = (0 .. 57u64).map(|index| (index * 3, index)).collect();
+    let cursor = TreeBuilder::new(4)
+        .bplus()
+        .build(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.sketch().mode(), sketch::Mode::BPlus);
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items);
+    assert_eq!(reader.get(&42).unwrap(), Some(14));
+    assert_eq!(reader.get(&43).unwrap(), None);
+}
+
+#[test]
+fn build_block_sizes() {
+    let items: Vec<_> = (0 .. 300u64).map(|index| (index, index * 2)).collect();
+    for block_sizes in [sketch::BlockSizes::LeafInner { leaf: 16, inner: 4, }, sketch::BlockSizes::PerLevel(vec![3, 7])] {
+        let builder = TreeBuilder::new(4)
+            .block_sizes(block_sizes.clone());
+        let cursor = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+        let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+        assert_eq!(reader.sketch(), &builder.sketch(300).unwrap());
+        assert_eq!(reader.sketch().block_sizes(), &block_sizes);
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+    }
+}
+
+#[test]
+fn build_byte_budget() {
+    let items: Vec<_> = (0 .. 500u64).map(|index| (index, "v".repeat((index as usize * 37) % 50))).collect();
+    for builder in [TreeBuilder::new(4), TreeBuilder::new(4).bplus()] {
+        let builder = builder
+            .page_size(256)
+            .byte_budget();
+        assert!(matches!(builder.sketch(500), Err(builder::Error::ByteBudgetNeedsItems)));
+        let built = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+        let streamed = builder.build_stream(items.clone(), Cursor::new(Vec::new())).unwrap();
+        assert_eq!(built.get_ref(), streamed.get_ref());
+        let mut reader: Reader<_, u64, String> = Reader::open(built).unwrap();
+        assert_eq!(reader.sketch().block_fill(), sketch::BlockFill::Explicit);
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+        assert_eq!(reader.get(&123).unwrap(), Some(items[123].1.clone()));
+    }
+}
+
+#[test]
+fn build_byte_budget_tail() {
+    // the last items go up the right spine, the root has no room left for the final one
+    let value_lens = [14, 7, 10, 7, 16, 22, 12, 30, 26, 21, 4, 27, 16, 29, 16];
+    let items: Vec<_> = value_lens.iter().enumerate().map(|(index, &len)| (index as u64, vec![7u8; len])).collect();
+    let builder = TreeBuilder::new(4).byte_budget().page_size(128);
+    let cursor = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, u64, Vec<u8>> = Reader::open(cursor).unwrap();
+    check_budgeted_pages(&mut reader, 128, block::Encoding::Plain);
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items);
+}
+
+#[test]
+fn build_byte_budget_random() {
+    let mut state = 0x2545f4914f6cdd1d;
+    let builds = [
+        (sketch::Mode::BTree, block::Encoding::Plain),
+        (sketch::Mode::BPlus, block::Encoding::Plain),
+        (sketch::Mode::BTree, block::Encoding::FrontCoded { restart_interval: 4, }),
+        (sketch::Mode::BPlus, block::Encoding::DeltaPacked),
+    ];
+    for round in 0 .. 80 {
+        let page_size = [128, 256, 512][round % 3];
+        let items_total = next_random(&mut state) % 300;
+        let max_value_len = next_random(&mut state) % 60 + 1;
+        let items: Vec<_> = (0 .. items_total)
+            .map(|index| (index * 3, vec![7u8; (next_random(&mut state) % max_value_len) as usize]))
+            .collect();
+        for (mode, encoding) in builds {
+            let builder = TreeBuilder::new(4).mode(mode).encoding(encoding).page_size(page_size).byte_budget();
+            let cursor = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+            let mut reader: Reader<_, u64, Vec<u8>> = Reader::open(cursor).unwrap();
+            check_budgeted_pages(&mut reader, page_size, encoding);
+            let scanned: Result<Vec<_>, _> = reader.scan().collect();
+            assert_eq!(scanned.unwrap(), items, "round {round} {mode:?} {encoding:?}");
+        }
+
+        // string keys of varying lengths share the bytes after their length prefix
+        let mut items: Vec<_> = items.into_iter().map(|(key, value)| (format!("key/{key}"), value)).collect();
+        items.sort();
+        for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+            let encoding = block::Encoding::FrontCoded { restart_interval: 1 + round % 5, };
+            let builder = TreeBuilder::new(4).mode(mode).encoding(encoding).page_size(page_size).byte_budget();
+            let cursor = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+            let mut reader: Reader<_, String, Vec<u8>> = Reader::open(cursor).unwrap();
+            check_budgeted_pages(&mut reader, page_size, encoding);
+            let scanned: Result<Vec<_>, _> = reader.scan().collect();
+            assert_eq!(scanned.unwrap(), items, "round {round} {mode:?} {encoding:?}");
+        }
+    }
+}
+
+// the footprints of every written block, less the shared key bytes, stay within the page budget and cover the bytes the items take
+fn check_budgeted_pages<S, K>(reader: &mut Reader<S, K, Vec<u8>>, page_size: usize, encoding: block::Encoding) where S: source::BlockSource, K: codec::Decode + Ord {
+    let restart_interval = match encoding {
+        block::Encoding::FrontCoded { restart_interval, } =>
+            restart_interval,
+        _ =>
+            1,
+    };
+    let levels = reader.sketch().levels().to_vec();
+    let mut page = Vec::new();
+    for level in levels {
+        for block_index in 0 .. level.blocks_count as usize {
+            reader.read_block(level.index, block_index, &mut page).unwrap();
+            let block = block::Block::decode(&page).unwrap();
+            let mut used = 0;
+            let mut prev_key = Vec::new();
+            let mut first_key = Vec::new();
+            for item_index in 0 .. block.items_count() {
+                let (key, value) = block.item(item_index).unwrap();
+                let shared_len = if item_index % restart_interval == 0 { 0 } else { encoding.shared_len(&prev_key, &key) };
+                if item_index == 0 {
+                    first_key = key.to_vec();
+                }
+                prev_key = key.to_vec();
+                let stored_value_len = match value {
+                    block::Value::Inline(bytes) =>
+                        bytes.len(),
+                    block::Value::Overflow(..) =>
+                        block::OVERFLOW_REF_SIZE,
+                    block::Value::Log(..) =>
+                        block::LOG_REF_SIZE,
+                };
+                used += encoding.item_footprint(key.len(), stored_value_len) - shared_len;
+            }
+            if encoding == block::Encoding::DeltaPacked && block.items_count() > 1 {
+                // only the first key stays whole, the others take the bits of the widest delta
+                let deltas_count = block.items_count() - 1;
+                let span = encoding.key_delta(&first_key, &prev_key);
+                used -= deltas_count * first_key.len();
+                used += (deltas_count * (u128::BITS - span.leading_zeros()) as usize).div_ceil(8);
+            }
+            assert!(used <= encoding.page_budget(page_size), "block {}/{block_index} uses {used}", level.index);
+
+            let mut builder = block::Builder::with_encoding(page_size - encoding.page_budget(page_size) + used, encoding);
+            for item_index in 0 .. block.items_count() {
+                let (key, value) = block.item(item_index).unwrap();
+                let result = match value {
+                    block::Value::Inline(bytes) =>
+                        builder.push(&key, bytes),
+                    block::Value::Overflow(overflow) =>
+                        builder.push_overflow(&key, overflow),
+                    block::Value::Log(log_ref) =>
+                        builder.push_log_ref(&key, log_ref),
+                };
+                assert_eq!(result, Ok(()), "block {}/{block_index} item {item_index}", level.index);
+            }
+        }
+    }
+}
+
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn build_front_coded() {
+    let items: Vec<_> = (0 .. 300u64).map(|index| (format!("https://example.com/catalog/items/{:05}", index * 3), index)).collect();
+    for builder in [TreeBuilder::new(6), TreeBuilder::new(6).bplus()] {
+        let builder = builder.page_size(512);
+        let plain = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+        let cursor = builder
+            .front_coding(4)
+            .build(items.clone(), Cursor::new(Vec::new()))
+            .unwrap();
+        assert_ne!(cursor.get_ref(), plain.get_ref());
+        assert!(verify::verify_source::<String, u64, _>(cursor.clone()).unwrap().is_ok());
+        let mut reader: Reader<_, String, u64> = Reader::open(cursor).unwrap();
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+        for (key, value) in &items {
+            assert_eq!(reader.get(key).unwrap(), Some(*value));
+        }
+        assert_eq!(reader.get("https://example.com/catalog/items/00004").unwrap(), None);
+        assert_eq!(reader.get("z").unwrap(), None);
+    }
+}
+
+#[test]
+fn build_front_coded_smaller() {
+    // string keys of varying lengths, so neighbours often differ in the varint length they start with
+    let mut items: Vec<_> = (1 .. 3000u64).map(|index| (format!("https://example.com/catalog/items/{}", index * index), index)).collect();
+    items.sort();
+    for builder in [TreeBuilder::new(6), TreeBuilder::new(6).bplus()] {
+        let builder = builder.page_size(512).byte_budget();
+        let plain = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+        let cursor = builder
+            .front_coding(8)
+            .build(items.clone(), Cursor::new(Vec::new()))
+            .unwrap();
+        assert!(cursor.get_ref().len() < plain.get_ref().len());
+        let mut reader: Reader<_, String, u64> = Reader::open(cursor).unwrap();
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+    }
+}
+
+#[test]
+fn build_delta_packed() {
+    let items: Vec<_> = (0 .. 1000u64).map(|index| (1_700_000_000_000 + index * 250, ())).collect();
+    let plain = TreeBuilder::new(12)
+        .page_size(256)
+        .build(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    // plain pages fit only a dozen of such items
+    assert!(TreeBuilder::new(20).page_size(256).build(items.clone(), Cursor::new(Vec::new())).is_err());
+    for builder in [TreeBuilder::new(20), TreeBuilder::new(20).bplus()] {
+        let cursor = builder
+            .page_size(256)
+            .delta_packed()
+            .build(items.clone(), Cursor::new(Vec::new()))
+            .unwrap();
+        assert!(cursor.get_ref().len() < plain.get_ref().len());
+        let mut reader: Reader<_, u64, ()> = Reader::open(cursor).unwrap();
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+        assert_eq!(reader.get(&1_700_000_000_500).unwrap(), Some(()));
+        assert_eq!(reader.get(&1_700_000_000_501).unwrap(), None);
+    }
+
+    // narrower keys pack to their own width, byte budget leaves take as many of them as the packed bits allow
+    let items: Vec<_> = (0 .. 1000u32).map(|index| (3_000_000_000 + index * 3, ())).collect();
+    let plain = TreeBuilder::new(4).page_size(256).byte_budget().build(items.clone(), Cursor::new(Vec::new())).unwrap();
+    for builder in [TreeBuilder::new(4), TreeBuilder::new(4).bplus()] {
+        let cursor = builder
+            .page_size(256)
+            .byte_budget()
+            .delta_packed()
+            .build(items.clone(), Cursor::new(Vec::new()))
+            .unwrap();
+        // budgeting whole four byte keys would leave three quarters of the plain size
+        assert!(cursor.get_ref().len() * 3 < plain.get_ref().len() * 2);
+        let mut reader: Reader<_, u32, ()> = Reader::open(cursor).unwrap();
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+    }
+    let items: Vec<_> = (0 .. 300u128).map(|index| (u128::MAX / 2 + index * u128::from(u64::MAX), index as u16)).collect();
+    let cursor = TreeBuilder::new(8).page_size(512).delta_packed().build(items.clone(), Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, u128, u16> = Reader::open(cursor).unwrap();
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items);
+
+    // the key codec is checked before anything is written
+    let mut cursor = Cursor::new(Vec::new());
+    let sketch = TreeBuilder::new(4).sketch(1).unwrap();
+    match file::write_encoded(&sketch, 256, file::Order::NonDecreasing, block::Encoding::DeltaPacked, vec![(-1i64, ())], &mut cursor) {
+        Err(file::Error::KeyNotPackable { key_codec, }) =>
+            assert_eq!(key_codec, "i64"),
+        other =>
+            panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(cursor.get_ref().is_empty());
+    match TreeBuilder::new(4).byte_budget().delta_packed().build(vec![("1".to_string(), ())], Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::KeyNotPackable { key_codec, })) =>
+            assert_eq!(key_codec, "string"),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn page_size_too_large() {
+    // inline value lengths would run into the value flag bits
+    let page_size = block::MAX_PAGE_SIZE + 1;
+    let mut cursor = Cursor::new(Vec::new());
+    let sketch = TreeBuilder::new(4).sketch(1).unwrap();
+    match file::write_encoded(&sketch, page_size, file::Order::NonDecreasing, block::Encoding::Plain, vec![(1u64, ())], &mut cursor) {
+        Err(file::Error::PageSizeTooLarge { page_size: found, max_page_size: block::MAX_PAGE_SIZE, }) =>
+            assert_eq!(found, page_size),
+        other =>
+            panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(cursor.get_ref().is_empty());
+    match TreeBuilder::new(4).page_size(page_size).byte_budget().build(vec![(1u64, ())], Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::PageSizeTooLarge { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+    match TreeBuilder::new(4).page_size(page_size).build_unsorted(vec![(1u64, ())], sort::Duplicates::Error, Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::PageSizeTooLarge { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn build_with_value_log() {
+    let items: Vec<_> = (0 .. 300u64).map(|index| (index, "v".repeat(20 + index as usize % 40))).collect();
+    let builder = TreeBuilder::new(4)
+        .page_size(256)
+        .byte_budget();
+    let (tree, log) = builder.build_with_value_log(items.clone(), Cursor::new(Vec::new()), Vec::new()).unwrap();
+    let inline_tree = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+    assert!(tree.get_ref().len() < inline_tree.get_ref().len());
+
+    let mut reader: Reader<_, u64, String> = Reader::open_with_value_log(tree, Cursor::new(log)).unwrap();
+    let inline_reader: Reader<_, u64, String> = Reader::open(inline_tree).unwrap();
+    let blocks_count = |sketch: &sketch::Tree| sketch.levels().iter().map(|level| level.blocks_count).sum::<u64>();
+    assert!(blocks_count(reader.sketch()) < blocks_count(inline_reader.sketch()));
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items);
+}
+
+#[test]
+fn build_empty() {
+    let cursor = TreeBuilder::new(4)
+        .build(Vec::<(u64, ())>::new(), Cursor::new(Vec::new()))
+        .unwrap();
+    let mut reader: Reader<_, u64, ()> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.scan().count(), 0);
+}
+
+#[test]
+fn build_page_overflow() {
+    let items = vec![(vec![0u8; 64], ()), (vec![1u8; 64], ())];
+    match TreeBuilder::new(2).page_size(64).build(items, Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::BlockAppend { level_index: 0, block_index: 0, item_index: 0, .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn build_stream_in_memory() {
+    let dir = make_spool_dir("in_memory");
+    let items = (0 .. 1000u64).filter(|index| index % 3 != 0).map(|index| (index, index * 2));
+    let cursor = TreeBuilder::new(5)
+        .spool_dir(&dir)
+        .build_stream(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+    let expected = TreeBuilder::new(5)
+        .build(items.collect::<Vec<_>>(), Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(cursor.into_inner(), expected.into_inner());
+}
+
+#[test]
+fn build_stream_spilled() {
+    let dir = make_spool_dir("spilled");
+    let items = (0 .. 1000u64).filter(|index| index % 7 != 0).map(|index| (index, format!("value {}", index)));
+    let cursor = TreeBuilder::new(6)
+        .spool_memory_limit(256)
+        .spool_dir(&dir)
+        .build_stream(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+    let mut reader: Reader<_, u64, String> = Reader::open(cursor).unwrap();
+    let scanned: Result<Vec<_>, _> = reader.scan().collect();
+    assert_eq!(scanned.unwrap(), items.collect::<Vec<_>>());
+}
+
+#[test]
+fn build_stream_cleanup_on_error() {
+    let dir = make_spool_dir("cleanup_on_error");
+    let items = (0 .. 100u64).map(|index| (index, vec![0u8; index as usize]));
+    let result = TreeBuilder::new(4)
+        .page_size(256)
+        .spool_memory_limit(128)
+        .spool_dir(&dir)
+        .build_stream(items, Cursor::new(Vec::new()));
+    assert!(matches!(result, Err(builder::Error::Write(file::Error::BlockAppend { .. }))));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn spool_replay() {
+    let dir = make_spool_dir("replay");
+    let mut spool = spool::Spool::new(40, dir.clone());
+    spool.push(b"a", b"first").unwrap();
+    assert!(!spool.is_spilled());
+    spool.push(b"bc", b"").unwrap();
+    spool.push(b"def", b"third").unwrap();
+    assert!(spool.is_spilled());
+    assert_eq!(spool.items_count(), 3);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let mut replay = spool.into_replay().unwrap();
+    let (mut key, mut value) = (Vec::new(), Vec::new());
+    let mut items = Vec::new();
+    while replay.next_item(&mut key, &mut value).unwrap() {
+        items.push((key.clone(), value.clone()));
+    }
+    assert_eq!(items, vec![
+        (b"a".to_vec(), b"first".to_vec()),
+        (b"bc".to_vec(), b"".to_vec()),
+        (b"def".to_vec(), b"third".to_vec()),
+    ]);
+    drop(replay);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn build_unsorted_spilled_runs() {
+    let dir = make_spool_dir("unsorted_spilled_runs");
+    let items: Vec<_> = (0 .. 2000u64).map(|index| ((index * 7919) % 2000, index)).collect();
+    let cursor = TreeBuilder::new(5)
+        .page_size(256)
+        .sort_memory_limit(1024)
+        .spool_dir(&dir)
+        .build_unsorted(items, sort::Duplicates::Error, Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+    let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    let expected: Vec<_> = (0 .. 2000u64).map(|key| (key, (0 .. 2000).find(|index| (index * 7919) % 2000 == key).unwrap())).collect();
+    assert_eq!(scanned, expected);
+}
+
+#[test]
+fn build_unsorted_duplicates() {
+    let items: Vec<_> = (0 .. 300u64).map(|index| ((index * 37) % 100, index)).collect();
+    let check = |duplicates, expected: Vec<(u64, u64)>| {
+        let dir = make_spool_dir("unsorted_duplicates");
+        let cursor = TreeBuilder::new(4)
+            .page_size(256)
+            .sort_memory_limit(512)
+            .spool_dir(&dir)
+            .build_unsorted(items.clone(), duplicates, Cursor::new(Vec::new()))
+            .unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+        let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+        let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, expected);
+    };
+    let occurrences = |key: u64| (0 .. 300u64).filter(move |index| (index * 37) % 100 == key);
+    check(sort::Duplicates::KeepFirst, (0 .. 100).map(|key| (key, occurrences(key).next().unwrap())).collect());
+    check(sort::Duplicates::KeepLast, (0 .. 100).map(|key| (key, occurrences(key).next_back().unwrap())).collect());
+    check(
+        sort::Duplicates::Merge(Box::new(|_key, a, b| a + b)),
+        (0 .. 100).map(|key| (key, occurrences(key).sum())).collect(),
+    );
+}
+
+#[test]
+fn build_unsorted_byte_budget() {
+    let dir = make_spool_dir("unsorted_byte_budget");
+    let items: Vec<_> = (0 .. 900u64).map(|index| ((index * 7919) % 300, "v".repeat(index as usize % 40))).collect();
+    let cursor = TreeBuilder::new(4)
+        .byte_budget()
+        .page_size(256)
+        .sort_memory_limit(1024)
+        .spool_dir(&dir)
+        .build_unsorted(items.clone(), sort::Duplicates::Merge(Box::new(|_key, a, b| a + &b)), Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+    let mut reader: Reader<_, u64, String> = Reader::open(cursor).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    let expected: Vec<_> = (0 .. 300u64)
+        .map(|key| (key, items.iter().filter(|item| item.0 == key).map(|item| item.1.as_str()).collect::<String>()))
+        .collect();
+    assert_eq!(scanned, expected);
+}
+
+#[test]
+fn build_unsorted_duplicate_error() {
+    let dir = make_spool_dir("unsorted_duplicate_error");
+    let items = vec![(3u64, ()), (1, ()), (2, ()), (1, ())];
+    let result = TreeBuilder::new(4)
+        .spool_dir(&dir)
+        .build_unsorted(items, sort::Duplicates::Error, Cursor::new(Vec::new()));
+    match result {
+        Err(builder::Error::Sort(sort::Error::DuplicateKey { key, })) =>
+            assert_eq!(key, 1u64.to_be_bytes()),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn sorter_runs() {
+    let dir = make_spool_dir("sorter_runs");
+    let mut sorter = sort::Sorter::new(64, dir.clone());
+    for key in (0 .. 20u32).rev() {
+        sorter.push(key, key.to_string()).unwrap();
+    }
+    assert!(sorter.runs_count() > 1);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), sorter.runs_count());
+    let mut merge = sorter.finish(sort::Duplicates::Error).unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = merge.next_item().unwrap() {
+        items.push(item);
+    }
+    assert_eq!(items, (0 .. 20).map(|key| (key, key.to_string())).collect::<Vec<_>>());
+    merge.rewind().unwrap();
+    let mut merge_items = merge.items();
+    assert_eq!(merge_items.by_ref().collect::<Vec<_>>(), items);
+    assert!(merge_items.take_error().is_none());
+    drop(merge);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn sorter_fan_in() {
+    let dir = make_spool_dir("sorter_fan_in");
+    let items: Vec<_> = (0 .. 600u64).map(|index| ((index * 37) % 200, index)).collect();
+    let occurrences = |key: u64| (0 .. 600u64).filter(move |index| (index * 37) % 200 == key);
+    for (duplicates, pick) in [(sort::Duplicates::KeepFirst, false), (sort::Duplicates::KeepLast, true)] {
+        let mut sorter = sort::Sorter::with_fan_in(64, 3, dir.clone());
+        let mut max_runs = 0;
+        for &(key, value) in &items {
+            sorter.push(key, value).unwrap();
+            max_runs = max_runs.max(sorter.runs_count());
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), sorter.runs_count());
+        }
+        // a couple of hundred runs are spilled, at most two of each merge pass stay around
+        assert!(max_runs <= 12, "{}", max_runs);
+        let mut merge = sorter.finish(duplicates).unwrap();
+        assert!(merge.runs_count() <= 3);
+        let mut merged = Vec::new();
+        while let Some(item) = merge.next_item().unwrap() {
+            merged.push(item);
+        }
+        let expected: Vec<_> = (0 .. 200)
+            .map(|key| (key, if pick { occurrences(key).next_back() } else { occurrences(key).next() }.unwrap()))
+            .collect();
+        assert_eq!(merged, expected);
+        drop(merge);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn build_order_errors() {
+    let items = vec![(1u64, ()), (2, ()), (2, ()), (3, ())];
+    let cursor = TreeBuilder::new(3)
+        .build(items.clone(), Cursor::new(Vec::new()))
+        .unwrap();
+    let mut reader: Reader<_, u64, ()> = Reader::open(cursor).unwrap();
+    assert_eq!(reader.scan().count(), 4);
+
+    match TreeBuilder::new(3).strictly_increasing().build(items.clone(), Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::DuplicateKey { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+    match TreeBuilder::new(3).strictly_increasing().build_stream(items, Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::DuplicateKey { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+    match TreeBuilder::new(3).build_stream(vec![(2u64, ()), (1, ())], Cursor::new(Vec::new())) {
+        Err(builder::Error::Write(file::Error::KeyOrder { .. })) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+fn make_spool_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bntree-test-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn build_invalid_block_size() {
+    for block_size in [0, 1] {
+        match TreeBuilder::new(block_size).build(vec![(1u64, ())], Cursor::new(Vec::new())) {
+            Err(builder::Error::Sketch(sketch::Error::InvalidBlockSize { block_size: 0 | 1, })) =>
+                (),
+            other =>
+                panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+        }
+    }
+}
+
+#[test]
+fn build_fill_strategies() {
+    let items: Vec<_> = (0 .. 300u64).map(|index| (index * 2, index)).collect();
+    let fills = [
+        sketch::FillStrategy::TopHeavy,
+        sketch::FillStrategy::MinHeight,
+        sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, },
+    ];
+    for fill in fills {
+        let builder = TreeBuilder::new(7).fill(fill).page_size(256);
+        let cursor = builder.build(items.clone(), Cursor::new(Vec::new())).unwrap();
+        let mut reader: Reader<_, u64, u64> = Reader::open(cursor).unwrap();
+        assert_eq!(reader.sketch(), &builder.sketch(300).unwrap());
+        let scanned: Result<Vec<_>, _> = reader.scan().collect();
+        assert_eq!(scanned.unwrap(), items);
+        let rev_scanned: Result<Vec<_>, _> = reader.rev_scan().collect();
+        assert_eq!(rev_scanned.unwrap(), items.iter().cloned().rev().collect::<Vec<_>>());
+        let ranged: Result<Vec<_>, _> = reader.range(101 .. 203).collect();
+        assert_eq!(ranged.unwrap(), items[51 .. 102]);
+        for &(key, value) in &items {
+            assert_eq!(reader.get(&key).unwrap(), Some(value));
+            assert_eq!(reader.get(&(key + 1)).unwrap(), None);
+        }
+    }
+}
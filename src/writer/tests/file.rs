@@ -0,0 +1,217 @@
+use std::{
+    io::{
+        Cursor,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    sync::Arc,
+};
+
+use super::super::{
+    file,
+    plan,
+    super::{
+        block,
+        codec,
+        sketch,
+        trailer,
+    },
+};
+
+#[test]
+fn tree17_4() {
+    let sketch = sketch::Tree::new(17, 4);
+    let items = sample_items(17);
+    let cursor = file::write(&sketch, 128, items.iter().cloned(), Cursor::new(Vec::new())).unwrap();
+    assert_eq!(replay_items(&sketch, cursor), items);
+}
+
+#[test]
+fn tree22_3() {
+    let sketch = sketch::Tree::new(22, 3);
+    let items = sample_items(22);
+    let cursor = file::write(&sketch, 96, items.iter().cloned(), Cursor::new(Vec::new())).unwrap();
+    assert_eq!(replay_items(&sketch, cursor), items);
+}
+
+#[test]
+fn trailer_levels() {
+    let sketch = sketch::Tree::new(17, 3);
+    let mut cursor = file::write(&sketch, 96, sample_items(17), Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut cursor).unwrap();
+    assert_eq!(trailer.items_total, 17);
+    assert_eq!(trailer.block_sizes, sketch::BlockSizes::Uniform(3));
+    assert_eq!(trailer.page_size, 96);
+    assert_eq!(trailer.key_codec, "bytes");
+    assert_eq!(trailer.value_codec, "bytes");
+    assert_eq!(&trailer.levels[..], sketch.levels());
+    let header_size = trailer::HEADER_SIZE;
+    let block_size = 96 + block::CHECKSUM_SIZE as u64;
+    assert_eq!(
+        trailer.layout,
+        trailer::Layout::Indexed {
+            blocks_offsets: vec![
+                vec![header_size + block_size * 5],
+                vec![header_size + block_size * 2, header_size + block_size * 3, header_size + block_size * 4],
+                vec![header_size, header_size + block_size],
+            ],
+        },
+    );
+}
+
+#[test]
+fn overflow_region() {
+    let sketch = sketch::Tree::new(30, 4);
+    let items: Vec<_> = (0 .. 30u64)
+        .map(|index| (index, vec![index as u8; if index % 4 == 0 { 700 } else { 10 }]))
+        .collect();
+    let mut cursor = file::write(&sketch, 256, items, Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut cursor).unwrap();
+    let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+    let block_size = 256 + block::CHECKSUM_SIZE as u64;
+
+    // blocks sit back to back right after the header, overflow pages follow them all
+    let mut offsets: Vec<_> = blocks_offsets.iter().flatten().cloned().collect();
+    offsets.sort();
+    let blocks_end = trailer::HEADER_SIZE + block_size * offsets.len() as u64;
+    assert_eq!(offsets, (0 .. offsets.len() as u64).map(|index| trailer::HEADER_SIZE + block_size * index).collect::<Vec<_>>());
+    let overflow_end = blocks_end + block_size * trailer.overflow_pages_count;
+    assert!(trailer.overflow_pages_count > 0);
+
+    let mut overflow_values_count = 0;
+    let mut page = Vec::new();
+    for offset in offsets {
+        page.resize(block_size as usize, 0);
+        cursor.seek(SeekFrom::Start(offset)).unwrap();
+        cursor.read_exact(&mut page).unwrap();
+        block::unseal_page(&mut page).unwrap();
+        let block = block::Block::decode(&page).unwrap();
+        for item_index in 0 .. block.items_count() {
+            if let (_key, block::Value::Overflow(overflow)) = block.item(item_index).unwrap() {
+                assert!(overflow.offset >= blocks_end && overflow.offset < overflow_end);
+                overflow_values_count += 1;
+            }
+        }
+    }
+    assert_eq!(overflow_values_count, 8);
+}
+
+#[test]
+fn items_exhausted() {
+    let sketch = sketch::Tree::new(17, 4);
+    match file::write(&sketch, 128, sample_items(3), Cursor::new(Vec::new())) {
+        Err(file::Error::ItemsExhausted { level_index: 1, block_index: 0, item_index: 3, }) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn items_leftover() {
+    let sketch = sketch::Tree::new(17, 4);
+    match file::write(&sketch, 128, sample_items(18), Cursor::new(Vec::new())) {
+        Err(file::Error::ItemsLeftover) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn page_overflow() {
+    let sketch = sketch::Tree::new(17, 4);
+    match file::write(&sketch, 32, sample_items(17), Cursor::new(Vec::new())) {
+        Err(file::Error::BlockAppend {
+            level_index: 1,
+            block_index: 0,
+            item_index: 1,
+            error: block::Error::PageOverflow { page_size: 32, required: 42, },
+        }) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn key_order() {
+    let sketch = sketch::Tree::new(17, 3);
+    let mut items = sample_items(17);
+    items.swap(9, 10);
+    match file::write(&sketch, 96, items, Cursor::new(Vec::new())) {
+        Err(file::Error::KeyOrder {
+            previous: file::ItemPosition { level_index: 1, block_index: 1, item_index: 0, },
+            current: file::ItemPosition { level_index: 1, block_index: 1, item_index: 1, },
+        }) =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn duplicate_keys() {
+    let sketch = sketch::Tree::new(17, 3);
+    let mut items = sample_items(17);
+    items[4].0 = items[3].0.clone();
+    let cursor = file::write(&sketch, 96, items.clone(), Cursor::new(Vec::new())).unwrap();
+    assert_eq!(replay_items(&sketch, cursor), items);
+    match file::write_ordered(&sketch, 96, file::Order::StrictlyIncreasing, items, Cursor::new(Vec::new())) {
+        Err(file::Error::DuplicateKey { previous, current, }) =>
+            assert_ne!(previous, current),
+        other =>
+            panic!("unexpected result: {:?}", other.map(Cursor::into_inner)),
+    }
+}
+
+#[test]
+fn unchecked_order() {
+    let sketch = sketch::Tree::new(17, 3);
+    let mut items = sample_items(17);
+    items.reverse();
+    let cursor = file::write_ordered(&sketch, 96, file::Order::Unchecked, items.clone(), Cursor::new(Vec::new())).unwrap();
+    assert_eq!(replay_items(&sketch, cursor), items);
+}
+
+fn sample_items(count: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    (0 .. count)
+        .map(|index| (format!("k{:02}", index).into_bytes(), format!("v{}", index).into_bytes()))
+        .collect()
+}
+
+fn replay_items(sketch: &sketch::Tree, mut cursor: Cursor<Vec<u8>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let trailer = trailer::Trailer::read_from(&mut cursor).unwrap();
+    let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+    let mut pages: Vec<Vec<u8>> = vec![Vec::new(); sketch.levels().len()];
+    let mut items = Vec::new();
+
+    let mut plan_ctx = plan::Context::new(Arc::new(sketch.clone()));
+    let mut kont = plan::Script::boot();
+    loop {
+        use plan::{Perform, Op};
+        match kont.next.step(&mut plan_ctx) {
+            plan::Instruction::Perform(Perform { op: Op::BlockStart { .. }, level_index, block_index, next, }) => {
+                let page = &mut pages[level_index];
+                page.resize(trailer.page_size + block::CHECKSUM_SIZE, 0);
+                cursor.seek(SeekFrom::Start(blocks_offsets[level_index][block_index])).unwrap();
+                cursor.read_exact(page).unwrap();
+                block::unseal_page(page).unwrap();
+                kont = next;
+            },
+            plan::Instruction::Perform(Perform { op: Op::BlockItem { index, }, level_index, next, .. }) => {
+                let block = block::Block::decode(&pages[level_index]).unwrap();
+                let (key, block::Value::Inline(value)) = block.item(index).unwrap() else {
+                    panic!("unexpected overflow value at {level_index}/{index}");
+                };
+                items.push((codec::decode(&key).unwrap(), codec::decode(value).unwrap()));
+                kont = next;
+            },
+            plan::Instruction::Perform(Perform { op: Op::BlockFinish, next, .. }) =>
+                kont = next,
+            plan::Instruction::Done =>
+                return items,
+        }
+    }
+}
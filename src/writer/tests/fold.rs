@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use super::super::{
     fold,
     plan,
@@ -18,7 +20,7 @@ fn tree17_3() {
 
 fn interpret_fold_count_items(sketch: &sketch::Tree) -> Vec<(usize, usize)> {
     let mut fold_ctx = fold::Context::new(
-        plan::Context::new(sketch),
+        plan::Context::new(Arc::new(sketch.clone())),
         sketch,
     );
 
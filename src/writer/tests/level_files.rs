@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    path::PathBuf,
+};
+
+use super::super::{
+    file,
+    level_files,
+    super::{
+        block,
+        reader::{
+            self,
+            Reader,
+        },
+        sketch,
+        verify,
+    },
+};
+
+#[test]
+fn write_read_back() {
+    let dir = make_dir("write_read_back");
+    let sketch = sketch::Tree::new(100, 4);
+    let items: Vec<_> = (0 .. 100u64).map(|index| (index * 2, format!("v{}", index))).collect();
+    level_files::write(&sketch, 128, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &dir).unwrap();
+
+    for level in sketch.levels() {
+        let metadata = fs::metadata(dir.join(level_files::level_file_name(level.index))).unwrap();
+        assert_eq!(metadata.len(), level.blocks_count * (128 + block::CHECKSUM_SIZE) as u64);
+    }
+
+    let mut reader: Reader<_, u64, String> = Reader::open_level_files(&dir).unwrap();
+    assert_eq!(reader.sketch(), &sketch);
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    let rev_ranged: Vec<_> = reader.rev_range(51 .. 61).map(Result::unwrap).collect();
+    assert_eq!(rev_ranged, vec![(60, "v30".to_string()), (58, "v29".to_string()), (56, "v28".to_string()), (54, "v27".to_string()), (52, "v26".to_string())]);
+    assert_eq!(reader.get(&42).unwrap(), Some("v21".to_string()));
+    assert_eq!(reader.get(&43).unwrap(), None);
+
+    assert!(verify::verify_level_files::<u64, String, _>(&dir).unwrap().is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bplus_scan_touches_leaf_file_only() {
+    let dir = make_dir("bplus_scan_touches_leaf_file_only");
+    let sketch = sketch::Tree::try_with_mode(200, 4, sketch::FillStrategy::TopHeavy, sketch::Mode::BPlus).unwrap();
+    let items: Vec<_> = (0 .. 200u64).map(|index| (index, index * 10)).collect();
+    level_files::write(&sketch, 128, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &dir).unwrap();
+    assert!(verify::verify_level_files::<u64, u64, _>(&dir).unwrap().is_ok());
+
+    let mut reader: Reader<_, u64, u64> = Reader::open_level_files(&dir).unwrap();
+    assert_eq!(reader.get(&77).unwrap(), Some(770));
+    let leaf_level_index = sketch.leaf_level_index().unwrap();
+    for level_index in 0 .. leaf_level_index {
+        fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join(level_files::level_file_name(level_index)))
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+    }
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    let rev_scanned: Vec<_> = reader.rev_scan().map(Result::unwrap).collect();
+    assert_eq!(rev_scanned, items.iter().rev().cloned().collect::<Vec<_>>());
+    assert!(matches!(reader.get(&77), Err(reader::Error::BlockRead { level_index: 0, .. })));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overflow_file() {
+    let dir = make_dir("overflow_file");
+    let sketch = sketch::Tree::new(50, 3);
+    let short_items: Vec<_> = (0 .. 50u64).map(|index| (index, vec![index as u8; 8])).collect();
+    level_files::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, short_items, &dir).unwrap();
+    assert!(!dir.join(level_files::OVERFLOW_FILE_NAME).exists());
+
+    let items: Vec<_> = (0 .. 50u64).map(|index| (index, vec![index as u8; index as usize * 11])).collect();
+    level_files::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &dir).unwrap();
+    assert!(dir.join(level_files::OVERFLOW_FILE_NAME).exists());
+    assert!(verify::verify_level_files::<u64, Vec<u8>, _>(&dir).unwrap().is_ok());
+    let mut reader: Reader<_, u64, Vec<u8>> = Reader::open_level_files(&dir).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    assert_eq!(reader.get(&49).unwrap(), Some(vec![49; 539]));
+
+    fs::remove_file(dir.join(level_files::OVERFLOW_FILE_NAME)).unwrap();
+    assert!(matches!(Reader::<_, u64, Vec<u8>>::open_level_files(&dir), Err(reader::Error::OverflowFileOpen(..))));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rewrite_dir() {
+    let dir = make_dir("rewrite_dir");
+    let sketch = sketch::Tree::new(200, 3);
+    let items: Vec<_> = (0 .. 200u64).map(|index| (index, vec![index as u8; index as usize * 3])).collect();
+    level_files::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items, &dir).unwrap();
+    assert!(dir.join(level_files::OVERFLOW_FILE_NAME).exists());
+
+    // a smaller tree with no long values leaves nothing of the older one behind
+    let sketch = sketch::Tree::new(10, 3);
+    let items: Vec<_> = (0 .. 10u64).map(|index| (index, vec![index as u8; 4])).collect();
+    level_files::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &dir).unwrap();
+    let mut file_names: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    file_names.sort();
+    let mut expected: Vec<_> = (0 .. sketch.levels().len()).map(level_files::level_file_name).collect();
+    expected.push(level_files::MANIFEST_FILE_NAME.to_string());
+    expected.sort();
+    assert_eq!(file_names, expected);
+    let mut reader: Reader<_, u64, Vec<u8>> = Reader::open_level_files(&dir).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn open_errors() {
+    let dir = make_dir("open_errors");
+    let sketch = sketch::Tree::new(10, 3);
+    level_files::write(&sketch, 64, file::Order::NonDecreasing, block::Encoding::Plain, (0 .. 10u64).map(|index| (index, ())), &dir).unwrap();
+
+    let manifest = fs::File::open(dir.join(level_files::MANIFEST_FILE_NAME)).unwrap();
+    assert!(matches!(Reader::<_, u64, ()>::open(manifest), Err(reader::Error::UnexpectedLayout)));
+
+    fs::remove_file(dir.join(level_files::level_file_name(1))).unwrap();
+    assert!(matches!(
+        Reader::<_, u64, ()>::open_level_files(&dir),
+        Err(reader::Error::LevelFileOpen { level_index: 1, .. }),
+    ));
+
+    fs::remove_file(dir.join(level_files::MANIFEST_FILE_NAME)).unwrap();
+    assert!(matches!(Reader::<_, u64, ()>::open_level_files(&dir), Err(reader::Error::ManifestOpen(..))));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn make_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bntree-test-{}-level-files-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use super::super::{
     plan,
     super::sketch,
@@ -132,7 +134,261 @@ fn tree22_3() {
     ]);
 }
 
-#[derive(PartialEq, Debug)]
+#[test]
+fn with_path_tree17_4() {
+    check_with_path(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn with_path_tree17_3() {
+    check_with_path(&sketch::Tree::new(17, 3));
+}
+
+#[test]
+fn with_path_tree22_3() {
+    check_with_path(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn with_path_tree200_4() {
+    check_with_path(&sketch::Tree::new(200, 4));
+}
+
+#[test]
+fn with_path_fill_strategies() {
+    for fill in [sketch::FillStrategy::MinHeight, sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, }] {
+        for (items_total, block_size) in [(5, 4), (17, 4), (22, 3), (200, 4), (300, 7)] {
+            check_with_path(&sketch::Tree::try_with_fill(items_total, block_size, fill).unwrap());
+        }
+    }
+}
+
+#[test]
+fn rev_fill_strategies() {
+    for fill in [sketch::FillStrategy::MinHeight, sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, }] {
+        for (items_total, block_size) in [(5, 4), (17, 4), (22, 3), (200, 4), (300, 7)] {
+            let sketch = sketch::Tree::try_with_fill(items_total, block_size, fill).unwrap();
+            check_rev(&sketch);
+            check_rev_with_path(&sketch);
+        }
+    }
+}
+
+#[test]
+fn block_sizes() {
+    let block_sizes = [
+        sketch::BlockSizes::LeafInner { leaf: 6, inner: 3, },
+        sketch::BlockSizes::PerLevel(vec![4, 2, 5]),
+    ];
+    for sizes in block_sizes {
+        for fill in [sketch::FillStrategy::TopHeavy, sketch::FillStrategy::Balanced { min_fill: sketch::MinFill::TWO_THIRDS, }] {
+            for items_total in [5, 17, 22, 200] {
+                let sketch = sketch::Tree::try_with_sizes(items_total, sizes.clone(), fill, sketch::Mode::BTree).unwrap();
+                check_with_path(&sketch);
+                check_rev(&sketch);
+                check_rev_with_path(&sketch);
+            }
+        }
+    }
+}
+
+#[test]
+fn rev_tree17_4() {
+    check_rev(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn rev_tree17_3() {
+    check_rev(&sketch::Tree::new(17, 3));
+}
+
+#[test]
+fn rev_tree22_3() {
+    check_rev(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn rev_tree200_4() {
+    check_rev(&sketch::Tree::new(200, 4));
+}
+
+#[test]
+fn rev_empty() {
+    check_rev(&sketch::Tree::new(0, 4));
+}
+
+#[test]
+fn rev_with_path_tree17_4() {
+    check_rev_with_path(&sketch::Tree::new(17, 4));
+}
+
+#[test]
+fn rev_with_path_tree22_3() {
+    check_rev_with_path(&sketch::Tree::new(22, 3));
+}
+
+#[test]
+fn rev_with_path_tree200_4() {
+    check_rev_with_path(&sketch::Tree::new(200, 4));
+}
+
+fn check_rev(sketch: &sketch::Tree) {
+    let script = collect_script(plan::Context::new(Arc::new(sketch.clone())));
+    let items_counts: std::collections::HashMap<_, _> = script
+        .iter()
+        .filter_map(|instruction| match *instruction {
+            Instruction::BlockStart { level_index, block_index, items_count, } =>
+                Some(((level_index, block_index), items_count)),
+            _ =>
+                None,
+        })
+        .collect();
+    let mut expected_rev: Vec<_> = script
+        .into_iter()
+        .rev()
+        .filter_map(|instruction| match instruction {
+            Instruction::BlockStart { level_index, block_index, .. } =>
+                Some(Instruction::BlockFinish { level_index, block_index, }),
+            Instruction::BlockFinish { level_index, block_index, } => {
+                let items_count = items_counts[&(level_index, block_index)];
+                Some(Instruction::BlockStart { level_index, block_index, items_count, })
+            },
+            Instruction::Done =>
+                None,
+            other =>
+                Some(other),
+        })
+        .collect();
+    expected_rev.push(Instruction::Done);
+    assert_eq!(collect_rev_script(plan::rev::Context::new(Arc::new(sketch.clone()))), expected_rev);
+}
+
+fn check_rev_with_path(sketch: &sketch::Tree) {
+    let script = collect_rev_script(plan::rev::Context::new(Arc::new(sketch.clone())));
+    for (offset, instruction) in script.iter().enumerate() {
+        if let Instruction::WriteItem { level_index, block_index, item_index, } = *instruction {
+            let path = make_path(sketch, level_index, block_index, item_index + 1);
+            let resumed = collect_rev_script(plan::rev::Context::with_path(Arc::new(sketch.clone()), &path));
+            assert_eq!(&resumed[..], &script[offset ..], "resumed with path {:?}", path);
+
+            let path = make_path(sketch, level_index, block_index, item_index);
+            let resumed_items: Vec<_> = collect_rev_script(plan::rev::Context::with_path(Arc::new(sketch.clone()), &path))
+                .into_iter()
+                .filter(|instruction| matches!(instruction, Instruction::WriteItem { .. }))
+                .collect();
+            let script_items: Vec<_> = script[offset + 1 ..]
+                .iter()
+                .filter(|instruction| matches!(instruction, Instruction::WriteItem { .. }))
+                .skip(child_subtree_items_count(sketch, sketch::ItemPosition { level_index, block_index, item_index, }))
+                .cloned()
+                .collect();
+            assert_eq!(resumed_items, script_items, "resumed with path {:?}", path);
+        }
+    }
+}
+
+fn child_subtree_items_count(sketch: &sketch::Tree, item: sketch::ItemPosition) -> usize {
+    let child = match sketch.child_block(item) {
+        Some(child) =>
+            child,
+        None =>
+            return 0,
+    };
+    (0 .. sketch.block_items_count(child).unwrap())
+        .map(|item_index| {
+            let child_item = sketch::ItemPosition { level_index: child.level_index, block_index: child.block_index, item_index, };
+            1 + child_subtree_items_count(sketch, child_item)
+        })
+        .sum()
+}
+
+fn collect_rev_script(mut plan_ctx: plan::rev::Context) -> Vec<Instruction> {
+    let mut script = Vec::new();
+    let mut kont = plan::rev::Script::boot();
+    loop {
+        use plan::{rev::Perform, Op};
+        match kont.next.step(&mut plan_ctx) {
+            plan::rev::Instruction::Perform(Perform { op: Op::BlockStart { items_count, }, level_index, block_index, next, }) => {
+                script.push(Instruction::BlockStart { level_index, block_index, items_count, });
+                kont = next;
+            },
+            plan::rev::Instruction::Perform(
+                Perform { op: Op::BlockItem { index: item_index, }, level_index, block_index, next, },
+            ) => {
+                script.push(Instruction::WriteItem { level_index, block_index, item_index, });
+                kont = next;
+            },
+            plan::rev::Instruction::Perform(Perform { op: Op::BlockFinish, level_index, block_index, next, }) => {
+                script.push(Instruction::BlockFinish { level_index, block_index, });
+                kont = next;
+            },
+            plan::rev::Instruction::Done => {
+                script.push(Instruction::Done);
+                return script;
+            },
+        }
+    }
+}
+
+fn check_with_path(sketch: &sketch::Tree) {
+    let script = collect_script(plan::Context::new(Arc::new(sketch.clone())));
+    for (offset, instruction) in script.iter().enumerate() {
+        let path = match *instruction {
+            Instruction::WriteItem { level_index, block_index, item_index, } =>
+                make_path(sketch, level_index, block_index, item_index),
+            Instruction::BlockFinish { level_index, block_index, } => {
+                let items_count = sketch.block_items_count(sketch::BlockPosition { level_index, block_index, }).unwrap();
+                make_path(sketch, level_index, block_index, items_count)
+            },
+            _ =>
+                continue,
+        };
+        let skip = if let Instruction::BlockFinish { .. } = instruction { 1 } else { 0 };
+        let resumed = collect_script(plan::Context::with_path(Arc::new(sketch.clone()), &path));
+        assert_eq!(&resumed[..], &script[offset + skip ..], "resumed with path {:?}", path);
+    }
+}
+
+fn make_path(sketch: &sketch::Tree, level_index: usize, block_index: usize, item_index: usize) -> Vec<plan::Position> {
+    let mut path = vec![plan::Position { block_index, item_index, }];
+    let mut block = sketch::BlockPosition { level_index, block_index, };
+    while let Some(parent) = sketch.parent_item(block) {
+        path.push(plan::Position { block_index: parent.block_index, item_index: parent.item_index, });
+        block = sketch::BlockPosition { level_index: parent.level_index, block_index: parent.block_index, };
+    }
+    path.reverse();
+    path
+}
+
+fn collect_script(mut plan_ctx: plan::Context) -> Vec<Instruction> {
+    let mut script = Vec::new();
+    let mut kont = plan::Script::boot();
+    loop {
+        use plan::{Perform, Op};
+        match kont.next.step(&mut plan_ctx) {
+            plan::Instruction::Perform(Perform { op: Op::BlockStart { items_count, }, level_index, block_index, next, }) => {
+                script.push(Instruction::BlockStart { level_index, block_index, items_count, });
+                kont = next;
+            },
+            plan::Instruction::Perform(
+                Perform { op: Op::BlockItem { index: item_index, }, level_index, block_index, next, },
+            ) => {
+                script.push(Instruction::WriteItem { level_index, block_index, item_index, });
+                kont = next;
+            },
+            plan::Instruction::Perform(Perform { op: Op::BlockFinish, level_index, block_index, next, }) => {
+                script.push(Instruction::BlockFinish { level_index, block_index, });
+                kont = next;
+            },
+            plan::Instruction::Done => {
+                script.push(Instruction::Done);
+                return script;
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 enum Instruction {
     TreeStart,
     BlockStart { level_index: usize, block_index: usize, items_count: usize, },
@@ -144,7 +400,7 @@ enum Instruction {
 fn interpret_script(sketch: &sketch::Tree, mut script: Vec<Instruction>) {
     script.reverse();
 
-    let mut plan_ctx = plan::Context::new(sketch);
+    let mut plan_ctx = plan::Context::new(Arc::new(sketch.clone()));
     let mut kont = plan::Script::boot();
 
     assert_eq!(script.pop(), Some(Instruction::TreeStart));
@@ -0,0 +1,185 @@
+use std::{
+    fs,
+    io::Cursor,
+    sync::Mutex,
+};
+
+use super::super::{
+    file,
+    regions,
+    super::{
+        block,
+        reader::{
+            self,
+            Reader,
+        },
+        sketch,
+        trailer,
+        verify,
+    },
+};
+
+#[test]
+fn regions_offsets() {
+    let sketch = sketch::Tree::new(17, 3);
+    let regions = regions::Regions::new(&sketch, 96);
+    let header_size = trailer::HEADER_SIZE;
+    assert_eq!(regions.level_offset(0), Some(header_size));
+    assert_eq!(regions.level_offset(1), Some(header_size + 100));
+    assert_eq!(regions.level_offset(2), Some(header_size + 400));
+    assert_eq!(regions.level_offset(3), None);
+    assert_eq!(regions.block_offset(1, 2), Some(header_size + 300));
+    assert_eq!(regions.block_offset(2, 1), Some(header_size + 500));
+    assert_eq!(regions.block_offset(1, 3), None);
+    assert_eq!(regions.block_offset(2, 2), None);
+    assert_eq!(regions.end_offset(), header_size + 600);
+}
+
+#[test]
+fn same_blocks_as_indexed() {
+    let sketch = sketch::Tree::new(17, 3);
+    let items: Vec<_> = (0 .. 17u64).map(|index| (index, index * 100)).collect();
+    let regions_data = regions::write(&sketch, 96, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+    let mut indexed = file::write(&sketch, 96, items, Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut indexed).unwrap();
+    let trailer::Layout::Indexed { blocks_offsets, } = trailer.layout else { panic!("unexpected layout") };
+    let indexed_data = indexed.into_inner();
+
+    let regions = regions::Regions::new(&sketch, 96);
+    for level in sketch.levels() {
+        for (block_index, &indexed_offset) in blocks_offsets[level.index].iter().enumerate() {
+            let regions_offset = regions.block_offset(level.index, block_index).unwrap() as usize;
+            let indexed_offset = indexed_offset as usize;
+            assert_eq!(&regions_data[regions_offset .. regions_offset + 100], &indexed_data[indexed_offset .. indexed_offset + 100]);
+        }
+    }
+
+    let mut regions_cursor = Cursor::new(regions_data);
+    assert_eq!(trailer::Trailer::read_from(&mut regions_cursor).unwrap().layout, trailer::Layout::Regions);
+    assert!(verify::verify_source::<u64, u64, _>(regions_cursor).unwrap().is_ok());
+}
+
+#[test]
+fn positioned_writes() {
+    let sketch = sketch::Tree::new(500, 4);
+    let items: Vec<_> = (0 .. 500u64).map(|index| (index, "v".repeat(if index % 50 == 0 { 700 } else { index as usize % 60 }))).collect();
+    let written = regions::write(&sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+
+    let sink = Mutex::new(Cursor::new(Vec::new()));
+    regions::write_at(&sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &sink).unwrap();
+    assert_eq!(sink.into_inner().unwrap().into_inner(), written);
+
+    #[cfg(unix)]
+    {
+        let path = std::env::temp_dir().join(format!("bntree-test-{}-regions.tree", std::process::id()));
+        let file = fs::File::create(&path).unwrap();
+        regions::write_at(&sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), &file).unwrap();
+        drop(file);
+        assert_eq!(fs::read(&path).unwrap(), written);
+        let mut reader: Reader<_, u64, String> = Reader::open(fs::File::open(&path).unwrap()).unwrap();
+        let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, items);
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn concurrent_levels() {
+    let sketch = sketch::Tree::new(500, 4);
+    let items: Vec<_> = (0 .. 500u64).map(|index| (index, "v".repeat(if index % 30 == 0 { 700 } else { index as usize % 60 }))).collect();
+    let written = regions::write(&sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+
+    let sink = Mutex::new(Cursor::new(Vec::new()));
+    write_levels_concurrently(&sketch, &items, &sink);
+    assert_eq!(sink.into_inner().unwrap().into_inner(), written);
+
+    #[cfg(unix)]
+    {
+        let path = std::env::temp_dir().join(format!("bntree-test-{}-concurrent-regions.tree", std::process::id()));
+        let file = fs::File::create(&path).unwrap();
+        write_levels_concurrently(&sketch, &items, &file);
+        drop(file);
+        assert_eq!(fs::read(&path).unwrap(), written);
+        fs::remove_file(&path).unwrap();
+    }
+
+    let levels_count = sketch.levels().len();
+    match regions::write_level_at(&sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, levels_count, items, &Mutex::new(Cursor::new(Vec::new()))) {
+        Err(file::Error::BlockOutsideRegions { level_index, block_index: 0, }) if level_index == levels_count =>
+            (),
+        other =>
+            panic!("unexpected result: {:?}", other),
+    }
+}
+
+fn write_levels_concurrently<P>(sketch: &sketch::Tree, items: &[(u64, String)], sink: &P) where P: regions::PositionedWrite + Sync {
+    let overflow_pages_counts: Vec<_> = std::thread::scope(|scope| {
+        let writers: Vec<_> = (0 .. sketch.levels().len())
+            .map(|level_index| scope.spawn(move || {
+                let items = items.iter().map(|(key, value)| (key, value));
+                regions::write_level_at(sketch, 512, file::Order::StrictlyIncreasing, block::Encoding::Plain, level_index, items, sink)
+                    .unwrap()
+            }))
+            .collect();
+        writers.into_iter().map(|writer| writer.join().unwrap()).collect()
+    });
+    assert!(overflow_pages_counts.iter().all(|&count| count > 0 && count == overflow_pages_counts[0]));
+    regions::write_frame_at::<_, u64, String>(sketch, 512, overflow_pages_counts[0], sink).unwrap();
+}
+
+#[test]
+fn read_back() {
+    let sketch = sketch::Tree::new(1000, 5);
+    let items: Vec<_> = (0 .. 1000u64).map(|index| (index * 3, format!("{}", index))).collect();
+    let cursor = regions::write(&sketch, 128, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new())).unwrap();
+    let mut reader: Reader<_, u64, String> = Reader::open(cursor).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    let rev_scanned: Vec<_> = reader.rev_scan().map(Result::unwrap).collect();
+    assert_eq!(rev_scanned, items.iter().rev().cloned().collect::<Vec<_>>());
+    for (key, value) in items.iter().step_by(7) {
+        assert_eq!(reader.get(key).unwrap().as_ref(), Some(value));
+        assert_eq!(reader.get(&(key + 1)).unwrap(), None);
+    }
+}
+
+#[test]
+fn overflow_region() {
+    let sketch = sketch::Tree::new(30, 3);
+    let items: Vec<_> = (0 .. 30u64).map(|index| (index, "x".repeat(index as usize * 20))).collect();
+    let mut cursor = regions::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new())).unwrap();
+    let trailer = trailer::Trailer::read_from(&mut cursor).unwrap();
+    let overflow_pages_count: u64 = items
+        .iter()
+        .map(|(_key, value)| value.len() + 1)
+        .filter(|&value_len| value_len > block::max_inline_value_len(256))
+        .map(|value_len| block::overflow_pages_count(256, value_len as u64))
+        .sum();
+    assert_eq!(trailer.overflow_pages_count, overflow_pages_count);
+    let regions = regions::Regions::new(&sketch, 256);
+    let overflow_end = regions.end_offset() + overflow_pages_count * (256 + block::CHECKSUM_SIZE) as u64;
+    assert!(overflow_pages_count > 0);
+    assert!(cursor.get_ref().len() as u64 > overflow_end);
+
+    let mut reader: Reader<_, u64, String> = Reader::open(cursor).unwrap();
+    let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+    assert_eq!(scanned, items);
+    let mut data = reader.into_inner().into_inner();
+    assert!(verify::verify_source::<u64, String, _>(Cursor::new(data.clone())).unwrap().is_ok());
+
+    // the first overflow page belongs to the first long value in plan order, damage it and look every value up
+    data[regions.end_offset() as usize + 5] ^= 1;
+    let mut reader: Reader<_, u64, String> = Reader::open(Cursor::new(data)).unwrap();
+    let failed: Vec<_> = items
+        .iter()
+        .filter_map(|(key, _value)| reader.get(key).err())
+        .collect();
+    assert_eq!(failed.len(), 1);
+    assert!(matches!(failed[0], reader::Error::OverflowChecksumMismatch { offset, .. } if offset == regions.end_offset()));
+}
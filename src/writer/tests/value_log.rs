@@ -0,0 +1,94 @@
+use std::io::{
+    Cursor,
+    Read,
+};
+
+use super::super::{
+    file,
+    value_log,
+    super::{
+        block,
+        codec,
+        crc32c,
+        reader::{
+            self,
+            Reader,
+        },
+        sketch,
+        trailer,
+        verify,
+    },
+};
+
+#[test]
+fn log_in_key_order() {
+    let sketch = sketch::Tree::new(100, 3);
+    let items: Vec<_> = (0 .. 100u64).map(|index| (index * 2, format!("value {}", index))).collect();
+    let (mut tree, log) = value_log::write(&sketch, 128, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.iter().cloned(), Cursor::new(Vec::new()), Vec::new())
+        .unwrap();
+
+    let mut expected_log = Vec::new();
+    let mut value_buf = Vec::new();
+    for (_key, value) in &items {
+        codec::encode(value, &mut value_buf);
+        expected_log.extend_from_slice(&value_buf);
+        expected_log.extend_from_slice(&crc32c::checksum(&value_buf).to_le_bytes());
+    }
+    assert_eq!(log, expected_log);
+    let trailer = trailer::Trailer::read_from(&mut tree).unwrap();
+    assert_eq!(trailer.value_log_len, Some(log.len() as u64));
+}
+
+#[test]
+fn read_back() {
+    for mode in [sketch::Mode::BTree, sketch::Mode::BPlus] {
+        let sketch = sketch::Tree::try_with_mode(200, 5, sketch::FillStrategy::TopHeavy, mode).unwrap();
+        let items: Vec<_> = (0 .. 200u64).map(|index| (index * 3, "v".repeat(index as usize % 97))).collect();
+        let (tree, log) = value_log::write(&sketch, 256, file::Order::StrictlyIncreasing, block::Encoding::Plain, items.clone(), Cursor::new(Vec::new()), Vec::new())
+            .unwrap();
+
+        assert!(matches!(Reader::<_, u64, String>::open(Cursor::new(tree.get_ref().clone())), Err(reader::Error::ValueLogRequired)));
+        let mut reader: Reader<_, u64, String> = Reader::open_with_value_log(tree, Cursor::new(log)).unwrap();
+        let scanned: Vec<_> = reader.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, items);
+        let ranged: Vec<_> = reader.range(30 .. 60).map(Result::unwrap).collect();
+        assert_eq!(ranged, items[10 .. 20].to_vec());
+        for (key, value) in items.iter().step_by(11) {
+            assert_eq!(reader.get(key).unwrap().as_ref(), Some(value));
+            assert_eq!(reader.get(&(key + 1)).unwrap(), None);
+
+            let mut value_reader = reader.get_value_reader(key).unwrap().unwrap();
+            assert!(value_reader.is_logged());
+            let mut streamed = Vec::new();
+            value_reader.read_to_end(&mut streamed).unwrap();
+            assert_eq!(streamed, value.as_bytes());
+        }
+        assert!(verify::verify_reader(reader).is_ok());
+    }
+}
+
+#[test]
+fn damaged_log() {
+    let sketch = sketch::Tree::new(20, 3);
+    let items: Vec<_> = (0 .. 20u64).map(|index| (index, vec![index as u8; 300])).collect();
+    let (tree, mut log) = value_log::write(&sketch, 128, file::Order::StrictlyIncreasing, block::Encoding::Plain, items, Cursor::new(Vec::new()), Vec::new())
+        .unwrap();
+    // the record of key 1 starts right after the first one: two bytes of length, the value and its checksum
+    let record_len = 2 + 300 + value_log::RECORD_CHECKSUM_SIZE;
+    log[record_len + 200] ^= 1;
+
+    let mut reader: Reader<_, u64, Vec<u8>> = Reader::open_with_value_log(tree, Cursor::new(log.clone())).unwrap();
+    assert_eq!(reader.get(&0).unwrap(), Some(vec![0; 300]));
+    assert!(matches!(
+        reader.get(&1),
+        Err(reader::Error::ValueLogChecksumMismatch { offset, .. }) if offset == record_len as u64,
+    ));
+    let mut streamed = Vec::new();
+    let error = reader.get_value_reader(&1).unwrap().unwrap().read_to_end(&mut streamed).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    log.truncate(record_len);
+    let (tree, _log) = reader.into_source().into_inner();
+    let mut reader: Reader<_, u64, Vec<u8>> = Reader::open_with_value_log(tree.into_inner(), Cursor::new(log)).unwrap();
+    assert!(matches!(reader.get(&1), Err(reader::Error::ValueLogRead { .. })));
+}
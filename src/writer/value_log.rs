@@ -0,0 +1,106 @@
+use std::io::{
+    self,
+    Seek,
+    SeekFrom,
+    Write,
+};
+
+use crate::{
+    writer::file::{
+        self,
+        BlockSink,
+        IndexedSink,
+    },
+    block,
+    codec,
+    crc32c,
+    sketch,
+    trailer,
+};
+
+pub const RECORD_CHECKSUM_SIZE: usize = 4;
+
+pub struct ValueLog<L> {
+    log: L,
+    end_offset: u64,
+}
+
+impl<L> ValueLog<L> where L: Write {
+    pub fn new(log: L) -> ValueLog<L> {
+        ValueLog { log, end_offset: 0, }
+    }
+
+    // a record is the value followed by its checksum
+    pub fn append(&mut self, value: &[u8]) -> io::Result<block::LogRef> {
+        let offset = self.end_offset;
+        self.log.write_all(value)?;
+        self.log.write_all(&crc32c::checksum(value).to_le_bytes())?;
+        self.end_offset += (value.len() + RECORD_CHECKSUM_SIZE) as u64;
+        Ok(block::LogRef { offset, value_len: value.len() as u64, })
+    }
+
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    pub fn into_inner(self) -> L {
+        self.log
+    }
+}
+
+struct ValueLogSink<'a, S, L> {
+    blocks: &'a mut S,
+    log: &'a mut ValueLog<L>,
+}
+
+impl<'a, S, L> BlockSink for ValueLogSink<'a, S, L> where S: BlockSink, L: Write {
+    fn write_block(&mut self, level_index: usize, block_index: usize, page: &[u8]) -> Result<(), file::Error> {
+        self.blocks.write_block(level_index, block_index, page)
+    }
+
+    fn write_overflow_page(&mut self, position: file::ItemPosition, page: &[u8]) -> Result<u64, file::Error> {
+        self.blocks.write_overflow_page(position, page)
+    }
+
+    fn append_value(&mut self, position: file::ItemPosition, value: &[u8]) -> Result<Option<block::LogRef>, file::Error> {
+        let file::ItemPosition { level_index, block_index, item_index, } = position;
+        self.log.append(value)
+            .map(Some)
+            .map_err(|error| file::Error::ValueLogWrite { level_index, block_index, item_index, error, })
+    }
+}
+
+// Writes an indexed tree which keeps only keys and log references in its blocks. Values go to `log` in
+// plan order, which is the key order, so the log comes out sorted as well.
+pub fn write<W, L, I, K, V>(sketch: &sketch::Tree, page_size: usize, order: file::Order, encoding: block::Encoding, items: I, mut sink: W, log: L) -> Result<(W, L), file::Error>
+where W: Write + Seek,
+      L: Write,
+      I: IntoIterator<Item = (K, V)>,
+      K: codec::Encode + Ord,
+      V: codec::Encode,
+{
+    file::check_encoding::<K>(encoding, page_size)?;
+    trailer::write_header(&mut sink)
+        .map_err(file::Error::HeaderWrite)?;
+
+    let mut indexed_sink = IndexedSink::new(&mut sink, sketch, page_size)
+        .map_err(file::Error::HeaderWrite)?;
+    let mut value_log = ValueLog::new(log);
+    let mut value_log_sink = ValueLogSink { blocks: &mut indexed_sink, log: &mut value_log, };
+    let overflow_pages_count = file::write_blocks(sketch, page_size, order, encoding, items, &mut value_log_sink)?;
+
+    let levels_offset = indexed_sink.end_offset();
+    let mut trailer = file::make_trailer::<K, V>(sketch, page_size, overflow_pages_count, indexed_sink.into_layout());
+    trailer.value_log_len = Some(value_log.end_offset());
+    sink.seek(SeekFrom::Start(levels_offset))
+        .map_err(file::Error::TrailerPosition)?;
+    trailer.write_to(&mut sink, levels_offset)
+        .map_err(file::Error::TrailerWrite)?;
+    sink.flush()
+        .map_err(file::Error::Flush)?;
+    let mut log = value_log.into_inner();
+    log.flush()
+        .map_err(file::Error::ValueLogFlush)?;
+
+    Ok((sink, log))
+}